@@ -0,0 +1,53 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use zk_chat::{
+    zk::{field, prover::{MessageProver, ProofProfile}, zk_hash},
+    Message,
+};
+
+// Compare backends by running this once as is and once with `--features f64`
+fn messages(count: u64) -> Vec<Message> {
+    // Four senders in turn, each numbering its own messages
+    (1..=count).map(|i| Message::new(i.div_ceil(4), i % 4, format!("benchmark message {}", i), 1000 + i)).collect()
+}
+
+// Rayon pool sizes to prove with; 0 is rayon's default of one thread per core
+#[cfg(feature = "concurrent")]
+const THREADS: &[(&str, usize)] = &[("single", 1), ("multi", 0)];
+#[cfg(not(feature = "concurrent"))]
+const THREADS: &[(&str, usize)] = &[("single", 1)];
+
+fn prove(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("prove/{}", field::NAME));
+    group.sample_size(10);
+    for count in [8u64, 32, 128] {
+        let messages = messages(count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &messages, |b, messages| {
+            b.iter(|| MessageProver::with_profile(ProofProfile::Fast).prove(messages).unwrap())
+        });
+    }
+    group.finish();
+}
+
+// Single- vs multi-threaded proving; the multi-threaded runs need `--features concurrent`
+fn prove_threads(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("prove_threads/{}", field::NAME));
+    group.sample_size(10);
+    for &(name, threads) in THREADS {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        for count in [32u64, 128, 512] {
+            let messages = messages(count);
+            group.bench_with_input(BenchmarkId::new(name, count), &messages, |b, messages| {
+                b.iter(|| pool.install(|| MessageProver::with_profile(ProofProfile::Fast).prove(messages).unwrap()))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn hash(c: &mut Criterion) {
+    let inputs: Vec<field::BaseElement> = (0..9u64).map(field::element).collect();
+    c.bench_function(&format!("zk_hash/{}", field::NAME), |b| b.iter(|| zk_hash(&inputs)));
+}
+
+criterion_group!(benches, prove, prove_threads, hash);
+criterion_main!(benches);
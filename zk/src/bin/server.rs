@@ -1,11 +1,27 @@
+#![recursion_limit = "256"]
+
 use warp::Filter;
 use tracing::{info, warn, Level};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use zk_chat::test_harness;
 use base64::{engine::general_purpose, Engine as _};
-use zk_chat::zk::{air::{PublicInputs, build_trace}, prover::{MessageProver, verify_proof}, elements_to_hash};
+use zk_chat::zk::{air::{self, PublicInputs, build_trace, GlobalId, BLOCK_LENGTH, CHAIN_HASH}, prover::{MessageProver, verify_proof}, Digest, session_salt, restore_session_salt};
+use zk_chat::zk::storage::StoredChain;
+use zk_chat::websocket::server::shutdown_signal;
+use zk_chat::zk::batch::prove_batch_with_profile;
+use zk_chat::zk::consistency::{ServerSigningKey, SignedInclusionProof};
+use zk_chat::zk::disclosure::{random_salt, redact_messages, redacted_segment_public_inputs, RedactedMessage};
+use zk_chat::zk::segment::{ChainBoundary, SegmentProof};
+use zk_chat::registry::{RegisteredUser, UserRegistry};
+use zk_chat::metrics;
+use zk_chat::config::ServerConfig;
+use zk_chat::rate_limit::RateLimiter;
+use zk_chat::tls::TlsReloader;
 use winterfell::math::FieldElement; // for BaseElement::ZERO
+use once_cell::sync::{Lazy, OnceCell};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, Notify};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -14,17 +30,60 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_max_level(Level::INFO)
         .init();
 
+    // Settings: defaults < zk-chat.toml (or --config) < ZK_CHAT_* environment < command line
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", zk_chat::config::usage());
+        return Ok(());
+    }
+    let config = ServerConfig::load(args, |key| std::env::var(key).ok())?;
+    let config = CONFIG.get_or_init(|| config);
+
     info!("ZK Chat Server - WebSocket Messenger with ZK-STARK Proofs");
     info!("=========================================================");
 
+    // Hash for new chains; a restored chain keeps the one it was built with
+    zk_chat::zk::hasher::set_default_hash(config.proof.hash);
+
+    // Long-term checkpoint signing key, created on first start
+    let key_path = config.signing_key_path();
+    let signer = SERVER_SIGNING_KEY.get_or_try_init(|| ServerSigningKey::load_or_create(&key_path))?;
+    info!("🔏 Checkpoint signing key {} (public key {})", key_path.display(), signer.public_key());
+
+    // Persistent user registry: server-allocated ids, unique usernames
+    let registry_path = config.users_path();
+    let registry = UserRegistry::open(&registry_path)?;
+    info!("👥 User registry {} ({} users)", registry_path.display(), registry.len());
+    GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner()).registry = registry;
+
+    // Chain flushed by the previous shutdown; extending it keeps its salt
+    let chain_path = config.chain_path();
+    if let Some(stored) = StoredChain::load(&chain_path)? {
+        restore_session_salt(stored.salt);
+        let chain = stored.restore()?;
+        info!("⛓️ Restored {} messages (epoch {}) from {}", chain.len(), stored.epoch, chain_path.display());
+        restore_chain(&mut GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner()), &stored, chain);
+    }
+
+    // Bearer token for /api/admin; a random one is generated when none is configured
+    let admin_token = config.admin_token.clone().unwrap_or_else(|| {
+        let token = hex::encode(random_salt());
+        if config.features.admin_api {
+            warn!("🔑 No admin_token configured; admin API token for this run: {}", token);
+        }
+        token
+    });
+    let _ = ADMIN_TOKEN.set(admin_token);
+
     // Serve static files from the static directory
-    let static_files = warp::fs::dir("static");
+    let static_files = warp::fs::dir(config.static_dir.clone());
 
     // WebSocket route (chat paused, retained for backward compatibility)
     let websocket = warp::path("ws")
         .and(warp::ws())
-        .map(|ws: warp::ws::Ws| {
-            ws.on_upgrade(handle_websocket)
+        .and(warp::addr::remote())
+        .map(|ws: warp::ws::Ws, remote: Option<std::net::SocketAddr>| {
+            ws.on_upgrade(move |socket| handle_websocket(socket, remote.map(|addr| addr.ip())))
         });
 
     // --- ZK Proof API: Single-message proof generation & verification ---
@@ -47,7 +106,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let prove_route = warp::path!("api" / "prove")
         .and(warp::post())
         .and(warp::body::json())
-        .and_then(|req: ProveRequest| async move {
+        .and_then(move |req: ProveRequest| async move {
             tracing::info!("/api/prove request received: sender_id={}, id={:?}", req.sender_id, req.id);
             let id = req.id.unwrap_or(1);
             let timestamp = req.timestamp.unwrap_or_else(|| {
@@ -57,13 +116,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .as_secs()
             });
             let message = zk_chat::Message::new(id, req.sender_id, req.content, timestamp);
-            let trace = build_trace(&[message.clone()]);
-            let mut final_hash_elements = [winterfell::math::fields::f128::BaseElement::ZERO; 4];
-            for i in 0..4 { final_hash_elements[i] = trace[4 + i][0]; }
-            let final_hash = elements_to_hash(&final_hash_elements);
-            let public_inputs = PublicInputs { initial_hash: [0u8; 32], final_hash, message_count: 1 };
-            let mut prover = MessageProver::new();
-            let proof_bytes = match prover.prove(&[message.clone()]) {
+            let trace = build_trace(std::slice::from_ref(&message));
+            let mut final_hash_elements = [zk_chat::zk::field::BaseElement::ZERO; 4];
+            for i in 0..4 { final_hash_elements[i] = trace[CHAIN_HASH + i][BLOCK_LENGTH - 1]; }
+            let final_hash = Digest::new(final_hash_elements);
+            let public_inputs = PublicInputs { initial_hash: Digest::ZERO, initial_timestamp: 0, final_hash, message_count: 1, message_root: message.hash, hash_id: message.hash_id, final_timestamp: message.timestamp, salt: session_salt(), message_index: vec![GlobalId::of(&message)] };
+            let mut prover = MessageProver::with_profile(config.proof.profile);
+            let proof_bytes = match prover.prove(std::slice::from_ref(&message)) {
                 Ok(p) => p,
                 Err(e) => {
                     tracing::error!("Proof generation failed: {:?}", e);
@@ -85,7 +144,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let verify_route = warp::path!("api" / "verify")
         .and(warp::post())
         .and(warp::body::json())
-        .and_then(|req: VerifyRequest| async move {
+        .and_then(move |req: VerifyRequest| async move {
             tracing::info!("/api/verify request received: message_id={}, sender_id={}", req.message.id, req.message.sender_id);
             // Hard fail early if client-supplied hash is invalid
             if !req.message.verify_hash() {
@@ -97,11 +156,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     )
                 ));
             }
-            let trace = build_trace(&[req.message.clone()]);
-            let mut final_hash_elements = [winterfell::math::fields::f128::BaseElement::ZERO; 4];
-            for i in 0..4 { final_hash_elements[i] = trace[4 + i][0]; }
-            let final_hash = elements_to_hash(&final_hash_elements);
-            let public_inputs = PublicInputs { initial_hash: [0u8; 32], final_hash, message_count: 1 };
+            let trace = build_trace(std::slice::from_ref(&req.message));
+            let mut final_hash_elements = [zk_chat::zk::field::BaseElement::ZERO; 4];
+            for i in 0..4 { final_hash_elements[i] = trace[CHAIN_HASH + i][BLOCK_LENGTH - 1]; }
+            let final_hash = Digest::new(final_hash_elements);
+            let public_inputs = PublicInputs { initial_hash: Digest::ZERO, initial_timestamp: 0, final_hash, message_count: 1, message_root: req.message.hash, hash_id: req.message.hash_id, final_timestamp: req.message.timestamp, salt: session_salt(), message_index: vec![GlobalId::of(&req.message)] };
             let proof_bytes = match general_purpose::STANDARD.decode(&req.proof_base64) {
                 Ok(p) => p,
                 Err(_) => {
@@ -114,7 +173,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     ));
                 }
             };
-            let verified = verify_proof(&proof_bytes, public_inputs.clone()).is_ok();
+            let verified = verify_proof(&proof_bytes, public_inputs.clone(), config.proof.profile).is_ok();
             let response = VerifyResponse { verified, public_inputs };
             tracing::info!("/api/verify result: verified={}", verified);
             Ok::<Box<dyn warp::Reply>, warp::Rejection>(Box::new(warp::reply::json(&response)))
@@ -140,24 +199,232 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     .as_secs()
             });
             let message = zk_chat::Message::new(id, req.sender_id, req.content, timestamp);
-            let trace = build_trace(&[message.clone()]);
-            let mut final_hash_elements = [winterfell::math::fields::f128::BaseElement::ZERO; 4];
-            for i in 0..4 { final_hash_elements[i] = trace[4 + i][0]; }
-            let final_hash = elements_to_hash(&final_hash_elements);
-            let public_inputs = PublicInputs { initial_hash: [0u8; 32], final_hash, message_count: 1 };
+            let trace = build_trace(std::slice::from_ref(&message));
+            let mut final_hash_elements = [zk_chat::zk::field::BaseElement::ZERO; 4];
+            for i in 0..4 { final_hash_elements[i] = trace[CHAIN_HASH + i][BLOCK_LENGTH - 1]; }
+            let final_hash = Digest::new(final_hash_elements);
+            let public_inputs = PublicInputs { initial_hash: Digest::ZERO, initial_timestamp: 0, final_hash, message_count: 1, message_root: message.hash, hash_id: message.hash_id, final_timestamp: message.timestamp, salt: session_salt(), message_index: vec![GlobalId::of(&message)] };
             // Build JSON-friendly trace rows (only the actual message step = 0)
             let mut rows = Vec::new();
             let step = 0usize;
             let mut prev_hash = [String::new(), String::new(), String::new(), String::new()];
             let mut chain_hash = [String::new(), String::new(), String::new(), String::new()];
-            for i in 0..4 { prev_hash[i] = format!("{}", trace[i][step]); chain_hash[i] = format!("{}", trace[4 + i][step]); }
-            let row = TraceRow { step, prev_hash, chain_hash, prev_timestamp: format!("{}", trace[8][step]), timestamp: format!("{}", trace[9][step]), sender_id: format!("{}", trace[10][step]) };
+            for i in 0..4 { prev_hash[i] = format!("{}", trace[air::PREV_HASH + i][step]); chain_hash[i] = format!("{}", trace[CHAIN_HASH + i][step]); }
+            let row = TraceRow { step, prev_hash, chain_hash, prev_timestamp: format!("{}", trace[air::PREV_TIMESTAMP][step]), timestamp: format!("{}", trace[air::INPUTS + 2][step]), sender_id: format!("{}", trace[air::INPUTS + 1][step]) };
             rows.push(row);
             let response = TraceResponse { message, public_inputs, trace: rows };
             tracing::info!("/api/trace success for message id={}, sender_id={}", response.message.id, response.message.sender_id);
             Ok::<Box<dyn warp::Reply>, warp::Rejection>(Box::new(warp::reply::json(&response)))
         });
 
+    // GET /api/inclusion/{message_hash} - Merkle path of a message hash under the server-signed head.
+    // Only a caller holding the message knows its hash; the response discloses no message or chain contents
+    let inclusion_route = warp::path!("api" / "inclusion" / String)
+        .and(warp::get())
+        .map(|leaf: String| {
+            let Ok(leaf) = leaf.parse::<Digest>() else {
+                return Box::new(warp::reply::with_status("invalid message hash", warp::http::StatusCode::BAD_REQUEST)) as Box<dyn warp::Reply>;
+            };
+            let state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+            let chain = &state_lock.message_chain;
+            let path = chain.messages.iter()
+                .position(|m| m.hash == leaf)
+                .and_then(|index| chain.inclusion_proof(index));
+            match path {
+                Some(inclusion) => {
+                    let response = SignedInclusionProof {
+                        leaf,
+                        path: inclusion.path,
+                        message_root: chain.message_root(),
+                        checkpoint: checkpoint_of(&state_lock),
+                    };
+                    Box::new(warp::reply::json(&response))
+                }
+                None => Box::new(warp::reply::with_status("message not in chain", warp::http::StatusCode::NOT_FOUND)),
+            }
+        });
+
+    // GET /api/redacted - selective-disclosure transcript: metadata and salted content commitments only.
+    // Admin only; segments proven for earlier requests are reused and only new messages are proven
+    #[derive(Debug, Serialize)]
+    struct RedactedSegment { public_inputs: PublicInputs, proof_base64: String }
+    #[derive(Debug, Serialize)]
+    struct RedactedResponse { messages: Vec<RedactedMessage>, segments: Vec<RedactedSegment> }
+    let redacted_route = warp::path!("api" / "redacted")
+        .and(warp::get())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .and_then(move || async move {
+            let (redacted, epoch) = {
+                let state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+                let salts: Vec<[u8; 32]> = state_lock.message_chain.messages.iter()
+                    .map(|m| state_lock.content_salts.get(&GlobalId::of(m)).copied().unwrap_or_default())
+                    .collect();
+                (redact_messages(&state_lock.message_chain.messages, &salts), state_lock.epoch)
+            };
+            let messages = match redacted {
+                Ok(messages) if !messages.is_empty() => messages,
+                _ => return Ok::<Box<dyn warp::Reply>, warp::Rejection>(Box::new(
+                    warp::reply::with_status("chain is empty", warp::http::StatusCode::NOT_FOUND)
+                )),
+            };
+            // Held across proving so concurrent requests wait for, then reuse, the same segment
+            let mut cache = REDACTED_PROOFS.lock().await;
+            if cache.epoch != epoch || !cache.matches(&messages) {
+                *cache = RedactedProofs { epoch, segments: Vec::new() };
+            }
+            let proven: usize = cache.segments.iter().map(|s| s.public_inputs.message_count).sum();
+            if proven < messages.len() {
+                let start = cache.segments.last().map_or(ChainBoundary::GENESIS, |s| ChainBoundary::end_of(&s.public_inputs));
+                let fresh = messages[proven..].to_vec();
+                let segment = tokio::task::spawn_blocking(move || {
+                    let proof = MessageProver::with_profile(config.proof.profile).prove_redacted_segment(&start, &fresh)?;
+                    let public_inputs = redacted_segment_public_inputs(&start, &fresh)?;
+                    Ok::<_, zk_chat::ZkChatError>(SegmentProof { public_inputs, proof })
+                }).await;
+                match segment {
+                    Ok(Ok(segment)) => {
+                        tracing::info!("/api/redacted proved {} new redacted messages", segment.public_inputs.message_count);
+                        cache.segments.push(segment);
+                    }
+                    Ok(Err(e)) => return Ok(Box::new(warp::reply::with_status(
+                        format!("redacted proof failed: {e}"),
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    ))),
+                    Err(e) => return Ok(Box::new(warp::reply::with_status(
+                        format!("redacted proof task failed: {e}"),
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    ))),
+                }
+            }
+            let segments = cache.segments.iter()
+                .map(|s| RedactedSegment { public_inputs: s.public_inputs.clone(), proof_base64: general_purpose::STANDARD.encode(&s.proof) })
+                .collect();
+            Ok(Box::new(warp::reply::json(&RedactedResponse { messages, segments })))
+        });
+
+    // GET /api/server-key - public key clients pin to verify checkpoints
+    let server_key_route = warp::path!("api" / "server-key")
+        .and(warp::get())
+        .map(|| {
+            let public_key = SERVER_SIGNING_KEY.get().map(|k| k.public_key()).unwrap_or_default();
+            warp::reply::json(&serde_json::json!({ "public_key": public_key }))
+        });
+
+    // GET /api/checkpoint - freshly signed checkpoint of the current chain head
+    let checkpoint_route = warp::path!("api" / "checkpoint")
+        .and(warp::get())
+        .map(|| warp::reply::json(&sign_checkpoint()));
+
+    // --- Admin API (requires the admin bearer token) ---
+    #[derive(Debug, Deserialize)]
+    struct RegisterRequest { username: String, public_key: Option<String> }
+    #[derive(Debug, Deserialize)]
+    struct RenameRequest { username: String }
+
+    // GET /api/admin/users - every registered user, including deactivated ones
+    let admin_list_users_route = warp::path!("api" / "admin" / "users")
+        .and(warp::get())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .map(|| {
+            let state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+            let users: Vec<RegisteredUser> = state_lock.registry.users().cloned().collect();
+            warp::reply::json(&users)
+        });
+
+    // POST /api/admin/users - register a user
+    let admin_register_route = warp::path!("api" / "admin" / "users")
+        .and(warp::post())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .and(warp::body::json())
+        .map(|req: RegisterRequest| {
+            let result = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner()).registry.register(&req.username, req.public_key);
+            registry_reply(result)
+        });
+
+    // POST /api/admin/users/{id}/rename
+    let admin_rename_route = warp::path!("api" / "admin" / "users" / u64 / "rename")
+        .and(warp::post())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .and(warp::body::json())
+        .map(|id: u64, req: RenameRequest| registry_reply(rename_user(id, &req.username)));
+
+    // POST /api/admin/users/{id}/deactivate
+    let admin_deactivate_route = warp::path!("api" / "admin" / "users" / u64 / "deactivate")
+        .and(warp::post())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .map(|id: u64| registry_reply(deactivate_user(id)));
+
+    // POST /api/admin/users/{id}/kick - disconnect a user without changing their registration
+    let admin_kick_route = warp::path!("api" / "admin" / "users" / u64 / "kick")
+        .and(warp::post())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .map(|id: u64| {
+            if kick_user(id, "kicked by an administrator") {
+                Box::new(warp::reply::json(&serde_json::json!({ "kicked": id }))) as Box<dyn warp::Reply>
+            } else {
+                Box::new(warp::reply::with_status("user not connected", warp::http::StatusCode::NOT_FOUND))
+            }
+        });
+
+    // POST /api/admin/users/{id}/ban and /unban
+    let admin_ban_route = warp::path!("api" / "admin" / "users" / u64 / "ban")
+        .and(warp::post())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .map(|id: u64| registry_reply(set_user_banned(id, true)));
+    let admin_unban_route = warp::path!("api" / "admin" / "users" / u64 / "unban")
+        .and(warp::post())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .map(|id: u64| registry_reply(set_user_banned(id, false)));
+
+    // GET /api/admin/sessions - users currently connected
+    let admin_sessions_route = warp::path!("api" / "admin" / "sessions")
+        .and(warp::get())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .map(|| {
+            let state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+            let mut online: Vec<(u64, String)> = state_lock.users.iter().map(|(&id, name)| (id, name.clone())).collect();
+            online.sort();
+            warp::reply::json(&online)
+        });
+
+    // GET /api/admin/chain - epoch, salt, length and head of the chain
+    let admin_chain_route = warp::path!("api" / "admin" / "chain")
+        .and(warp::get())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .map(|| warp::reply::json(&chain_status(&GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner()))));
+
+    // GET /api/admin/jobs - messages queued for, and currently in, batch proving
+    let admin_jobs_route = warp::path!("api" / "admin" / "jobs")
+        .and(warp::get())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .map(|| {
+            let state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+            let queued: Vec<u64> = state_lock.batch_prover.pending().iter().map(|m| m.id).collect();
+            let proving = state_lock.proving.as_ref().map(|job| serde_json::json!({
+                "message_ids": job.message_ids,
+                "elapsed_ms": job.started.elapsed().as_millis() as u64,
+            }));
+            warp::reply::json(&serde_json::json!({ "queued": queued, "proving": proving }))
+        });
+
+    // POST /api/admin/rotate - new session salt and a fresh chain (the old head stays signed)
+    let admin_rotate_route = warp::path!("api" / "admin" / "rotate")
+        .and(warp::post())
+        .and(enabled(config.features.admin_api))
+        .and(admin_auth())
+        .map(|| warp::reply::json(&rotate_epoch()));
+
     // --- Test Harness API (list & run tests) ---
     #[derive(Serialize)]
     struct ListedTest { name: &'static str, category: &'static str, description: &'static str }
@@ -166,6 +433,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let tests_list_route = warp::path!("api" / "tests" / "list")
         .and(warp::get())
+        .and(enabled(config.features.test_harness))
         .map(|| {
             let tests: Vec<ListedTest> = test_harness::all_tests().into_iter()
                 .map(|t| ListedTest { name: t.name, category: t.category, description: t.description })
@@ -175,6 +443,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let tests_run_route = warp::path!("api" / "tests" / "run")
         .and(warp::get())
+        .and(enabled(config.features.test_harness))
         .and(warp::query::<HashMap<String,String>>())
         .and_then(|q: HashMap<String,String>| async move {
             if let Some(name) = q.get("name") {
@@ -187,6 +456,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             Ok(Box::new(warp::reply::json(&results)))
         });
 
+    // GET /metrics - Prometheus scrape endpoint
+    let metrics_route = warp::path!("metrics")
+        .and(warp::get())
+        .and(enabled(config.features.metrics))
+        .map(|| {
+            {
+                let state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+                metrics::ONLINE_USERS.set(state_lock.users.len() as i64);
+                metrics::CHAIN_LENGTH.set(state_lock.message_chain.len() as i64);
+            }
+            metrics::BROADCAST_LAG.set(BROADCAST_TX.len() as i64);
+            warp::reply::with_header(metrics::render(), "content-type", "text/plain; version=0.0.4")
+        });
+
     // Root redirect to index.html
     let root = warp::path::end()
         .map(|| warp::redirect::redirect(warp::http::Uri::from_static("/index.html")));
@@ -196,73 +479,205 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .or(trace_route)
         .or(prove_route)
         .or(verify_route)
+        .or(inclusion_route)
+        .or(redacted_route)
+        .or(server_key_route)
+        .or(checkpoint_route)
+        .or(admin_list_users_route)
+        .or(admin_register_route)
+        .or(admin_rename_route)
+        .or(admin_deactivate_route)
+        .or(admin_kick_route)
+        .or(admin_ban_route)
+        .or(admin_unban_route)
+        .or(admin_sessions_route)
+        .or(admin_chain_route)
+        .or(admin_jobs_route)
+        .or(admin_rotate_route)
         .or(tests_list_route)
         .or(tests_run_route)
+        .or(metrics_route)
         .or(websocket)
-        .or(static_files);
+        .or(static_files)
+        .recover(handle_rejection);
+
+    let scheme = if config.tls_enabled() { "https" } else { "http" };
+    info!("🚀 Server starting on {}://{}", scheme, config.bind);
+    info!("📱 Open your browser and navigate to {}://{}", scheme, config.bind);
+    info!("🔐 Each message will be verified using ZK-STARK proofs ({:?} profile, {} hash)", config.proof.profile, config.proof.hash);
 
-    info!("🚀 Server starting on http://127.0.0.1:8081");
-    info!("📱 Open your browser and navigate to http://127.0.0.1:8081");
-    info!("🔐 Each message will be verified using ZK-STARK proofs");
+    // Batch prover: one proof per batch of queued messages
+    tokio::spawn(run_batch_prover());
+    // Signed checkpoints of the chain head, broadcast periodically
+    tokio::spawn(run_checkpoints());
     
-    warp::serve(routes)
-        .run(([127, 0, 0, 1], 8081))
-        .await;
+    // Serve until Ctrl-C/SIGTERM; stopping the server stops accepting connections and lets in-flight requests finish
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let stopped = async {
+        let _ = stop_rx.await;
+    };
+    let server = match (&config.tls.cert, &config.tls.key) {
+        (Some(cert), Some(key)) => {
+            let tls = TlsReloader::load(cert, key)?;
+            if config.tls.reload_secs > 0 {
+                tls.watch(std::time::Duration::from_secs(config.tls.reload_secs));
+            }
+            let listener = tokio::net::TcpListener::bind(config.bind).await?;
+            tokio::spawn(warp::serve(routes).serve_incoming_with_graceful_shutdown(tls.incoming(listener), stopped))
+        }
+        _ => {
+            let (_, server) = warp::serve(routes).try_bind_with_graceful_shutdown(config.bind, stopped)?;
+            tokio::spawn(server)
+        }
+    };
 
+    shutdown_signal().await;
+    let _ = stop_tx.send(());
+    if !shutdown(server, config.shutdown_timeout()).await {
+        // A proof still running on a blocking thread would hold up runtime teardown
+        std::process::exit(1);
+    }
     Ok(())
 }
 
-async fn handle_websocket(websocket: warp::ws::WebSocket) {
-    use futures_util::{SinkExt, StreamExt};
-    use zk_chat::websocket::ProtocolMessage;
-    use std::{
-        sync::{Arc, Mutex},
-    };
-    use once_cell::sync::Lazy;
-    use tokio::sync::broadcast;
+// Global state with broadcast channel for real-time updates
+static GLOBAL_STATE: Lazy<Arc<Mutex<GlobalState>>> = Lazy::new(|| {
+    Arc::new(Mutex::new(GlobalState::new()))
+});
 
-    // Global state with broadcast channel for real-time updates
-    static GLOBAL_STATE: Lazy<Arc<Mutex<GlobalState>>> = Lazy::new(|| {
-        Arc::new(Mutex::new(GlobalState::new()))
-    });
+static BROADCAST_TX: Lazy<broadcast::Sender<String>> = Lazy::new(|| {
+    let (tx, _) = broadcast::channel(config().broadcast_capacity);
+    tx
+});
 
-    static BROADCAST_TX: Lazy<broadcast::Sender<String>> = Lazy::new(|| {
-        let (tx, _) = broadcast::channel(100);
-        tx
-    });
+// Wakes the batch prover when a message is queued
+static BATCH_NOTIFY: Lazy<Notify> = Lazy::new(Notify::new);
+
+// Long-term key signing chain checkpoints (loaded in main)
+static SERVER_SIGNING_KEY: OnceCell<ServerSigningKey> = OnceCell::new();
+
+// Redacted segment proofs served by /api/redacted, extended as the chain grows
+static REDACTED_PROOFS: Lazy<tokio::sync::Mutex<RedactedProofs>> = Lazy::new(|| {
+    tokio::sync::Mutex::new(RedactedProofs { epoch: 0, segments: Vec::new() })
+});
+
+// Bearer token guarding the admin API (set in main)
+static ADMIN_TOKEN: OnceCell<String> = OnceCell::new();
+
+// Effective server settings (loaded in main)
+static CONFIG: OnceCell<ServerConfig> = OnceCell::new();
+
+fn config() -> &'static ServerConfig {
+    CONFIG.get_or_init(ServerConfig::default)
+}
+
+// How long a closing connection may take to flush its last messages
+const CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+// How often the server broadcasts a signed checkpoint of the chain head
+const CHECKPOINT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+async fn handle_websocket(websocket: warp::ws::WebSocket, remote: Option<std::net::IpAddr>) {
+    use futures_util::{SinkExt, StreamExt};
+    use zk_chat::websocket::ProtocolMessage;
 
     let (mut ws_sender, mut ws_receiver) = websocket.split();
     let mut user_id: Option<u64> = None;
+    // Join awaiting its signed challenge: (user id, nonce)
+    let mut pending_join: Option<(u64, String)> = None;
+    // Registrations are limited per client address; connections without one (e.g. over TLS) share a bucket
+    let remote = remote.unwrap_or(std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED));
     let mut broadcast_rx = BROADCAST_TX.subscribe();
-
     // Use a channel to send messages to the WebSocket sender
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
-    
-    // Spawn task to handle outgoing messages
-    tokio::spawn(async move {
-        while let Some(message) = rx.recv().await {
-            let _ = ws_sender.send(warp::ws::Message::text(message)).await;
+    let session = ClientSession { tx: tx.clone(), kicked: Arc::new(Notify::new()) };
+    let heartbeat = config().heartbeat;
+
+    // Spawn task to handle outgoing messages and heartbeat pings; it closes the socket once every
+    // sender is gone, or gives up as soon as the socket stops accepting writes
+    let ping_interval = heartbeat.interval();
+    let mut outgoing = tokio::spawn(async move {
+        let mut pings = ping_interval.map(|period| tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+        loop {
+            let message = tokio::select! {
+                message = rx.recv() => match message {
+                    Some(message) => warp::ws::Message::text(message),
+                    None => break,
+                },
+                _ = async {
+                    match pings.as_mut() {
+                        Some(pings) => { pings.tick().await; }
+                        None => std::future::pending().await,
+                    }
+                } => warp::ws::Message::ping(Vec::new()),
+            };
+            if ws_sender.send(message).await.is_err() {
+                break;
+            }
         }
+        let _ = ws_sender.close().await;
     });
     
     // Spawn task to handle broadcasts from other clients
     let tx_clone = tx.clone();
-    tokio::spawn(async move {
-        while let Ok(message) = broadcast_rx.recv().await {
-            let _ = tx_clone.send(message);
+    let forwarding = tokio::spawn(async move {
+        loop {
+            match broadcast_rx.recv().await {
+                Ok(message) => {
+                    let _ = tx_clone.send(message);
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Connection fell behind; skipped {} broadcasts", skipped);
+                    metrics::BROADCASTS_DROPPED.inc_by(skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
         }
     });
 
-    while let Some(result) = ws_receiver.next().await {
+    // Any frame from the client, including the pong answering a heartbeat, counts as a sign of life
+    let mut last_seen = tokio::time::Instant::now();
+    loop {
+        let idle = async {
+            match heartbeat.idle_timeout() {
+                Some(timeout) => tokio::time::sleep_until(last_seen + timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let result = tokio::select! {
+            result = ws_receiver.next() => match result {
+                Some(result) => result,
+                None => break,
+            },
+            _ = session.kicked.notified() => break,
+            _ = idle => {
+                info!("Dropping idle connection (user {:?}, silent for {}s)", user_id, heartbeat.idle_timeout_secs);
+                metrics::IDLE_DISCONNECTS.inc();
+                break;
+            }
+        };
+        last_seen = tokio::time::Instant::now();
         match result {
             Ok(msg) => {
                 if msg.is_text() {
                     let text = msg.to_str().unwrap();
                     match serde_json::from_str::<ProtocolMessage>(text) {
                         Ok(protocol_msg) => {
-                            let response = handle_protocol_message(protocol_msg, &*GLOBAL_STATE, &mut user_id, &BROADCAST_TX).await;
-                            
-                            if let Ok(Some(response_msg)) = response {
+                            let joined_as = user_id;
+                            let response = handle_protocol_message(protocol_msg, &GLOBAL_STATE, &mut user_id, &mut pending_join, remote, &BROADCAST_TX).await;
+                            if user_id != joined_as {
+                                track_session(joined_as, user_id, &session);
+                            }
+
+                            let response = match response {
+                                Ok(response) => response,
+                                Err(e) => {
+                                    info!("Rejected message from {:?}: {}", user_id, e);
+                                    Some(ProtocolMessage::from(e))
+                                }
+                            };
+                            if let Some(response_msg) = response {
+                                metrics::record_outgoing(&response_msg);
                                 if let Ok(response_json) = serde_json::to_string(&response_msg) {
                                     let _ = tx.send(response_json);
                                 }
@@ -281,8 +696,12 @@ async fn handle_websocket(websocket: warp::ws::WebSocket) {
         }
     }
 
+    // Stop relaying broadcasts so the outgoing task drains and closes the socket
+    forwarding.abort();
+
     // Clean up user on disconnect
     if let Some(uid) = user_id {
+        track_session(Some(uid), None, &session);
         let mut state_lock = match GLOBAL_STATE.lock() {
             Ok(lock) => lock,
             Err(poisoned) => {
@@ -291,37 +710,83 @@ async fn handle_websocket(websocket: warp::ws::WebSocket) {
             }
         };
         state_lock.users.remove(&uid);
+        state_lock.public_keys.remove(&uid);
+        // Buckets outlive the connection so reconnecting does not reset the limit; only refilled ones go
+        state_lock.rate_limiter.prune();
+        state_lock.registration_limiter.prune();
         info!("User {} disconnected", uid);
-        
-        // Broadcast user list update
-        let users: Vec<(u64, String)> = state_lock
-            .users
-            .iter()
-            .map(|(&id, name)| (id, name.clone()))
-            .collect();
-        
-        if let Ok(update_msg) = serde_json::to_string(&ProtocolMessage::UserListUpdate { users }) {
-            let _ = BROADCAST_TX.send(update_msg);
-        }
+        broadcast_user_list(&state_lock);
+    }
+
+    // With every sender dropped the outgoing task closes the socket; a dead peer must not keep it alive
+    drop(tx);
+    drop(session);
+    if tokio::time::timeout(CLOSE_TIMEOUT, &mut outgoing).await.is_err() {
+        outgoing.abort();
     }
 }
 
+// A joined connection, as seen by the admin API
+#[derive(Debug, Clone)]
+struct ClientSession {
+    tx: tokio::sync::mpsc::UnboundedSender<String>,
+    kicked: Arc<Notify>,
+}
+
+// A batch currently being proven
+#[derive(Debug)]
+struct ProvingJob {
+    message_ids: Vec<GlobalId>,
+    started: std::time::Instant,
+}
+
 #[derive(Debug)]
 struct GlobalState {
+    // Registered users; `users` holds only those currently online
+    registry: UserRegistry,
     users: std::collections::HashMap<u64, String>,
+    // Connections of joined users, so they can be kicked
+    sessions: std::collections::HashMap<u64, ClientSession>,
+    // Incremented on every salt rotation; each epoch starts a fresh chain
+    epoch: u64,
+    proving: Option<ProvingJob>,
     message_chain: zk_chat::zk::MessageChain,
-    next_global_id: u64,
+    // Last message id assigned to each sender; ids are per-sender sequence numbers proven gap-free
     per_sender_local: std::collections::HashMap<u64, u64>,
+    // Messages awaiting a shared batch proof
+    batch_prover: zk_chat::zk::batch::BatchProver,
+    // Latest compressed chain proof, broadcast once per proven batch
+    proof_cache: zk_chat::zk::compression::ProofCache,
+    // E2E identity keys of connected users (message content is room-key ciphertext)
+    public_keys: std::collections::HashMap<u64, String>,
+    // Per-message content commitment salts for redacted transcripts, keyed by global id
+    content_salts: std::collections::HashMap<GlobalId, [u8; 32]>,
+    // Per-user limit on SendMessage
+    rate_limiter: RateLimiter,
+    // Per-address limit on WebSocket Register
+    registration_limiter: RateLimiter<std::net::IpAddr>,
+    // Set on shutdown: new messages are refused while queued ones are proven
+    shutting_down: bool,
     // Removed prover/proof_options stored here; per-message instantiated
 }
 
 impl GlobalState {
     fn new() -> Self {
         Self {
+            registry: UserRegistry::in_memory(),
             users: std::collections::HashMap::new(),
+            sessions: std::collections::HashMap::new(),
+            epoch: 0,
+            proving: None,
             message_chain: zk_chat::zk::MessageChain::new(),
-            next_global_id: 1,
             per_sender_local: std::collections::HashMap::new(),
+            batch_prover: zk_chat::zk::batch::BatchProver::new(config().batch_config()),
+            proof_cache: zk_chat::zk::compression::ProofCache::default(),
+            rate_limiter: RateLimiter::new(config().rate_limit.messages_per_minute, config().rate_limit.burst),
+            registration_limiter: RateLimiter::new(config().rate_limit.registrations_per_minute, config().rate_limit.registrations_per_minute),
+            public_keys: std::collections::HashMap::new(),
+            content_salts: std::collections::HashMap::new(),
+            shutting_down: false,
             // Prover instantiated on demand
         }
     }
@@ -331,16 +796,57 @@ async fn handle_protocol_message(
     msg: zk_chat::websocket::ProtocolMessage,
     state: &std::sync::Arc<std::sync::Mutex<GlobalState>>,
     user_id: &mut Option<u64>,
+    pending_join: &mut Option<(u64, String)>,
+    remote: std::net::IpAddr,
     broadcast_tx: &tokio::sync::broadcast::Sender<String>,
 ) -> zk_chat::Result<Option<zk_chat::websocket::ProtocolMessage>> {
     use zk_chat::{
+        registry::{join_challenge, verify_join},
         websocket::ProtocolMessage,
         ZkChatError,
     };
 
     match msg {
-        ProtocolMessage::Join { user_id: uid, username } => {
-            let mut state_lock = state.lock().unwrap();
+        ProtocolMessage::Register { username, public_key } => {
+            // Self-registered accounts must bring the key they will sign join challenges with
+            let public_key = public_key.ok_or(ZkChatError::InvalidPublicKey)?;
+            let mut state_lock = state.lock().unwrap_or_else(|p| p.into_inner());
+            state_lock.registration_limiter.check(remote)?;
+            let user = state_lock.registry.register(&username, Some(public_key))?;
+            info!("🆕 Registered user {} ({})", user.username, user.id);
+            Ok(Some(ProtocolMessage::Registered { user }))
+        }
+
+        ProtocolMessage::Rename { username } => {
+            let uid = user_id.ok_or(ZkChatError::InvalidSender)?;
+            Ok(Some(ProtocolMessage::Registered { user: rename_user(uid, &username)? }))
+        }
+
+        ProtocolMessage::Deactivate => {
+            let uid = user_id.ok_or(ZkChatError::InvalidSender)?;
+            let user = deactivate_user(uid)?;
+            *user_id = None;
+            Ok(Some(ProtocolMessage::Registered { user }))
+        }
+
+        ProtocolMessage::Join { user_id: uid, username: _ } => {
+            // Only registered, active ids with an account key may join; they must first sign a fresh nonce
+            let user = state.lock().unwrap_or_else(|p| p.into_inner()).registry.active_user(uid)?.clone();
+            if user.public_key.is_none() {
+                return Err(ZkChatError::AuthenticationFailed(uid));
+            }
+            let nonce = join_challenge();
+            *pending_join = Some((uid, nonce.clone()));
+            Ok(Some(ProtocolMessage::JoinChallenge { nonce }))
+        }
+
+        ProtocolMessage::JoinProof { signature } => {
+            let (uid, nonce) = pending_join.take().ok_or(ZkChatError::InvalidSender)?;
+            let mut state_lock = state.lock().unwrap_or_else(|p| p.into_inner());
+            // The display name comes from the registry
+            let user = state_lock.registry.active_user(uid)?;
+            verify_join(user, &nonce, &signature)?;
+            let username = user.username.clone();
             state_lock.users.insert(uid, username.clone());
             *user_id = Some(uid);
 
@@ -352,12 +858,17 @@ async fn handle_protocol_message(
                 .map(|(&id, name)| (id, name.clone()))
                 .collect();
 
-            // Broadcast user list update to all clients
-            if let Ok(broadcast_msg) = serde_json::to_string(&ProtocolMessage::UserListUpdate { users: users.clone() }) {
+            // Broadcast user list update to all clients (including the one joining)
+            if let Ok(broadcast_msg) = serde_json::to_string(&ProtocolMessage::UserListUpdate { users }) {
                 let _ = broadcast_tx.send(broadcast_msg);
             }
 
-            Ok(Some(ProtocolMessage::UserListUpdate { users }))
+            // Hand the newcomer the current head so it can mirror the chain from here
+            Ok(Some(ProtocolMessage::ChainState {
+                salt: session_salt(),
+                message_count: state_lock.message_chain.len(),
+                chain_hash: state_lock.message_chain.chain_hash,
+            }))
         }
 
         ProtocolMessage::SendMessage { message, proof: _ } => {
@@ -374,97 +885,520 @@ async fn handle_protocol_message(
                     poisoned.into_inner()
                 }
             };
+            if state_lock.shutting_down {
+                return Err(ZkChatError::ShuttingDown);
+            }
+            // The account may have been deactivated since joining
+            state_lock.registry.active_user(uid)?;
+            state_lock.rate_limiter.check(uid)?;
 
-            // Assign global id and derive per-sender local id (ignore client-provided id)
-            let global_id = state_lock.next_global_id;
-            state_lock.next_global_id += 1;
+            // Assign the sender's next sequence number (ignore client-provided id)
             let local_entry = state_lock.per_sender_local.entry(uid).or_insert(0);
             *local_entry += 1;
             let local_id = *local_entry;
-            // We use global_id for ZK hashing, local_id only for display; existing Message has single id field so we store global id.
-            
-            // Create message with server-assigned global id (ignoring client id)
-            let server_message = zk_chat::Message::new(global_id, message.sender_id, format!("{}", message.content.clone()), message.timestamp);
-            
-            // CRITICAL: Build proof with ALL messages in chain INCLUDING new one
-            let messages_for_proof = {
-                let mut msgs = state_lock.message_chain.messages.clone();
-                msgs.push(server_message.clone());
-                msgs
-            };
-            
-            let mut prover = zk_chat::zk::prover::MessageProver::new();
-            
-            // Generate ZK-STARK proof for complete chain
-            let proof_result = prover.prove(&messages_for_proof);
-            
-            let verified = match proof_result {
-                Ok(proof) => {
-                    // Verify the generated proof
-                    let mut temp_chain = state_lock.message_chain.clone();
-                    match temp_chain.add_message(server_message.clone()) {
-                        Ok(_) => {
-                            let pub_inputs = zk_chat::zk::air::PublicInputs {
-                                initial_hash: [0u8; 32], // Empty initial hash
-                                final_hash: temp_chain.chain_hash,
-                                message_count: temp_chain.len(),
-                            };
-                            
-                            match zk_chat::zk::prover::verify_proof(&proof, pub_inputs) {
-                                Ok(_) => {
-                                    // Also verify hash consistency
-                                    server_message.verify_hash()
-                                },
-                                Err(e) => {
-                                    info!("❌ ZK proof verification failed: {:?}", e);
-                                    false
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            info!("❌ Failed to add message to chain for verification: {:?}", e);
-                            false
-                        }
-                    }
-                },
-                Err(e) => {
-                    info!("❌ ZK proof generation failed: {:?}", e);
-                    false
+
+            // Create message with the server-assigned id; (sender, id) is its global id
+            let server_message = zk_chat::Message::hashed_with(state_lock.message_chain.hash_id, local_id, message.sender_id, message.content.clone(), message.timestamp);
+
+            // Queue for the batch prover; it proves and broadcasts the whole batch at once
+            state_lock.batch_prover.enqueue(server_message);
+            BATCH_NOTIFY.notify_one();
+
+            Ok(None) // Don't send individual response since we broadcast
+        }
+
+        ProtocolMessage::KeyAnnounce { user_id: announced, public_key } => {
+            let uid = user_id.ok_or(ZkChatError::InvalidSender)?;
+            if announced != uid {
+                return Err(ZkChatError::InvalidSender);
+            }
+
+            // Record the session's identity key, tell everyone about it and hand the newcomer the directory;
+            // the registered account key is never replaced from a session
+            let mut state_lock = state.lock().unwrap_or_else(|p| p.into_inner());
+            state_lock.public_keys.insert(uid, public_key.clone());
+            if let Ok(announce_json) = serde_json::to_string(&ProtocolMessage::KeyAnnounce { user_id: uid, public_key }) {
+                let _ = broadcast_tx.send(announce_json);
+            }
+            let keys = state_lock.public_keys.iter().map(|(&id, key)| (id, key.clone())).collect();
+            Ok(Some(ProtocolMessage::KeyDirectory { keys }))
+        }
+
+        ProtocolMessage::RoomKeyShare { from, to, sealed_key } => {
+            let uid = user_id.ok_or(ZkChatError::InvalidSender)?;
+            if from != uid {
+                return Err(ZkChatError::InvalidSender);
+            }
+
+            // Relay only: the sealed room key is opaque to the server
+            if let Ok(share_json) = serde_json::to_string(&ProtocolMessage::RoomKeyShare { from, to, sealed_key }) {
+                let _ = broadcast_tx.send(share_json);
+            }
+            Ok(None)
+        }
+
+        ProtocolMessage::HeadGossip { head } => {
+            let uid = user_id.ok_or(ZkChatError::InvalidSender)?;
+            if head.user_id != uid || head.salt != session_salt() || !head.verify() {
+                return Err(ZkChatError::InvalidSender);
+            }
+            if let Ok(gossip_json) = serde_json::to_string(&ProtocolMessage::HeadGossip { head }) {
+                let _ = broadcast_tx.send(gossip_json);
+            }
+            Ok(None)
+        }
+
+        ProtocolMessage::ForkAlert { evidence } => {
+            if !evidence.verify() {
+                return Err(ZkChatError::ChainInconsistency("invalid fork evidence".into()));
+            }
+            warn!(
+                "⚠️ Fork reported at {} messages: user {} saw {}, user {} saw {}",
+                evidence.first.message_count,
+                evidence.first.user_id, evidence.first.chain_hash,
+                evidence.second.user_id, evidence.second.chain_hash
+            );
+            if let Ok(alert_json) = serde_json::to_string(&ProtocolMessage::ForkAlert { evidence }) {
+                let _ = broadcast_tx.send(alert_json);
+            }
+            Ok(None)
+        }
+
+        ProtocolMessage::ChainMismatch { message_count, chain_hash, reason } => {
+            let uid = user_id.ok_or(ZkChatError::InvalidSender)?;
+            warn!("⚠️ User {} could not derive head {} at {} messages: {}", uid, chain_hash, message_count, reason);
+            Ok(None)
+        }
+
+        ProtocolMessage::Ping => Ok(Some(ProtocolMessage::Pong)),
+        _ => Ok(None),
+    }
+}
+
+/// Background task: proves queued messages in batches and broadcasts them with a shared proof
+async fn run_batch_prover() {
+    use zk_chat::websocket::ProtocolMessage;
+
+    loop {
+        // Wait until the queue holds a full batch or its oldest message has waited long enough
+        // (on shutdown, whatever is queued is proven straight away)
+        let wait = {
+            let state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+            let wait = state_lock.batch_prover.time_until_ready();
+            if state_lock.shutting_down { wait.map(|_| std::time::Duration::ZERO) } else { wait }
+        };
+        match wait {
+            None => {
+                BATCH_NOTIFY.notified().await;
+                continue;
+            }
+            Some(delay) if !delay.is_zero() => {
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = BATCH_NOTIFY.notified() => {}
                 }
-            };
-            
-            if verified {
-                info!("✅ Message verified with ZK-STARK proof from user {}: {}", uid, server_message.content);
-            } else {
-                info!("❌ ZK-STARK proof verification failed for user {}: {}", uid, server_message.content);
-            };
-            
-            if verified {
-                match state_lock.message_chain.add_message(server_message.clone()) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        info!("❌ Failed to add message to chain: {}", e);
-                    }
+                continue;
+            }
+            Some(_) => {}
+        }
+
+        let (batch, chain, epoch) = {
+            let mut state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+            let batch = state_lock.batch_prover.take_batch();
+            state_lock.proving = Some(ProvingJob { message_ids: batch.iter().map(GlobalId::of).collect(), started: std::time::Instant::now() });
+            (batch, state_lock.message_chain.clone(), state_lock.epoch)
+        };
+        let batch_len = batch.len();
+        let original = batch.clone();
+
+        // Proving is CPU-bound; keep it off the async workers
+        let outcome = match tokio::task::spawn_blocking(move || prove_batch_with_profile(&chain, batch, config().proof.profile)).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                warn!("Batch prover task failed: {}", e);
+                GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner()).proving = None;
+                continue;
+            }
+        };
+        let proof_ref = outcome.proof_ref();
+        let dropped = outcome.dropped();
+
+        let mut state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+        state_lock.proving = None;
+        if state_lock.epoch != epoch {
+            // The salt was rotated while proving: prove these messages again in the new epoch
+            // (as queued, before any renumbering against the old chain)
+            info!("🔁 Epoch rotated during proving; requeueing {} messages", batch_len);
+            state_lock.batch_prover.requeue(original);
+            BATCH_NOTIFY.notify_one();
+            continue;
+        }
+        if outcome.verified {
+            info!("✅ Batch of {} messages verified with one ZK-STARK proof (chain length {})", outcome.accepted.len(), outcome.chain.len());
+            state_lock.message_chain = outcome.chain;
+            for message in &outcome.accepted {
+                state_lock.content_salts.insert(GlobalId::of(message), random_salt());
+            }
+        } else {
+            info!("❌ ZK-STARK batch proof failed for {} messages", batch_len);
+        }
+        for (message, e) in &outcome.rejected {
+            info!("❌ Failed to add message {} from {} to chain: {}", message.id, message.sender_id, e);
+        }
+        // Messages that did not enter the chain leave holes in their senders' sequences:
+        // move the queued successors down and continue numbering after them
+        state_lock.batch_prover.close_gaps(&dropped);
+        for (sender_id, count) in dropped {
+            if let Some(last_id) = state_lock.per_sender_local.get_mut(&sender_id) {
+                *last_id = last_id.saturating_sub(count);
+            }
+        }
+
+        // Broadcast accepted messages in chain order with a reference to the shared proof, then rejected ones
+        let batch_reason = if outcome.verified { "ok" } else { "proof_failed" };
+        let broadcasts = outcome.accepted.into_iter()
+            .map(|message| (message, outcome.verified, proof_ref, batch_reason))
+            .chain(outcome.rejected.iter().map(|(message, e)| (message.clone(), false, None, metrics::error_reason(e))));
+        for (message, verified, proof_ref, reason) in broadcasts {
+            metrics::record_broadcast(verified, reason);
+            let local_id = message.id;
+            let broadcast_message = ProtocolMessage::MessageBroadcast { message, verified, local_id, proof_ref };
+            if let Ok(broadcast_json) = serde_json::to_string(&broadcast_message) {
+                let _ = BROADCAST_TX.send(broadcast_json);
+            }
+        }
+
+        // Publish the batch proof once, after the messages it covers; it proves the batch's segment only
+        if let Some(segment) = outcome.proof {
+            let start_count = state_lock.message_chain.len() - segment.public_inputs.message_count;
+            if let Err(e) = state_lock.proof_cache.update(start_count, &segment.public_inputs, &segment.proof) {
+                warn!("Failed to cache chain proof: {}", e);
+            }
+            if let Some(cached) = state_lock.proof_cache.flush() {
+                info!("📦 Broadcasting chain proof for {} messages ({} bytes compressed)", cached.message_count, cached.proof.len());
+                if let Ok(proof_json) = serde_json::to_string(&ProtocolMessage::from(cached)) {
+                    let _ = BROADCAST_TX.send(proof_json);
                 }
             }
+        }
+    }
+}
 
-            // Broadcast server-computed message to all clients
-            let broadcast_message = ProtocolMessage::MessageBroadcast {
-                message: server_message,
-                verified,
-                local_id,
+// Removed unused local compute_chain_hash; chain hashing is centralized in zk::MessageChain
+
+/// Sign the current chain head, referencing the latest published chain proof
+fn sign_checkpoint() -> zk_chat::zk::consistency::SignedCheckpoint {
+    checkpoint_of(&GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner()))
+}
+
+/// Sign the head of `state`; callers that change the chain sign before releasing the lock
+fn checkpoint_of(state: &GlobalState) -> zk_chat::zk::consistency::SignedCheckpoint {
+    let signer = SERVER_SIGNING_KEY.get().expect("signing key loaded in main");
+    let proof_ref = state.proof_cache.latest().map(|proof| proof.message_count);
+    let chain = &state.message_chain;
+    signer.sign(chain.len(), chain.chain_hash, chain.message_root(), session_salt(), proof_ref)
+}
+
+/// Background task: broadcasts a server-signed checkpoint whenever the chain has advanced
+async fn run_checkpoints() {
+    use zk_chat::websocket::ProtocolMessage;
+
+    let mut interval = tokio::time::interval(CHECKPOINT_INTERVAL);
+    let mut last_count = None;
+    loop {
+        interval.tick().await;
+        let checkpoint = sign_checkpoint();
+        if last_count == Some(checkpoint.message_count) {
+            continue;
+        }
+        last_count = Some(checkpoint.message_count);
+        info!("🔏 Broadcasting signed checkpoint at {} messages", checkpoint.message_count);
+        if let Ok(checkpoint_json) = serde_json::to_string(&ProtocolMessage::from(checkpoint)) {
+            let _ = BROADCAST_TX.send(checkpoint_json);
+        }
+    }
+}
+
+/// Rename a registered user and refresh the online user list if they are connected
+fn rename_user(id: u64, username: &str) -> zk_chat::Result<RegisteredUser> {
+    let mut state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+    let user = state_lock.registry.rename(id, username)?;
+    info!("✏️ User {} renamed to {}", id, user.username);
+    if let Some(name) = state_lock.users.get_mut(&id) {
+        *name = user.username.clone();
+        broadcast_user_list(&state_lock);
+    }
+    Ok(user)
+}
+
+/// Deactivate a registered user and take them offline
+fn deactivate_user(id: u64) -> zk_chat::Result<RegisteredUser> {
+    let mut state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+    let user = state_lock.registry.deactivate(id)?;
+    info!("🚫 User {} ({}) deactivated", user.username, id);
+    state_lock.public_keys.remove(&id);
+    if state_lock.users.remove(&id).is_some() {
+        broadcast_user_list(&state_lock);
+    }
+    Ok(user)
+}
+
+fn broadcast_user_list(state: &GlobalState) {
+    use zk_chat::websocket::ProtocolMessage;
+
+    let users = state.users.iter().map(|(&id, name)| (id, name.clone())).collect();
+    if let Ok(update_msg) = serde_json::to_string(&ProtocolMessage::UserListUpdate { users }) {
+        let _ = BROADCAST_TX.send(update_msg);
+    }
+}
+
+/// Map a registry result onto an admin API response
+fn registry_reply(result: zk_chat::Result<RegisteredUser>) -> Box<dyn warp::Reply> {
+    use warp::http::StatusCode;
+    use zk_chat::ZkChatError;
+
+    match result {
+        Ok(user) => Box::new(warp::reply::json(&user)),
+        Err(e) => {
+            let status = match e {
+                ZkChatError::InvalidUsername(_) => StatusCode::BAD_REQUEST,
+                ZkChatError::UsernameTaken(_) => StatusCode::CONFLICT,
+                ZkChatError::UnknownUser(_) => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
             };
-            
-            if let Ok(broadcast_json) = serde_json::to_string(&broadcast_message) {
-                let _ = broadcast_tx.send(broadcast_json);
+            Box::new(warp::reply::with_status(e.to_string(), status))
+        }
+    }
+}
+
+// Record which connection a user is joined on, dropping the entry it replaces
+fn track_session(previous: Option<u64>, current: Option<u64>, session: &ClientSession) {
+    let mut state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+    if let Some(uid) = previous {
+        if state_lock.sessions.get(&uid).is_some_and(|s| Arc::ptr_eq(&s.kicked, &session.kicked)) {
+            state_lock.sessions.remove(&uid);
+        }
+    }
+    if let Some(uid) = current {
+        state_lock.sessions.insert(uid, session.clone());
+    }
+}
+
+/// Disconnect a user, telling them why; returns whether they were connected
+fn kick_user(id: u64, reason: &str) -> bool {
+    use zk_chat::websocket::{error_codes, ProtocolMessage};
+
+    let session = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner()).sessions.remove(&id);
+    let Some(session) = session else {
+        return false;
+    };
+    let notice = ProtocolMessage::error(error_codes::UNAUTHORIZED, reason);
+    metrics::record_outgoing(&notice);
+    if let Ok(notice) = serde_json::to_string(&notice) {
+        let _ = session.tx.send(notice);
+    }
+    session.kicked.notify_one();
+    info!("👢 User {} kicked: {}", id, reason);
+    true
+}
+
+/// Ban or unban a registered user; banning also disconnects them
+fn set_user_banned(id: u64, banned: bool) -> zk_chat::Result<RegisteredUser> {
+    let user = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner()).registry.set_banned(id, banned)?;
+    info!("{} User {} ({})", if banned { "⛔ Banned" } else { "✅ Unbanned" }, user.username, id);
+    if banned {
+        kick_user(id, "banned by an administrator");
+    }
+    Ok(user)
+}
+
+/// Chain status reported by the admin API
+#[derive(Debug, Serialize)]
+struct ChainStatus {
+    epoch: u64,
+    salt: String, // decimal, as in `ChainState`
+    message_count: usize,
+    chain_hash: String,
+    message_root: String,
+    latest_proof: Option<usize>,
+}
+
+fn chain_status(state: &GlobalState) -> ChainStatus {
+    ChainStatus {
+        epoch: state.epoch,
+        salt: session_salt().to_string(),
+        message_count: state.message_chain.len(),
+        chain_hash: state.message_chain.chain_hash.to_string(),
+        message_root: state.message_chain.message_root().to_string(),
+        latest_proof: state.proof_cache.latest().map(|proof| proof.message_count),
+    }
+}
+
+/// Redacted segment proofs of the current epoch's chain, in order from genesis
+struct RedactedProofs {
+    epoch: u64,
+    segments: Vec<SegmentProof>,
+}
+
+impl RedactedProofs {
+    /// Whether every cached segment still covers the same messages, so it can be reused
+    fn matches(&self, messages: &[RedactedMessage]) -> bool {
+        let mut rest = messages;
+        self.segments.iter().all(|segment| {
+            let count = segment.public_inputs.message_count;
+            if count > rest.len() {
+                return false;
             }
+            let (covered, remaining) = rest.split_at(count);
+            rest = remaining;
+            redacted_segment_public_inputs(&ChainBoundary::start_of(&segment.public_inputs), covered)
+                .is_ok_and(|inputs| inputs == segment.public_inputs)
+        })
+    }
+}
 
-            Ok(None) // Don't send individual response since we broadcast
+/// Close the current epoch with a final signed checkpoint and start a fresh chain under a new salt
+fn rotate_epoch() -> ChainStatus {
+    use zk_chat::websocket::ProtocolMessage;
+
+    let mut state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+    let final_checkpoint = checkpoint_of(&state_lock);
+    let salt = zk_chat::zk::rotate_session_salt();
+    state_lock.epoch += 1;
+    state_lock.message_chain = zk_chat::zk::MessageChain::new();
+    state_lock.proof_cache = zk_chat::zk::compression::ProofCache::default();
+    state_lock.content_salts.clear();
+    info!("🔄 Epoch {} started after {} messages", state_lock.epoch, final_checkpoint.message_count);
+    let status = chain_status(&state_lock);
+    let empty_checkpoint = checkpoint_of(&state_lock);
+    drop(state_lock);
+
+    // Clients keep the old epoch's final head, re-anchor their mirrors on the new one, and switch
+    // checkpoint verification to the new salt on the signed head of its empty chain
+    let announcements = [
+        ProtocolMessage::from(final_checkpoint),
+        ProtocolMessage::ChainState { salt, message_count: 0, chain_hash: Digest::ZERO },
+        ProtocolMessage::from(empty_checkpoint),
+    ];
+    for message in announcements {
+        if let Ok(json) = serde_json::to_string(&message) {
+            let _ = BROADCAST_TX.send(json);
         }
+    }
+    status
+}
 
-        ProtocolMessage::Ping => Ok(Some(ProtocolMessage::Pong)),
-        _ => Ok(None),
+/// Adopt a chain restored from storage; each sender's ids continue after its last stored one
+fn restore_chain(state: &mut GlobalState, stored: &StoredChain, chain: zk_chat::zk::MessageChain) {
+    state.epoch = stored.epoch;
+    state.per_sender_local.clear();
+    for message in &chain.messages {
+        let last_id = state.per_sender_local.entry(message.sender_id).or_insert(0);
+        *last_id = (*last_id).max(message.id);
     }
+    state.content_salts = stored.content_salts.iter().map(|s| (s.global_id(), s.salt)).collect();
+    state.message_chain = chain;
 }
 
-// Removed unused local compute_chain_hash; chain hashing is centralized in zk::MessageChain
\ No newline at end of file
+// Time given to connection tasks to send their last messages and close frames
+const CLOSE_GRACE: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Graceful shutdown once the HTTP server has stopped accepting: tell clients, finish queued and
+/// in-flight proofs (up to `timeout`), flush the chain and close the sessions.
+/// Returns false if proofs were still pending when the timeout expired.
+async fn shutdown(server: tokio::task::JoinHandle<()>, timeout: std::time::Duration) -> bool {
+    use zk_chat::websocket::ProtocolMessage;
+
+    info!("🛑 Shutting down: no longer accepting connections or messages");
+    GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner()).shutting_down = true;
+    let notice = ProtocolMessage::ServerShutdown { reason: "Server is shutting down".into() };
+    if let Ok(json) = serde_json::to_string(&notice) {
+        let _ = BROADCAST_TX.send(json);
+    }
+
+    // In-flight REST requests (/api/prove, /api/verify, ...) finish before the server task does;
+    // then wait for the batch prover to empty its queue
+    let drained = tokio::time::timeout(timeout, async {
+        let _ = server.await;
+        loop {
+            {
+                let state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+                if state_lock.proving.is_none() && state_lock.batch_prover.pending().is_empty() {
+                    break;
+                }
+            }
+            BATCH_NOTIFY.notify_one();
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .is_ok();
+
+    let final_checkpoint = sign_checkpoint();
+    let sessions: Vec<ClientSession> = {
+        let state_lock = GLOBAL_STATE.lock().unwrap_or_else(|p| p.into_inner());
+        if !drained {
+            let in_flight = state_lock.proving.as_ref().map_or(0, |job| job.message_ids.len());
+            warn!(
+                "⏱️ Shutdown timed out after {:?}; {} queued messages were not proven",
+                timeout,
+                in_flight + state_lock.batch_prover.pending().len()
+            );
+        }
+
+        let chain_path = config().chain_path();
+        let stored = StoredChain::new(&state_lock.message_chain, state_lock.epoch)
+            .with_content_salts(state_lock.content_salts.iter().map(|(&global_id, &salt)| (global_id, salt)));
+        match stored.save(&chain_path) {
+            Ok(()) => info!("💾 Saved {} messages to {}", stored.messages.len(), chain_path.display()),
+            Err(e) => warn!("Failed to save chain to {}: {}", chain_path.display(), e),
+        }
+        state_lock.sessions.values().cloned().collect()
+    };
+
+    // Hand every session the final signed head, then close it
+    let checkpoint = serde_json::to_string(&ProtocolMessage::from(final_checkpoint));
+    for session in sessions {
+        if let Ok(checkpoint) = &checkpoint {
+            let _ = session.tx.send(checkpoint.clone());
+        }
+        session.kicked.notify_one();
+    }
+    tokio::time::sleep(CLOSE_GRACE).await;
+    drained
+}
+
+/// Pass only when a feature toggle is on; disabled endpoints answer 404
+fn enabled(flag: bool) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::any()
+        .and_then(move || async move { if flag { Ok(()) } else { Err(warp::reject::not_found()) } })
+        .untuple_one()
+}
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+/// Require `Authorization: Bearer <admin token>`
+fn admin_auth() -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(|header: Option<String>| async move {
+            let expected = ADMIN_TOKEN.get().map(String::as_bytes).unwrap_or_default();
+            let given = header.as_deref().and_then(|h| h.strip_prefix("Bearer ")).unwrap_or_default().as_bytes();
+            // Compare without an early exit so the token cannot be guessed byte by byte
+            let matches = !expected.is_empty()
+                && given.len() == expected.len()
+                && given.iter().zip(expected).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0;
+            if matches { Ok(()) } else { Err(warp::reject::custom(Unauthorized)) }
+        })
+        .untuple_one()
+}
+
+/// Turn admin authentication failures into 401 responses
+async fn handle_rejection(rejection: warp::Rejection) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    if rejection.find::<Unauthorized>().is_some() {
+        return Ok(Box::new(warp::reply::with_status("admin token required", warp::http::StatusCode::UNAUTHORIZED)));
+    }
+    Err(rejection)
+}
@@ -1,5 +1,5 @@
-use zk_chat::{Message, zk::air::{build_trace, PublicInputs}, zk::{elements_to_hash, hash_to_elements}};
-use winterfell::math::fields::f128::BaseElement;
+use zk_chat::{Message, zk::air::{build_trace, GlobalId, PublicInputs, BLOCK_LENGTH, CHAIN_HASH, PREV_HASH}, zk::{session_salt, Digest}};
+use zk_chat::zk::field::BaseElement;
 use winterfell::math::FieldElement;
 
 fn main() {
@@ -10,16 +10,16 @@ fn main() {
 
     println!("Trace length: {} (cols: {})", trace[0].len(), trace.len());
 
-    // Print first 8 columns for actual message step 0
-    println!("Row 0 prev_chain_hash (0..3): {:?}", &trace[0..4].iter().map(|c| c[0]).collect::<Vec<_>>());
-    println!("Row 0 chain_hash (4..7): {:?}", &trace[4..8].iter().map(|c| c[0]).collect::<Vec<_>>());
+    // Print the hash columns of the message block
+    println!("Row 0 prev_chain_hash (0..3): {:?}", &trace[PREV_HASH..PREV_HASH + 4].iter().map(|c| c[0]).collect::<Vec<_>>());
+    println!("Row {} chain_hash (4..7): {:?}", BLOCK_LENGTH - 1, &trace[CHAIN_HASH..CHAIN_HASH + 4].iter().map(|c| c[BLOCK_LENGTH - 1]).collect::<Vec<_>>());
 
-    // Derive expected final hash from trace row 0 columns 4..7
+    // Derive expected final hash from the block's last step, columns 4..7
     let mut final_hash_elements = [BaseElement::ZERO; 4];
-    for i in 0..4 { final_hash_elements[i] = trace[4 + i][0]; }
-    let final_hash = elements_to_hash(&final_hash_elements);
+    for i in 0..4 { final_hash_elements[i] = trace[CHAIN_HASH + i][BLOCK_LENGTH - 1]; }
+    let final_hash = Digest::new(final_hash_elements);
 
-    let _pub_inputs = PublicInputs { initial_hash: [0u8;32], final_hash, message_count: 1 };
-    println!("PublicInputs final_hash bytes: {}", hex::encode(final_hash));
-    println!("PublicInputs final_hash elements: {:?}", hash_to_elements(&final_hash));
+    let _pub_inputs = PublicInputs { initial_hash: Digest::ZERO, initial_timestamp: 0, final_hash, message_count: 1, message_root: msg.hash, hash_id: msg.hash_id, final_timestamp: msg.timestamp, salt: session_salt(), message_index: vec![GlobalId::of(&msg)] };
+    println!("PublicInputs final_hash bytes: {}", final_hash);
+    println!("PublicInputs final_hash elements: {:?}", final_hash.elements());
 }
@@ -0,0 +1,352 @@
+use crate::{Result, ZkChatError, zk::{hasher::HashId, prover::ProofProfile}};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Prefix of environment variables overriding config keys (`ZK_CHAT_BIND`, `ZK_CHAT_PROOF_PROFILE`, ...)
+pub const ENV_PREFIX: &str = "ZK_CHAT_";
+
+/// Config file read when neither `--config` nor `ZK_CHAT_CONFIG` names one (skipped if missing)
+pub const DEFAULT_CONFIG_PATH: &str = "zk-chat.toml";
+
+/// Settings of the warp server, layered as defaults < TOML file < environment < command line
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: SocketAddr,
+    pub static_dir: PathBuf,
+    pub broadcast_capacity: usize,
+    pub admin_token: Option<String>,
+    pub shutdown_timeout_secs: u64,
+    pub tls: TlsConfig,
+    pub proof: ProofConfig,
+    pub rate_limit: RateLimitConfig,
+    pub heartbeat: HeartbeatConfig,
+    pub storage: StorageConfig,
+    pub features: FeatureToggles,
+}
+
+/// TLS is enabled when both a certificate chain and a private key are configured (PEM files).
+/// The files are checked for changes every `reload_secs` seconds (0 disables reloading).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub reload_secs: u64,
+}
+
+/// Proof parameters, the hash new chains are built with, and batching
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProofConfig {
+    pub profile: ProofProfile,
+    pub hash: HashId,
+    pub batch_size: usize,
+    pub batch_wait_ms: u64,
+}
+
+/// Per-user limit on sent messages and per-address limit on WebSocket registrations (token buckets); 0 disables either
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub messages_per_minute: u32,
+    pub burst: u32,
+    pub registrations_per_minute: u32,
+}
+
+/// Server-initiated WebSocket pings and the idle timeout after which a silent connection is dropped; 0 disables either
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatConfig {
+    pub interval_secs: u64,
+    pub idle_timeout_secs: u64,
+}
+
+/// Where persistent server state lives; relative file names are resolved against `dir`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub dir: PathBuf,
+    pub signing_key: PathBuf,
+    pub users: PathBuf,
+    pub chain: PathBuf,
+}
+
+/// Optional groups of HTTP endpoints
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeatureToggles {
+    pub test_harness: bool,
+    pub admin_api: bool,
+    pub metrics: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 8081)),
+            static_dir: PathBuf::from("static"),
+            broadcast_capacity: 100,
+            admin_token: None,
+            shutdown_timeout_secs: 30,
+            tls: TlsConfig::default(),
+            proof: ProofConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            heartbeat: HeartbeatConfig::default(),
+            storage: StorageConfig::default(),
+            features: FeatureToggles::default(),
+        }
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self { cert: None, key: None, reload_secs: 30 }
+    }
+}
+
+impl Default for ProofConfig {
+    fn default() -> Self {
+        Self { profile: ProofProfile::default(), hash: HashId::default(), batch_size: 16, batch_wait_ms: 200 }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self { messages_per_minute: 120, burst: 20, registrations_per_minute: 5 }
+    }
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self { interval_secs: 30, idle_timeout_secs: 90 }
+    }
+}
+
+impl HeartbeatConfig {
+    /// How often to ping each connection
+    pub fn interval(&self) -> Option<Duration> {
+        Some(Duration::from_secs(self.interval_secs)).filter(|d| !d.is_zero())
+    }
+
+    /// How long a connection may stay silent (no frames, not even pongs) before it is dropped
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        Some(Duration::from_secs(self.idle_timeout_secs)).filter(|d| !d.is_zero())
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self { dir: PathBuf::from("."), signing_key: PathBuf::from("server_signing.key"), users: PathBuf::from("users.json"), chain: PathBuf::from("chain.json") }
+    }
+}
+
+impl Default for FeatureToggles {
+    fn default() -> Self {
+        Self { test_harness: true, admin_api: true, metrics: true }
+    }
+}
+
+/// Keys accepted by `ServerConfig::set`, as `--flag` names (with '-') and `ZK_CHAT_*` suffixes (upper case)
+pub const CONFIG_KEYS: &[&str] = &[
+    "bind", "static_dir", "broadcast_capacity", "admin_token", "shutdown_timeout_secs",
+    "tls_cert", "tls_key", "tls_reload_secs",
+    "proof_profile", "hash", "batch_size", "batch_wait_ms",
+    "rate_limit", "rate_limit_burst", "registration_limit",
+    "heartbeat_secs", "idle_timeout_secs",
+    "storage_dir", "signing_key", "users", "chain",
+    "test_harness", "admin_api", "metrics",
+];
+
+impl ServerConfig {
+    /// Parse a TOML config document
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| ZkChatError::Config(e.to_string()))
+    }
+
+    /// Build the effective config from a process's arguments (without the program name) and environment
+    pub fn load(args: impl IntoIterator<Item = String>, env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let args = CliArgs::parse(args)?;
+        let (path, required) = match args.config.clone().or_else(|| env("ZK_CHAT_CONFIG").map(PathBuf::from)) {
+            Some(path) => (path, true),
+            None => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+        };
+
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(text) => Self::from_toml(&text).map_err(|e| ZkChatError::Config(format!("{}: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => Self::default(),
+            Err(e) => return Err(ZkChatError::Config(format!("{}: {}", path.display(), e))),
+        };
+        config.apply_env(env)?;
+        for (key, value) in &args.settings {
+            config.set(key, value)?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Apply `ZK_CHAT_<KEY>` overrides
+    pub fn apply_env(&mut self, env: impl Fn(&str) -> Option<String>) -> Result<()> {
+        for key in CONFIG_KEYS {
+            if let Some(value) = env(&format!("{}{}", ENV_PREFIX, key.to_uppercase())) {
+                self.set(key, &value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set one key (see `CONFIG_KEYS`) from its string form
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let key = key.replace('-', "_");
+        let invalid = |e: &dyn std::fmt::Display| ZkChatError::Config(format!("invalid value {:?} for {}: {}", value, key, e));
+        match key.as_str() {
+            "bind" => self.bind = value.parse().map_err(|e| invalid(&e))?,
+            "static_dir" => self.static_dir = value.into(),
+            "broadcast_capacity" => self.broadcast_capacity = value.parse().map_err(|e| invalid(&e))?,
+            "admin_token" => self.admin_token = Some(value.to_string()).filter(|t| !t.is_empty()),
+            "shutdown_timeout_secs" => self.shutdown_timeout_secs = value.parse().map_err(|e| invalid(&e))?,
+            "tls_cert" => self.tls.cert = Some(value.into()).filter(|p: &PathBuf| !p.as_os_str().is_empty()),
+            "tls_key" => self.tls.key = Some(value.into()).filter(|p: &PathBuf| !p.as_os_str().is_empty()),
+            "tls_reload_secs" => self.tls.reload_secs = value.parse().map_err(|e| invalid(&e))?,
+            "proof_profile" => self.proof.profile = value.parse()?,
+            "hash" => self.proof.hash = value.parse()?,
+            "batch_size" => self.proof.batch_size = value.parse().map_err(|e| invalid(&e))?,
+            "batch_wait_ms" => self.proof.batch_wait_ms = value.parse().map_err(|e| invalid(&e))?,
+            "rate_limit" => self.rate_limit.messages_per_minute = value.parse().map_err(|e| invalid(&e))?,
+            "rate_limit_burst" => self.rate_limit.burst = value.parse().map_err(|e| invalid(&e))?,
+            "registration_limit" => self.rate_limit.registrations_per_minute = value.parse().map_err(|e| invalid(&e))?,
+            "heartbeat_secs" => self.heartbeat.interval_secs = value.parse().map_err(|e| invalid(&e))?,
+            "idle_timeout_secs" => self.heartbeat.idle_timeout_secs = value.parse().map_err(|e| invalid(&e))?,
+            "storage_dir" => self.storage.dir = value.into(),
+            "signing_key" => self.storage.signing_key = value.into(),
+            "users" => self.storage.users = value.into(),
+            "chain" => self.storage.chain = value.into(),
+            "test_harness" => self.features.test_harness = parse_bool(value).ok_or_else(|| invalid(&"expected true or false"))?,
+            "admin_api" => self.features.admin_api = parse_bool(value).ok_or_else(|| invalid(&"expected true or false"))?,
+            "metrics" => self.features.metrics = parse_bool(value).ok_or_else(|| invalid(&"expected true or false"))?,
+            _ => return Err(ZkChatError::Config(format!("unknown setting {:?}", key))),
+        }
+        Ok(())
+    }
+
+    /// Reject settings the server cannot run with
+    pub fn validate(&self) -> Result<()> {
+        if self.broadcast_capacity == 0 {
+            return Err(ZkChatError::Config("broadcast_capacity must be at least 1".into()));
+        }
+        if self.proof.batch_size == 0 {
+            return Err(ZkChatError::Config("batch_size must be at least 1".into()));
+        }
+        if !self.proof.hash.provable() {
+            return Err(ZkChatError::Config(format!("hash {} cannot be proven; use poseidon or poseidon-separated", self.proof.hash)));
+        }
+        if let (Some(interval), Some(idle_timeout)) = (self.heartbeat.interval(), self.heartbeat.idle_timeout()) {
+            if idle_timeout <= interval {
+                return Err(ZkChatError::Config("idle_timeout_secs must be longer than heartbeat_secs".into()));
+            }
+        }
+        if self.tls.cert.is_some() != self.tls.key.is_some() {
+            return Err(ZkChatError::Config("TLS needs both tls_cert and tls_key".into()));
+        }
+        Ok(())
+    }
+
+    /// Whether the server terminates TLS itself
+    pub fn tls_enabled(&self) -> bool {
+        self.tls.cert.is_some() && self.tls.key.is_some()
+    }
+
+    /// Batching parameters for the batch prover
+    pub fn batch_config(&self) -> crate::zk::batch::BatchConfig {
+        crate::zk::batch::BatchConfig {
+            max_batch_size: self.proof.batch_size,
+            max_wait: Duration::from_millis(self.proof.batch_wait_ms),
+        }
+    }
+
+    /// Path of the checkpoint signing key
+    pub fn signing_key_path(&self) -> PathBuf {
+        self.storage.resolve(&self.storage.signing_key)
+    }
+
+    /// Path of the user registry
+    pub fn users_path(&self) -> PathBuf {
+        self.storage.resolve(&self.storage.users)
+    }
+
+    /// Path of the chain flushed on shutdown and restored on start
+    pub fn chain_path(&self) -> PathBuf {
+        self.storage.resolve(&self.storage.chain)
+    }
+
+    /// How long shutdown waits for queued and in-flight proofs
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+}
+
+impl StorageConfig {
+    /// Resolve a storage file name against the storage directory
+    pub fn resolve(&self, file: &Path) -> PathBuf {
+        if file.is_absolute() { file.to_path_buf() } else { self.dir.join(file) }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parsed command line: `[ADDR] [--config PATH] [--key value | --key=value | --no-<toggle>]...`
+#[derive(Debug, Default)]
+struct CliArgs {
+    config: Option<PathBuf>,
+    settings: Vec<(String, String)>,
+}
+
+impl CliArgs {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                // A bare address, as in `cargo run --bin server -- 127.0.0.1:9090`
+                parsed.settings.push(("bind".into(), arg));
+                continue;
+            };
+            let (key, value) = match flag.split_once('=') {
+                Some((key, value)) => (key.to_string(), value.to_string()),
+                None if flag.starts_with("no-") => (flag[3..].to_string(), "false".into()),
+                None if ["test-harness", "admin-api", "metrics"].contains(&flag) => (flag.to_string(), "true".into()),
+                None => {
+                    let value = args.next().ok_or_else(|| ZkChatError::Config(format!("--{} needs a value", flag)))?;
+                    (flag.to_string(), value)
+                }
+            };
+            if key == "config" {
+                parsed.config = Some(value.into());
+            } else {
+                parsed.settings.push((key, value));
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Usage text for the server binary
+pub fn usage() -> String {
+    let flags: Vec<String> = CONFIG_KEYS.iter().map(|key| format!("--{}", key.replace('_', "-"))).collect();
+    format!(
+        "usage: server [ADDR] [--config PATH] [--<setting> VALUE]...\n\nsettings: {}\n\n\
+         Every setting can also be given as {}<SETTING> in the environment or in the TOML config file ({} by default).\n\
+         Feature toggles accept --no-test-harness, --no-admin-api and --no-metrics.",
+        flags.join(", "),
+        ENV_PREFIX,
+        DEFAULT_CONFIG_PATH
+    )
+}
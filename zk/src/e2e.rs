@@ -0,0 +1,345 @@
+use crate::{websocket::ProtocolMessage, Result, ZkChatError};
+use aes_gcm::{aead::{Aead, Payload}, Aes256Gcm, KeyInit, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use hkdf::Hkdf;
+use p256::{elliptic_curve::sec1::ToEncodedPoint, PublicKey, SecretKey};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+
+/// Prefix marking encrypted message content
+pub const E2E_CONTENT_PREFIX: &str = "e2e1:";
+
+/// HKDF info string for pairwise room key wrapping keys
+const ROOM_KEY_INFO: &[u8] = b"zk-chat room key v1";
+
+const NONCE_LEN: usize = 12;
+
+/// Check whether message content is E2E ciphertext
+pub fn is_encrypted(content: &str) -> bool {
+    content.starts_with(E2E_CONTENT_PREFIX)
+}
+
+fn encryption_error(what: &str) -> ZkChatError {
+    ZkChatError::Encryption(what.to_string())
+}
+
+// AES-256-GCM with a random nonce prepended to the ciphertext
+fn seal(cipher: &Aes256Gcm, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(&Nonce::from(nonce), Payload { msg: plaintext, aad })
+        .map_err(|_| encryption_error("encryption failed"))?;
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+fn open(cipher: &Aes256Gcm, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(encryption_error("ciphertext too short"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let nonce: [u8; NONCE_LEN] = nonce.try_into().expect("split at nonce length");
+    cipher
+        .decrypt(&Nonce::from(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| encryption_error("decryption failed"))
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>> {
+    general_purpose::STANDARD.decode(data).map_err(|_| encryption_error("invalid base64"))
+}
+
+/// Fingerprint of an identity key for out-of-band comparison: the first 16 bytes of SHA-256 over
+/// the SEC1 point, as eight groups of four hex digits
+pub fn fingerprint(public_key: &str) -> Result<String> {
+    let digest = Sha256::digest(decode_base64(public_key)?);
+    Ok(digest[..16].chunks(2).map(hex::encode).collect::<Vec<_>>().join(" "))
+}
+
+/// Symmetric key shared by the members of a room
+#[derive(Clone, PartialEq, Eq)]
+pub struct RoomKey {
+    key: [u8; 32],
+}
+
+impl RoomKey {
+    /// Generate a fresh random room key
+    pub fn generate() -> Self {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        Self { key }
+    }
+
+    /// Wrap raw key bytes
+    pub fn from_bytes(key: [u8; 32]) -> Self {
+        Self { key }
+    }
+
+    /// Short identifier carried in ciphertext: first 4 bytes of SHA-256(key), hex encoded
+    pub fn key_id(&self) -> String {
+        hex::encode(&Sha256::digest(self.key)[..4])
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new_from_slice(&self.key).expect("room key is 32 bytes")
+    }
+
+    /// Encrypt content, binding the sender id as associated data
+    pub fn encrypt(&self, sender_id: u64, plaintext: &str) -> Result<String> {
+        let sealed = seal(&self.cipher(), plaintext.as_bytes(), &sender_id.to_le_bytes())?;
+        Ok(format!("{}{}:{}", E2E_CONTENT_PREFIX, self.key_id(), general_purpose::STANDARD.encode(sealed)))
+    }
+
+    /// Decrypt content produced by `encrypt` for the same sender
+    pub fn decrypt(&self, sender_id: u64, content: &str) -> Result<String> {
+        let (key_id, payload) = split_content(content)?;
+        if key_id != self.key_id() {
+            return Err(encryption_error("content encrypted under a different room key"));
+        }
+        let plaintext = open(&self.cipher(), &decode_base64(payload)?, &sender_id.to_le_bytes())?;
+        String::from_utf8(plaintext).map_err(|_| encryption_error("plaintext is not UTF-8"))
+    }
+}
+
+impl std::fmt::Debug for RoomKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RoomKey({})", self.key_id())
+    }
+}
+
+// Split `e2e1:<key id>:<base64>` into key id and payload
+fn split_content(content: &str) -> Result<(&str, &str)> {
+    content
+        .strip_prefix(E2E_CONTENT_PREFIX)
+        .and_then(|rest| rest.split_once(':'))
+        .ok_or_else(|| encryption_error("content is not E2E ciphertext"))
+}
+
+/// Key id of encrypted content
+pub fn content_key_id(content: &str) -> Option<&str> {
+    split_content(content).ok().map(|(key_id, _)| key_id)
+}
+
+/// Long-term P-256 identity key used to receive room keys
+pub struct IdentityKey {
+    secret: SecretKey,
+}
+
+impl IdentityKey {
+    /// Generate a fresh identity key
+    pub fn generate() -> Self {
+        Self { secret: SecretKey::random(&mut OsRng) }
+    }
+
+    /// Restore a key saved with `to_bytes`; keeping the identity across connections keeps peers' pins valid
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        SecretKey::from_slice(bytes).map(|secret| Self { secret }).map_err(|_| encryption_error("invalid identity key"))
+    }
+
+    /// Secret scalar, for persisting the identity
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.secret.to_bytes().into()
+    }
+
+    /// Public key as base64 SEC1 uncompressed point (WebCrypto "raw" format)
+    pub fn public_key(&self) -> String {
+        general_purpose::STANDARD.encode(self.secret.public_key().to_encoded_point(false).as_bytes())
+    }
+
+    // ECDH with the peer, then HKDF-SHA256 into an AES-256-GCM wrapping key
+    fn wrapping_cipher(&self, peer_public_key: &str) -> Result<Aes256Gcm> {
+        let peer = PublicKey::from_sec1_bytes(&decode_base64(peer_public_key)?)
+            .map_err(|_| encryption_error("invalid peer public key"))?;
+        let shared = p256::ecdh::diffie_hellman(self.secret.to_nonzero_scalar(), peer.as_affine());
+        let mut okm = [0u8; 32];
+        Hkdf::<Sha256>::new(None, shared.raw_secret_bytes())
+            .expand(ROOM_KEY_INFO, &mut okm)
+            .map_err(|_| encryption_error("key derivation failed"))?;
+        Ok(Aes256Gcm::new_from_slice(&okm).expect("derived key is 32 bytes"))
+    }
+
+    /// Seal a room key from user `from` (this identity) to user `to`
+    pub fn seal_room_key(&self, from: u64, to: u64, peer_public_key: &str, room_key: &RoomKey) -> Result<String> {
+        let sealed = seal(&self.wrapping_cipher(peer_public_key)?, &room_key.key, &share_aad(from, to))?;
+        Ok(general_purpose::STANDARD.encode(sealed))
+    }
+
+    /// Open a room key sealed by user `from` to user `to` (this identity)
+    pub fn open_room_key(&self, from: u64, to: u64, peer_public_key: &str, sealed_key: &str) -> Result<RoomKey> {
+        let key = open(&self.wrapping_cipher(peer_public_key)?, &decode_base64(sealed_key)?, &share_aad(from, to))?;
+        let key: [u8; 32] = key.try_into().map_err(|_| encryption_error("room key must be 32 bytes"))?;
+        Ok(RoomKey::from_bytes(key))
+    }
+}
+
+fn share_aad(from: u64, to: u64) -> [u8; 16] {
+    let mut aad = [0u8; 16];
+    aad[..8].copy_from_slice(&from.to_le_bytes());
+    aad[8..].copy_from_slice(&to.to_le_bytes());
+    aad
+}
+
+/// Client-side key agreement state: peer identity keys and known room keys.
+/// A room key is generated on first send when none is known, and shared with every peer;
+/// newcomers receive the current key from members who hold it, and the key is rotated when a member leaves.
+/// Peer identity keys are pinned on first use: a different key for a pinned user is refused until `trust`ed.
+pub struct E2eSession {
+    user_id: u64,
+    identity: IdentityKey,
+    // Identity keys pinned on first use; kept when a peer leaves
+    pinned: HashMap<u64, String>,
+    // Peers currently in the room, who receive room keys
+    members: HashSet<u64>,
+    room_keys: HashMap<String, RoomKey>,
+    current_key_id: Option<String>,
+}
+
+impl E2eSession {
+    /// Create a session with a fresh identity key
+    pub fn new(user_id: u64) -> Self {
+        Self::with_identity(user_id, IdentityKey::generate())
+    }
+
+    /// Create a session with a saved identity key
+    pub fn with_identity(user_id: u64, identity: IdentityKey) -> Self {
+        Self {
+            user_id,
+            identity,
+            pinned: HashMap::new(),
+            members: HashSet::new(),
+            room_keys: HashMap::new(),
+            current_key_id: None,
+        }
+    }
+
+    /// Fingerprint of this client's identity key
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.identity.public_key()).expect("own key is valid base64")
+    }
+
+    /// Fingerprint of the key pinned for `user_id`, if any
+    pub fn peer_fingerprint(&self, user_id: u64) -> Option<String> {
+        self.pinned.get(&user_id).and_then(|key| fingerprint(key).ok())
+    }
+
+    /// Accept a new identity key for `user_id` after checking its fingerprint out of band
+    pub fn trust(&mut self, user_id: u64, public_key: String) {
+        self.pinned.insert(user_id, public_key);
+    }
+
+    // Pin on first use and add the peer to the room; a changed key is refused
+    fn admit(&mut self, user_id: u64, public_key: &str) -> Result<()> {
+        let pinned = self.pinned.entry(user_id).or_insert_with(|| public_key.to_string());
+        if pinned != public_key {
+            return Err(encryption_error(&format!(
+                "identity key of user {} changed (pinned {}, announced {})",
+                user_id,
+                fingerprint(pinned)?,
+                fingerprint(public_key).unwrap_or_else(|_| "invalid".into())
+            )));
+        }
+        self.members.insert(user_id);
+        Ok(())
+    }
+
+    /// Message announcing this client's identity key
+    pub fn announce(&self) -> ProtocolMessage {
+        ProtocolMessage::KeyAnnounce { user_id: self.user_id, public_key: self.identity.public_key() }
+    }
+
+    /// Record peer keys from the server's key directory; peers whose key changed are left out and reported
+    pub fn handle_directory(&mut self, keys: &[(u64, String)]) -> Result<()> {
+        let mut refused = Ok(());
+        for (user_id, public_key) in keys {
+            if *user_id != self.user_id {
+                if let Err(e) = self.admit(*user_id, public_key) {
+                    refused = Err(e);
+                }
+            }
+        }
+        refused
+    }
+
+    /// Record a newly announced peer and share the current room key with it
+    pub fn handle_announce(&mut self, user_id: u64, public_key: String) -> Result<Vec<ProtocolMessage>> {
+        if user_id == self.user_id {
+            return Ok(Vec::new());
+        }
+        self.admit(user_id, &public_key)?;
+        match self.current_room_key() {
+            Some(room_key) => Ok(vec![self.share_with(user_id, &room_key.clone())?]),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Open a room key shared with this client by a member; it becomes the key used for sending
+    pub fn handle_share(&mut self, from: u64, to: u64, sealed_key: &str) -> Result<()> {
+        if to != self.user_id {
+            return Ok(());
+        }
+        if !self.members.contains(&from) {
+            return Err(encryption_error("room key from unknown peer"));
+        }
+        let room_key = self.identity.open_room_key(from, to, &self.pinned[&from], sealed_key)?;
+        let key_id = room_key.key_id();
+        self.room_keys.insert(key_id.clone(), room_key);
+        self.current_key_id = Some(key_id);
+        Ok(())
+    }
+
+    /// Apply the server's list of online users. When members left, the remaining member with the
+    /// lowest id rotates the room key so they cannot read later messages; its shares are returned.
+    pub fn handle_members(&mut self, online: &[u64]) -> Result<Vec<ProtocolMessage>> {
+        let before = self.members.len();
+        self.members.retain(|member| online.contains(member));
+        let rotator = self.members.iter().copied().chain([self.user_id]).min();
+        if self.members.len() < before && self.current_key_id.is_some() && rotator == Some(self.user_id) {
+            return self.rotate();
+        }
+        Ok(Vec::new())
+    }
+
+    /// Encrypt content for the room; returns the ciphertext and any key shares to send first
+    pub fn encrypt(&mut self, plaintext: &str) -> Result<(String, Vec<ProtocolMessage>)> {
+        let mut shares = Vec::new();
+        if self.current_key_id.is_none() {
+            shares = self.rotate()?;
+        }
+        let room_key = self.current_room_key().expect("room key present after rotation");
+        Ok((room_key.encrypt(self.user_id, plaintext)?, shares))
+    }
+
+    /// Decrypt content from `sender_id`; plaintext content is returned unchanged
+    pub fn decrypt(&self, sender_id: u64, content: &str) -> Result<String> {
+        if !is_encrypted(content) {
+            return Ok(content.to_string());
+        }
+        let key_id = content_key_id(content).unwrap_or_default();
+        let room_key = self.room_keys.get(key_id).ok_or_else(|| encryption_error("unknown room key"))?;
+        room_key.decrypt(sender_id, content)
+    }
+
+    /// Generate a new room key and seal it to every member in the room
+    pub fn rotate(&mut self) -> Result<Vec<ProtocolMessage>> {
+        let room_key = RoomKey::generate();
+        let peers: Vec<u64> = self.members.iter().copied().collect();
+        let shares = peers.into_iter().map(|peer| self.share_with(peer, &room_key)).collect::<Result<Vec<_>>>()?;
+        let key_id = room_key.key_id();
+        self.room_keys.insert(key_id.clone(), room_key);
+        self.current_key_id = Some(key_id);
+        Ok(shares)
+    }
+
+    /// Room key used for sending, if one is known
+    pub fn current_room_key(&self) -> Option<&RoomKey> {
+        self.current_key_id.as_ref().and_then(|id| self.room_keys.get(id))
+    }
+
+    fn share_with(&self, peer: u64, room_key: &RoomKey) -> Result<ProtocolMessage> {
+        let public_key = self.pinned.get(&peer).ok_or_else(|| encryption_error("unknown peer"))?;
+        let sealed_key = self.identity.seal_room_key(self.user_id, peer, public_key, room_key)?;
+        Ok(ProtocolMessage::RoomKeyShare { from: self.user_id, to: peer, sealed_key })
+    }
+}
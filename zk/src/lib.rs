@@ -1,6 +1,17 @@
 pub mod zk;
 pub mod websocket;
 pub mod test_harness;
+/// End-to-end encryption of message content (room keys sealed pairwise with P-256 ECDH)
+pub mod e2e;
+pub mod registry;
+/// Prometheus metrics for proving, verification and connections
+pub mod metrics;
+/// Server configuration: TOML file, environment and command line
+pub mod config;
+/// Per-user message rate limiting
+pub mod rate_limit;
+/// TLS termination with certificate hot reload
+pub mod tls;
 
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -12,19 +23,27 @@ pub struct Message {
     pub sender_id: u64,
     pub content: String,
     pub timestamp: u64,
-    #[serde(with = "hex_serde")]
-    pub hash: [u8; 32],
+    pub hash: zk::Digest,
+    /// Hash `hash` was computed with; transcripts from before this field are the backend's default
+    #[serde(default)]
+    pub hash_id: zk::hasher::HashId,
 }
 
 impl Message {
-    /// Create a new message with hash
+    /// Create a new message hashed with the process default (see `zk::hasher::set_default_hash`)
     pub fn new(id: u64, sender_id: u64, content: String, timestamp: u64) -> Self {
+        Self::hashed_with(zk::hasher::default_hash(), id, sender_id, content, timestamp)
+    }
+
+    /// Create a new message hashed with a specific hash
+    pub fn hashed_with(hash_id: zk::hasher::HashId, id: u64, sender_id: u64, content: String, timestamp: u64) -> Self {
         let mut message = Self {
             id,
             sender_id,
             content,
             timestamp,
-            hash: [0u8; 32],
+            hash: zk::Digest::ZERO,
+            hash_id,
         };
         // Use ZK-friendly hash for consistency with proof system
         message.hash = message.compute_zk_hash();
@@ -32,13 +51,14 @@ impl Message {
     }
     
     /// Create a message with a pre-computed hash (for deserialization)
-    pub fn with_hash(id: u64, sender_id: u64, content: String, timestamp: u64, hash: [u8; 32]) -> Self {
+    pub fn with_hash(id: u64, sender_id: u64, content: String, timestamp: u64, hash: zk::Digest) -> Self {
         Self {
             id,
             sender_id,
             content,
             timestamp,
             hash,
+            hash_id: zk::hasher::default_hash(),
         }
     }
 
@@ -52,32 +72,16 @@ impl Message {
     }
     
     /// Compute ZK-friendly hash for STARK verification  
-    /// INDUSTRY PRODUCTION STANDARD: Uses authentic Poseidon with cryptographically secure parameters
-    /// Delegates to centralized zk::zk_hash for consistency across codebase
-    pub fn compute_zk_hash(&self) -> [u8; 32] {
-        use winterfell::math::fields::f128::BaseElement;
-        
-        // Convert message data to field elements
-        let id = BaseElement::from(self.id);
-        let sender = BaseElement::from(self.sender_id);
-        let timestamp = BaseElement::from(self.timestamp);
-        
-        let content_elements = zk::hash::pack_content(&self.content);
-        let mut all_inputs = vec![id, sender, timestamp];
-        all_inputs.extend_from_slice(&content_elements);
-        
-        // Use centralized production ZK hash function
-        let hash_result = zk::zk_hash(&all_inputs);
-        
-        // Convert to bytes
-        zk::elements_to_hash(&hash_result)
+    /// Uses the message's own `hash_id`, so old transcripts verify after the default changes
+    pub fn compute_zk_hash(&self) -> zk::Digest {
+        self.hash_id.hasher().message_hash(&zk::air::message_row(self))
     }
     
     // Removed unused alternative hash helpers; centralized hashing lives in `zk` module
 
     /// Verify the hash of this message using ZK-friendly hash
     pub fn verify_hash(&self) -> bool {
-        // Compute ZK Poseidon hash and compare directly. No legacy SHA debug.
+        // Compute ZK hash and compare directly. No legacy SHA debug.
         self.compute_zk_hash() == self.hash
     }
 }
@@ -91,7 +95,7 @@ impl fmt::Display for Message {
             self.sender_id,
             self.content,
             self.timestamp,
-            hex::encode(self.hash)
+            self.hash
         )
     }
 }
@@ -101,24 +105,62 @@ impl fmt::Display for Message {
 pub enum ZkChatError {
     #[error("Invalid message hash")]
     InvalidMessageHash,
+    #[error("Message hashed with {found}, but the chain uses {expected}")]
+    HashMismatch { expected: zk::hasher::HashId, found: zk::hasher::HashId },
+    #[error("Invalid digest: {0}")]
+    InvalidDigest(String),
     #[error("Invalid timestamp sequence")]
     InvalidTimestamp,
-        #[error("Duplicate message ID - replay attack detected")]
-        DuplicateMessageId,
+    #[error("Duplicate message ID - replay attack detected")]
+    DuplicateMessageId,
+    #[error("Sender {sender_id} skipped its sequence: expected message ID {expected}, found {found}")]
+    SequenceGap { sender_id: u64, expected: u64, found: u64 },
     #[error("Invalid sender ID")]
     InvalidSender,
     #[error("Proof verification failed")]
     ProofVerificationFailed,
     #[error("Proof generation error: {0}")]
     ProofGeneration(String),
+    #[error("Proof compression error: {0}")]
+    ProofCompression(String),
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+    #[error("Chain inconsistency: {0}")]
+    ChainInconsistency(String),
+    #[error("Invalid username: {0}")]
+    InvalidUsername(String),
+    #[error("Username already taken: {0}")]
+    UsernameTaken(String),
+    #[error("Unknown or deactivated user {0}")]
+    UnknownUser(u64),
+    #[error("User {0} is banned")]
+    UserBanned(u64),
+    #[error("Authentication failed for user {0}")]
+    AuthenticationFailed(u64),
+    #[error("Invalid public key")]
+    InvalidPublicKey,
+    #[error("Rate limit exceeded")]
+    RateLimited,
+    #[error("Configuration error: {0}")]
+    Config(String),
+    #[error("TLS error: {0}")]
+    Tls(String),
+    #[error("Server is shutting down")]
+    ShuttingDown,
     #[error("WebSocket error: {0}")]
-    WebSocket(#[from] tungstenite::Error),
+    WebSocket(Box<tungstenite::Error>),
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 }
 
+impl From<tungstenite::Error> for ZkChatError {
+    fn from(error: tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(error))
+    }
+}
+
 pub type Result<T> = std::result::Result<T, ZkChatError>;
 
 // Helper module for hex serialization of byte arrays
@@ -145,4 +187,24 @@ mod hex_serde {
         result.copy_from_slice(&bytes);
         Ok(result)
     }
-}
\ No newline at end of file
+}
+
+// Helper module serializing u64 values as decimal strings (JavaScript numbers lose precision above 2^53)
+mod u64_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
@@ -5,5 +5,5 @@ fn main() {
     println!("To start the server, run:");
     println!("  cargo run --bin server");
     println!();
-    println!("Then open your browser to http://127.0.0.1:8080");
+    println!("Then open your browser to http://127.0.0.1:8081");
 }
@@ -0,0 +1,117 @@
+use crate::{websocket::ProtocolMessage, ZkChatError};
+use once_cell::sync::Lazy;
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+
+// Proving takes from tens of milliseconds (one message) to tens of seconds (large batches)
+const PROVE_BUCKETS: &[f64] = &[0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+const VERIFY_BUCKETS: &[f64] = &[0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+/// Registry holding every zk-chat metric
+pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
+
+/// Duration of `MessageProver` proof generation
+pub static PROVE_DURATION: Lazy<Histogram> = Lazy::new(|| {
+    register(Histogram::with_opts(
+        HistogramOpts::new("zk_chat_prove_duration_seconds", "Time spent generating a STARK proof").buckets(PROVE_BUCKETS.to_vec()),
+    ))
+});
+
+/// Duration of `verify_proof`
+pub static VERIFY_DURATION: Lazy<Histogram> = Lazy::new(|| {
+    register(Histogram::with_opts(
+        HistogramOpts::new("zk_chat_verify_duration_seconds", "Time spent verifying a STARK proof").buckets(VERIFY_BUCKETS.to_vec()),
+    ))
+});
+
+/// Broadcast messages by verification status and reason
+pub static BROADCASTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("zk_chat_broadcasts_total", "Messages broadcast to clients"),
+        &["verified", "reason"],
+    ))
+});
+
+/// Error messages sent to clients, by error code
+pub static PROTOCOL_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register(IntCounterVec::new(
+        Opts::new("zk_chat_protocol_errors_total", "ProtocolMessage::Error replies sent to clients"),
+        &["code"],
+    ))
+});
+
+/// Users currently joined
+pub static ONLINE_USERS: Lazy<IntGauge> = Lazy::new(|| {
+    register(IntGauge::new("zk_chat_online_users", "Users currently joined"))
+});
+
+/// Messages in the current chain
+pub static CHAIN_LENGTH: Lazy<IntGauge> = Lazy::new(|| {
+    register(IntGauge::new("zk_chat_chain_length", "Messages in the current chain epoch"))
+});
+
+/// Broadcasts queued but not yet received by every connection
+pub static BROADCAST_LAG: Lazy<IntGauge> = Lazy::new(|| {
+    register(IntGauge::new("zk_chat_broadcast_lag", "Broadcasts not yet received by the slowest connection"))
+});
+
+/// Broadcasts dropped because a connection fell too far behind
+pub static BROADCASTS_DROPPED: Lazy<IntCounter> = Lazy::new(|| {
+    register(IntCounter::new("zk_chat_broadcasts_dropped_total", "Broadcasts skipped by lagging connections"))
+});
+
+/// Connections dropped by the idle timeout
+pub static IDLE_DISCONNECTS: Lazy<IntCounter> = Lazy::new(|| {
+    register(IntCounter::new("zk_chat_idle_disconnects_total", "Connections dropped after missing heartbeats"))
+});
+
+fn register<M: prometheus::core::Collector + Clone + 'static>(metric: prometheus::Result<M>) -> M {
+    let metric = metric.expect("valid metric definition");
+    REGISTRY.register(Box::new(metric.clone())).expect("metric registered once");
+    metric
+}
+
+/// Count a broadcast; `reason` is "ok" for verified messages
+pub fn record_broadcast(verified: bool, reason: &str) {
+    BROADCASTS.with_label_values(&[if verified { "true" } else { "false" }, reason]).inc();
+}
+
+/// Count an outgoing message if it is an error reply
+pub fn record_outgoing(message: &ProtocolMessage) {
+    if let ProtocolMessage::Error { code, .. } = message {
+        PROTOCOL_ERRORS.with_label_values(&[&code.to_string()]).inc();
+    }
+}
+
+/// Short label describing why a message was not verified
+pub fn error_reason(error: &ZkChatError) -> &'static str {
+    match error {
+        ZkChatError::InvalidMessageHash => "invalid_hash",
+        ZkChatError::InvalidTimestamp => "invalid_timestamp",
+        ZkChatError::DuplicateMessageId => "duplicate_id",
+        ZkChatError::SequenceGap { .. } => "sequence_gap",
+        ZkChatError::InvalidSender => "invalid_sender",
+        ZkChatError::ProofVerificationFailed => "proof_failed",
+        ZkChatError::ProofGeneration(_) => "proof_generation",
+        _ => "other",
+    }
+}
+
+/// Render every metric in the Prometheus text format
+pub fn render() -> String {
+    // Touch each metric so it is exported even before its first observation
+    Lazy::force(&PROVE_DURATION);
+    Lazy::force(&VERIFY_DURATION);
+    Lazy::force(&BROADCASTS);
+    Lazy::force(&PROTOCOL_ERRORS);
+    Lazy::force(&ONLINE_USERS);
+    Lazy::force(&CHAIN_LENGTH);
+    Lazy::force(&BROADCAST_LAG);
+    Lazy::force(&BROADCASTS_DROPPED);
+    Lazy::force(&IDLE_DISCONNECTS);
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer) {
+        tracing::warn!("Failed to encode metrics: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
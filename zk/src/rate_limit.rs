@@ -0,0 +1,71 @@
+use crate::{Result, ZkChatError};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Instant;
+
+/// Token buckets per key (a user id by default): `burst` requests at once, refilled at `per_minute`
+#[derive(Debug, Clone)]
+pub struct RateLimiter<K = u64> {
+    per_minute: u32,
+    burst: u32,
+    buckets: HashMap<K, Bucket>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    /// Create a limiter; `per_minute = 0` allows everything
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        Self { per_minute, burst: burst.max(1), buckets: HashMap::new() }
+    }
+
+    /// Take one token for `key`, failing with `RateLimited` when the bucket is empty
+    pub fn check(&mut self, key: K) -> Result<()> {
+        self.check_at(key, Instant::now())
+    }
+
+    /// `check` at a given instant
+    pub fn check_at(&mut self, key: K, now: Instant) -> Result<()> {
+        if self.per_minute == 0 {
+            return Ok(());
+        }
+        let burst = f64::from(self.burst);
+        let bucket = self.buckets.entry(key).or_insert(Bucket { tokens: burst, refilled_at: now });
+        let elapsed = now.saturating_duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * f64::from(self.per_minute) / 60.0).min(burst);
+        bucket.refilled_at = now;
+        if bucket.tokens < 1.0 {
+            return Err(ZkChatError::RateLimited);
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Drop buckets that have refilled completely; a full bucket limits nothing, so reconnecting
+    /// cannot reset a key that is still rate limited
+    pub fn prune(&mut self) {
+        self.prune_at(Instant::now())
+    }
+
+    /// `prune` at a given instant
+    pub fn prune_at(&mut self, now: Instant) {
+        if self.per_minute == 0 {
+            self.buckets.clear();
+            return;
+        }
+        let refill_secs = f64::from(self.burst) * 60.0 / f64::from(self.per_minute);
+        self.buckets.retain(|_, bucket| {
+            let missing = f64::from(self.burst) - bucket.tokens;
+            now.saturating_duration_since(bucket.refilled_at).as_secs_f64() < refill_secs * missing / f64::from(self.burst)
+        });
+    }
+
+    /// Number of keys with a bucket that has not refilled yet (as of the last `check` or `prune`)
+    pub fn tracked(&self) -> usize {
+        self.buckets.len()
+    }
+}
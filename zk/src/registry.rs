@@ -0,0 +1,262 @@
+use crate::{Result, ZkChatError};
+use base64::{engine::general_purpose, Engine as _};
+use p256::ecdsa::{signature::{Signer, Verifier}, Signature, SigningKey, VerifyingKey};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Allowed username length, in characters
+pub const USERNAME_MIN_LEN: usize = 3;
+pub const USERNAME_MAX_LEN: usize = 32;
+
+/// Domain separator for signed join challenges
+const JOIN_SIGNATURE_DOMAIN: &[u8] = b"zk-chat join v1";
+
+/// A registered user; ids are allocated by the server and never reused
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisteredUser {
+    pub id: u64,
+    pub username: String,
+    // Account key that join challenges are verified against; users without one cannot join
+    pub public_key: Option<String>,
+    pub active: bool,
+    #[serde(default)]
+    pub banned: bool,
+    pub registered_at: u64,
+}
+
+// On-disk form of the registry
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RegistryFile {
+    next_id: u64,
+    users: Vec<RegisteredUser>,
+}
+
+/// Check a username: 3-32 ASCII letters, digits, '_', '-' or '.'; surrounding whitespace is trimmed
+pub fn validate_username(username: &str) -> Result<String> {
+    let username = username.trim();
+    let length = username.chars().count();
+    if !(USERNAME_MIN_LEN..=USERNAME_MAX_LEN).contains(&length) {
+        return Err(ZkChatError::InvalidUsername(format!(
+            "must be {}-{} characters", USERNAME_MIN_LEN, USERNAME_MAX_LEN
+        )));
+    }
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+        return Err(ZkChatError::InvalidUsername("only letters, digits, '_', '-' and '.' are allowed".into()));
+    }
+    Ok(username.to_string())
+}
+
+/// Parse a registered account key (base64 SEC1 P-256 point)
+pub fn parse_account_key(public_key: &str) -> Result<VerifyingKey> {
+    general_purpose::STANDARD.decode(public_key).ok()
+        .and_then(|bytes| VerifyingKey::from_sec1_bytes(&bytes).ok())
+        .ok_or(ZkChatError::InvalidPublicKey)
+}
+
+/// Fresh random nonce a joining client must sign, hex encoded
+pub fn join_challenge() -> String {
+    let mut nonce = [0u8; 32];
+    OsRng.fill_bytes(&mut nonce);
+    hex::encode(nonce)
+}
+
+// Domain || user id || nonce
+fn join_signing_bytes(user_id: u64, nonce: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(JOIN_SIGNATURE_DOMAIN.len() + 8 + nonce.len());
+    bytes.extend_from_slice(JOIN_SIGNATURE_DOMAIN);
+    bytes.extend_from_slice(&user_id.to_le_bytes());
+    bytes.extend_from_slice(nonce.as_bytes());
+    bytes
+}
+
+/// Check a join challenge signature against the user's registered key.
+/// Users without a registered key cannot authenticate.
+pub fn verify_join(user: &RegisteredUser, nonce: &str, signature: &str) -> Result<()> {
+    let key = parse_account_key(user.public_key.as_deref().ok_or(ZkChatError::AuthenticationFailed(user.id))?)?;
+    let signature = general_purpose::STANDARD.decode(signature).ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or(ZkChatError::AuthenticationFailed(user.id))?;
+    key.verify(&join_signing_bytes(user.id, nonce), &signature)
+        .map_err(|_| ZkChatError::AuthenticationFailed(user.id))
+}
+
+/// ECDSA P-256 account key a client registers with and signs join challenges with
+pub struct AccountKey {
+    key: SigningKey,
+}
+
+impl AccountKey {
+    /// Generate a fresh account key
+    pub fn generate() -> Self {
+        Self { key: SigningKey::random(&mut OsRng) }
+    }
+
+    /// Verifying key as base64 SEC1 uncompressed point, as passed to `register`
+    pub fn public_key(&self) -> String {
+        general_purpose::STANDARD.encode(self.key.verifying_key().to_encoded_point(false).as_bytes())
+    }
+
+    /// Sign the server's join challenge for `user_id`
+    pub fn sign_join(&self, user_id: u64, nonce: &str) -> String {
+        let signature: Signature = self.key.sign(&join_signing_bytes(user_id, nonce));
+        general_purpose::STANDARD.encode(signature.to_bytes())
+    }
+}
+
+/// Persistent registry of users with unique (case-insensitive) usernames.
+/// Every change is written through to the backing JSON file, if any.
+#[derive(Debug)]
+pub struct UserRegistry {
+    path: Option<PathBuf>,
+    next_id: u64,
+    users: BTreeMap<u64, RegisteredUser>,
+}
+
+impl UserRegistry {
+    /// Registry that is not persisted
+    pub fn in_memory() -> Self {
+        Self { path: None, next_id: 1, users: BTreeMap::new() }
+    }
+
+    /// Open the registry stored at `path`, starting empty if the file does not exist yet
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = if path.exists() {
+            serde_json::from_slice::<RegistryFile>(&std::fs::read(&path)?)?
+        } else {
+            RegistryFile::default()
+        };
+        let users: BTreeMap<u64, RegisteredUser> = file.users.into_iter().map(|u| (u.id, u)).collect();
+        let next_id = file.next_id.max(users.keys().next_back().map_or(1, |id| id + 1));
+        Ok(Self { path: Some(path), next_id, users })
+    }
+
+    /// Register a new user under a unique username
+    pub fn register(&mut self, username: &str, public_key: Option<String>) -> Result<RegisteredUser> {
+        let username = validate_username(username)?;
+        if let Some(public_key) = &public_key {
+            parse_account_key(public_key)?;
+        }
+        self.ensure_available(&username, None)?;
+        let user = RegisteredUser {
+            id: self.next_id,
+            username,
+            public_key,
+            active: true,
+            banned: false,
+            registered_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        };
+        let mut users = self.users.clone();
+        users.insert(user.id, user.clone());
+        self.commit(self.next_id + 1, users)?;
+        Ok(user)
+    }
+
+    /// Change a user's username
+    pub fn rename(&mut self, id: u64, username: &str) -> Result<RegisteredUser> {
+        let username = validate_username(username)?;
+        self.ensure_available(&username, Some(id))?;
+        self.update(id, |user| user.username = username)
+    }
+
+    /// Replace a user's account key
+    pub fn set_public_key(&mut self, id: u64, public_key: Option<String>) -> Result<RegisteredUser> {
+        if let Some(public_key) = &public_key {
+            parse_account_key(public_key)?;
+        }
+        self.update(id, |user| user.public_key = public_key)
+    }
+
+    /// Deactivate a user; the id and username stay reserved
+    pub fn deactivate(&mut self, id: u64) -> Result<RegisteredUser> {
+        self.update(id, |user| user.active = false)
+    }
+
+    /// Ban or unban a user; banned users cannot join until unbanned
+    pub fn set_banned(&mut self, id: u64, banned: bool) -> Result<RegisteredUser> {
+        self.update(id, |user| user.banned = banned)
+    }
+
+    /// Look up a user by id
+    pub fn get(&self, id: u64) -> Option<&RegisteredUser> {
+        self.users.get(&id)
+    }
+
+    /// Look up an active user by id, failing for unknown, deactivated or banned users
+    pub fn active_user(&self, id: u64) -> Result<&RegisteredUser> {
+        match self.users.get(&id).filter(|u| u.active) {
+            Some(user) if user.banned => Err(ZkChatError::UserBanned(id)),
+            Some(user) => Ok(user),
+            None => Err(ZkChatError::UnknownUser(id)),
+        }
+    }
+
+    /// Look up a user by username (case-insensitive)
+    pub fn find_by_username(&self, username: &str) -> Option<&RegisteredUser> {
+        let username = username.trim();
+        self.users.values().find(|u| u.username.eq_ignore_ascii_case(username))
+    }
+
+    /// All users, ordered by id
+    pub fn users(&self) -> impl Iterator<Item = &RegisteredUser> {
+        self.users.values()
+    }
+
+    /// Number of registered users, including deactivated ones
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    /// Check if no users are registered
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    /// Backing file, if persisted
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn ensure_available(&self, username: &str, except: Option<u64>) -> Result<()> {
+        match self.find_by_username(username) {
+            Some(existing) if Some(existing.id) != except => Err(ZkChatError::UsernameTaken(username.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    // Change one user on a copy of the registry and commit it
+    fn update(&mut self, id: u64, change: impl FnOnce(&mut RegisteredUser)) -> Result<RegisteredUser> {
+        let mut users = self.users.clone();
+        let user = users.get_mut(&id).ok_or(ZkChatError::UnknownUser(id))?;
+        change(user);
+        let user = user.clone();
+        self.commit(self.next_id, users)?;
+        Ok(user)
+    }
+
+    // Persist the new state first and adopt it only once it is saved, so a failed write changes nothing
+    fn commit(&mut self, next_id: u64, users: BTreeMap<u64, RegisteredUser>) -> Result<()> {
+        self.save(next_id, &users)?;
+        self.next_id = next_id;
+        self.users = users;
+        Ok(())
+    }
+
+    // Write to a temporary file and rename it over the registry so a crash never leaves it half written
+    fn save(&self, next_id: u64, users: &BTreeMap<u64, RegisteredUser>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = RegistryFile { next_id, users: users.values().cloned().collect() };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&file)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
-use crate::{Message, zk::{MessageChain, air::{PublicInputs, build_trace, MessageAir}, prover::{MessageProver, verify_proof}, elements_to_hash}};
+use crate::{Message, zk::{MessageChain, air::{self, PublicInputs, build_trace, GlobalId, MessageAir, BLOCK_LENGTH, CHAIN_HASH}, prover::{MessageProver, ProofProfile, verify_proof}, session_salt, Digest}};
 use serde::Serialize;
 use std::time::Instant;
-use winterfell::{math::{fields::f128::BaseElement, FieldElement}, Air};
+use crate::zk::field::{element, BaseElement, FIELD_EXTENSION};
+use winterfell::{math::FieldElement, Air};
 
 #[derive(Debug, Serialize, Clone)]
 pub struct TestResult {
@@ -24,7 +25,7 @@ pub struct TestCase {
 }
 
 fn unit_zk_hash_deterministic() -> Result<(), String> {
-    let inputs: Vec<BaseElement> = vec![1u64,2,3,4,5].into_iter().map(BaseElement::from).collect();
+    let inputs: Vec<BaseElement> = vec![1u64,2,3,4,5].into_iter().map(element).collect();
     let h1 = crate::zk::zk_hash(&inputs);
     let h2 = crate::zk::zk_hash(&inputs);
     if h1 != h2 { return Err("Poseidon hash not deterministic".into()); }
@@ -50,37 +51,36 @@ fn unit_chain_updates() -> Result<(), String> {
 
 fn integration_prover_single_message() -> Result<(), String> {
     let m = Message::new(1, 42, "zk".into(), 1000);
-    let trace = build_trace(&[m.clone()]);
-    let mut final_hash_elements = [winterfell::math::fields::f128::BaseElement::ZERO; 4];
-    for i in 0..4 { final_hash_elements[i] = trace[4 + i][0]; }
-    let final_hash = elements_to_hash(&final_hash_elements);
-    let pub_inputs = PublicInputs { initial_hash: [0u8;32], final_hash, message_count: 1 };
+    let trace = build_trace(std::slice::from_ref(&m));
+    let mut final_hash_elements = [BaseElement::ZERO; 4];
+    for i in 0..4 { final_hash_elements[i] = trace[CHAIN_HASH + i][BLOCK_LENGTH - 1]; }
+    let final_hash = Digest::new(final_hash_elements);
+    let pub_inputs = PublicInputs { initial_hash: Digest::ZERO, initial_timestamp: 0, final_hash, message_count: 1, message_root: m.hash, hash_id: m.hash_id, final_timestamp: m.timestamp, salt: session_salt(), message_index: vec![GlobalId::of(&m)] };
     let mut prover = MessageProver::new();
-    let proof = prover.prove(&[m.clone()]).map_err(|e| format!("Proof generation failed: {e}"))?;
-    verify_proof(&proof, pub_inputs).map_err(|e| format!("Proof verification failed: {e}"))?;
+    let proof = prover.prove(std::slice::from_ref(&m)).map_err(|e| format!("Proof generation failed: {e}"))?;
+    verify_proof(&proof, pub_inputs, ProofProfile::Standard).map_err(|e| format!("Proof verification failed: {e}"))?;
     Ok(())
 }
 
 fn integration_air_assertions() -> Result<(), String> {
-    use winterfell::{ProofOptions, FieldExtension};
-    use winterfell::TraceInfo;
+    use winterfell::ProofOptions;
     let m = Message::new(1, 1, "x".into(), 1000);
-    let trace = build_trace(&[m.clone()]);
+    let trace = build_trace(std::slice::from_ref(&m));
     let mut final_hash_elements = [BaseElement::ZERO; 4];
-    for i in 0..4 { final_hash_elements[i] = trace[4 + i][0]; }
-    let final_hash = elements_to_hash(&final_hash_elements);
-    let pub_inputs = PublicInputs { initial_hash: [0u8;32], final_hash, message_count: 1 };
-    let trace_info = TraceInfo::new(crate::zk::air::TRACE_WIDTH, 8);
-    let options = ProofOptions::new(32,8,0, FieldExtension::None,8,31);
+    for i in 0..4 { final_hash_elements[i] = trace[CHAIN_HASH + i][BLOCK_LENGTH - 1]; }
+    let final_hash = Digest::new(final_hash_elements);
+    let pub_inputs = PublicInputs { initial_hash: Digest::ZERO, initial_timestamp: 0, final_hash, message_count: 1, message_root: m.hash, hash_id: m.hash_id, final_timestamp: m.timestamp, salt: session_salt(), message_index: vec![GlobalId::of(&m)] };
+    let trace_info = air::trace_info(2 * BLOCK_LENGTH);
+    let options = ProofOptions::new(32,8,0, FIELD_EXTENSION,8,31);
     let air = MessageAir::new(trace_info, pub_inputs.clone(), options);
     let assertions = air.get_assertions();
-    if assertions.len() != 8 { return Err(format!("Expected 8 assertions, got {}", assertions.len())); }
+    if assertions.len() != 12 { return Err(format!("Expected 12 assertions, got {}", assertions.len())); }
     Ok(())
 }
 
 fn e2e_three_message_chain() -> Result<(), String> {
     let mut chain = MessageChain::new();
-    for i in 0..3 { chain.add_message(Message::new(i+1, 50+i as u64, format!("msg{i}"), 2000+i as u64)).map_err(|e| e.to_string())?; }
+    for i in 0..3 { chain.add_message(Message::new(i+1, 50+i, format!("msg{i}"), 2000+i)).map_err(|e| e.to_string())?; }
     if chain.len() != 3 { return Err("Chain length mismatch".into()); }
     Ok(())
 }
@@ -114,7 +114,7 @@ fn negative_non_monotonic_timestamp() -> Result<(), String> {
 
 fn negative_tampered_hash() -> Result<(), String> {
     let mut msg = Message::new(1, 1, "tamper".into(), 1000);
-    msg.hash[0] ^= 0xFF; // corrupt
+    msg.hash = Digest::new(msg.hash.elements().map(|e| e + BaseElement::ONE)); // corrupt
     if msg.verify_hash() { return Err("Tampered hash passed verification".into()); }
     Ok(())
 }
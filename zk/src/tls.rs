@@ -0,0 +1,142 @@
+use crate::{Result, ZkChatError};
+use futures_util::Stream;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{rustls, server::TlsStream, TlsAcceptor};
+use tracing::{info, warn};
+
+// A client that has not finished its handshake by then is dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// TLS server settings loaded from PEM files, reloaded when the files change
+#[derive(Debug, Clone)]
+pub struct TlsReloader {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: Arc<RwLock<Arc<rustls::ServerConfig>>>,
+}
+
+impl TlsReloader {
+    /// Load a PEM certificate chain and private key (PKCS#8, PKCS#1 or SEC1)
+    pub fn load(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Result<Self> {
+        let cert_path = cert_path.into();
+        let key_path = key_path.into();
+        let config = load_server_config(&cert_path, &key_path)?;
+        Ok(Self { cert_path, key_path, current: Arc::new(RwLock::new(config)) })
+    }
+
+    /// Re-read the certificate and key; on failure the previous ones stay in use
+    pub fn reload(&self) -> Result<()> {
+        let config = load_server_config(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap_or_else(|p| p.into_inner()) = config;
+        Ok(())
+    }
+
+    /// rustls settings with the certificate that is current right now
+    pub fn server_config(&self) -> Arc<rustls::ServerConfig> {
+        self.current.read().unwrap_or_else(|p| p.into_inner()).clone()
+    }
+
+    /// Acceptor using the certificate that is current right now
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.server_config())
+    }
+
+    /// Poll the certificate and key files every `interval` and reload when either changes
+    pub fn watch(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let reloader = self.clone();
+        tokio::spawn(async move {
+            let mut seen = reloader.modified();
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let modified = reloader.modified();
+                if modified == seen {
+                    continue;
+                }
+                seen = modified;
+                match reloader.reload() {
+                    Ok(()) => info!("🔐 Reloaded TLS certificate from {}", reloader.cert_path.display()),
+                    Err(e) => warn!("Keeping previous TLS certificate: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Complete the TLS handshake on an accepted connection
+    pub async fn accept(&self, stream: TcpStream) -> Result<TlsStream<TcpStream>> {
+        let handshake = self.acceptor().accept(stream);
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(ZkChatError::Tls("handshake timed out".into())),
+        }
+    }
+
+    /// Accept connections from `listener` and yield them once their handshakes complete.
+    /// Handshakes run concurrently, so a slow client does not hold up the others.
+    pub fn incoming(&self, listener: TcpListener) -> impl Stream<Item = std::io::Result<TlsStream<TcpStream>>> {
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        let reloader = self.clone();
+        tokio::spawn(async move {
+            loop {
+                // Stop listening once the server drops the stream (e.g. on graceful shutdown)
+                let accepted = tokio::select! {
+                    accepted = listener.accept() => accepted,
+                    _ = tx.closed() => break,
+                };
+                let (stream, peer_addr) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Failed to accept connection: {}", e);
+                        continue;
+                    }
+                };
+                let reloader = reloader.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match reloader.accept(stream).await {
+                        Ok(tls_stream) => {
+                            let _ = tx.send(Ok(tls_stream)).await;
+                        }
+                        Err(e) => warn!("TLS handshake with {} failed: {}", peer_addr, e),
+                    }
+                });
+            }
+        });
+        futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|item| (item, rx)) })
+    }
+
+    fn modified(&self) -> (Option<SystemTime>, Option<SystemTime>) {
+        let mtime = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        (mtime(&self.cert_path), mtime(&self.key_path))
+    }
+}
+
+fn load_server_config(cert_path: &Path, key_path: &Path) -> Result<Arc<rustls::ServerConfig>> {
+    let open = |path: &Path| {
+        std::fs::File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| ZkChatError::Tls(format!("{}: {}", path.display(), e)))
+    };
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(|e| ZkChatError::Tls(format!("{}: {}", cert_path.display(), e)))?;
+    if certs.is_empty() {
+        return Err(ZkChatError::Tls(format!("{}: no certificates found", cert_path.display())));
+    }
+    let key = rustls_pemfile::private_key(&mut open(key_path)?)
+        .map_err(|e| ZkChatError::Tls(format!("{}: {}", key_path.display(), e)))?
+        .ok_or_else(|| ZkChatError::Tls(format!("{}: no private key found", key_path.display())))?;
+
+    let mut config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| ZkChatError::Tls(e.to_string()))?;
+    // WebSocket upgrades need HTTP/1.1
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
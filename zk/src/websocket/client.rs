@@ -1,6 +1,8 @@
 use crate::{
+    e2e::{E2eSession, IdentityKey},
+    registry::AccountKey,
     websocket::ProtocolMessage,
-    zk::prover::MessageProver,
+    zk::{compression::CachedProof, consistency::{ChainMirror, CheckpointVerifier, ForkMonitor, HeadSigner, SignedCheckpoint}, prover::{MessageProver, ProofProfile}},
     Message, Result,
 };
 use futures_util::{SinkExt, StreamExt};
@@ -14,21 +16,62 @@ use tracing::{error, info, warn};
 pub struct ChatClient {
     user_id: u64,
     username: String,
+    // Registered account key, answering the server's join challenge
+    account_key: AccountKey,
     prover: MessageProver,
     message_counter: u64,
+    e2e: E2eSession,
+    // Local mirror of the server's chain, anchored by the `ChainState` received on join
+    mirror: Option<ChainMirror>,
+    // Verified broadcasts awaiting the `ChainProof` that covers them
+    pending_batch: Vec<Message>,
+    head_signer: HeadSigner,
+    forks: ForkMonitor,
+    // Server checkpoints are verified once the server's public key is pinned
+    checkpoints: Option<CheckpointVerifier>,
+    // Weakest proof parameters accepted for the server's chain proofs
+    min_profile: ProofProfile,
 }
 
 impl ChatClient {
-    /// Create a new chat client
-    pub fn new(user_id: u64, username: String) -> Self {
+    /// Create a new chat client for a user id registered with `account_key` (see `ProtocolMessage::Register`)
+    pub fn new(user_id: u64, username: String, account_key: AccountKey) -> Self {
         Self {
             user_id,
             username,
+            account_key,
             prover: MessageProver::new(),
             message_counter: 0,
+            e2e: E2eSession::new(user_id),
+            mirror: None,
+            pending_batch: Vec::new(),
+            head_signer: HeadSigner::generate(),
+            forks: ForkMonitor::new(),
+            checkpoints: None,
+            min_profile: ProofProfile::default(),
         }
     }
 
+    /// Use a saved E2E identity key, so peers' pins of it stay valid across connections
+    pub fn use_identity_key(&mut self, identity: IdentityKey) {
+        self.e2e = E2eSession::with_identity(self.user_id, identity);
+    }
+
+    /// Fingerprint of our E2E identity key, for members to compare out of band
+    pub fn fingerprint(&self) -> String {
+        self.e2e.fingerprint()
+    }
+
+    /// Pin the server's checkpoint signing key (from `/api/server-key`, obtained out of band)
+    pub fn pin_server_key(&mut self, public_key: impl Into<String>) {
+        self.checkpoints = Some(CheckpointVerifier::new(public_key));
+    }
+
+    /// Accept chain proofs only if made with `profile`'s options or stronger ones
+    pub fn require_profile(&mut self, profile: ProofProfile) {
+        self.min_profile = profile;
+    }
+
     /// Connect to the chat server and start the client
     pub async fn connect(&mut self, server_url: &str) -> Result<()> {
         let (ws_stream, _) = connect_async(server_url).await?;
@@ -63,7 +106,12 @@ impl ChatClient {
                 Ok(WsMessage::Text(text)) => {
                     match ProtocolMessage::from_bytes(text.as_bytes()) {
                         Ok(protocol_msg) => {
-                            self.handle_server_message(protocol_msg).await;
+                            for reply in self.handle_server_message(protocol_msg).await {
+                                let reply_bytes = reply.to_bytes()?;
+                                if let Err(e) = ws_sender.send(WsMessage::Text(String::from_utf8_lossy(&reply_bytes).to_string())).await {
+                                    error!("Failed to send reply: {}", e);
+                                }
+                            }
                         }
                         Err(e) => {
                             warn!("Failed to parse server message: {}", e);
@@ -90,27 +138,103 @@ impl ChatClient {
         Ok(())
     }
 
-    /// Handle messages from the server
-    async fn handle_server_message(&self, msg: ProtocolMessage) {
+    /// Handle messages from the server, returning any replies to send
+    async fn handle_server_message(&mut self, msg: ProtocolMessage) -> Vec<ProtocolMessage> {
         match msg {
-            ProtocolMessage::MessageBroadcast { message, verified, local_id } => {
+            ProtocolMessage::JoinChallenge { nonce } => {
+                // Prove we hold the registered account key, then publish our E2E identity key
+                // so members can share the room key with us
+                let signature = self.account_key.sign_join(self.user_id, &nonce);
+                return vec![ProtocolMessage::JoinProof { signature }, self.e2e.announce()];
+            }
+            ProtocolMessage::MessageBroadcast { message, verified, local_id, .. } => {
+                if verified {
+                    self.pending_batch.push(message.clone());
+                }
                 let verification_status = if verified { "✓" } else { "✗" };
+                let content = self.e2e.decrypt(message.sender_id, &message.content)
+                    .unwrap_or_else(|e| format!("<undecryptable: {}>", e));
                 println!(
                     "[{}] user={} local#{} msg_id={} :: {} {}",
                     verification_status,
                     message.sender_id,
                     local_id,
                     message.id,
-                    message.content,
+                    content,
                     format_timestamp(message.timestamp)
                 );
             }
+            ProtocolMessage::ChainProof { start_count, initial_hash, initial_timestamp, message_count, final_hash, message_root, proof } => {
+                let proof = CachedProof { start_count, initial_hash, initial_timestamp, message_count, final_hash, message_root, proof };
+                return self.check_chain_proof(proof);
+            }
+            ProtocolMessage::ChainState { salt, message_count, chain_hash } => {
+                // A different salt mid-session means the server rotated to a new epoch; checkpoints
+                // follow only once the server signs the new epoch's empty chain
+                if self.mirror.as_ref().is_some_and(|mirror| mirror.salt() != salt) {
+                    info!("Server started a new epoch (salt {})", salt);
+                    self.forks.new_epoch();
+                }
+                self.mirror = Some(ChainMirror::resume(salt, message_count, chain_hash));
+                self.pending_batch.clear();
+            }
+            ProtocolMessage::Checkpoint { message_count, chain_hash, message_root, salt, proof_ref, signature } => {
+                let checkpoint = SignedCheckpoint { message_count, chain_hash, message_root, salt, proof_ref, signature };
+                self.check_checkpoint(&checkpoint);
+            }
+            ProtocolMessage::HeadGossip { head } => {
+                match self.forks.observe(head) {
+                    Ok(Some(evidence)) => {
+                        println!(
+                            "⚠️ FORK DETECTED at {} messages: user {} saw {}, user {} saw {}",
+                            evidence.first.message_count,
+                            evidence.first.user_id, evidence.first.chain_hash,
+                            evidence.second.user_id, evidence.second.chain_hash
+                        );
+                        return vec![ProtocolMessage::ForkAlert { evidence }];
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Rejected head gossip: {}", e),
+                }
+            }
+            ProtocolMessage::ForkAlert { evidence } => {
+                if evidence.verify() {
+                    println!(
+                        "⚠️ Fork reported at {} messages: users {} and {} saw different chain heads",
+                        evidence.first.message_count, evidence.first.user_id, evidence.second.user_id
+                    );
+                }
+            }
+            ProtocolMessage::KeyDirectory { keys } => {
+                if let Err(e) = self.e2e.handle_directory(&keys) {
+                    println!("⚠️ {}", e);
+                }
+            }
+            ProtocolMessage::KeyAnnounce { user_id, public_key } => {
+                match self.e2e.handle_announce(user_id, public_key) {
+                    Ok(shares) => return shares,
+                    Err(e) => println!("⚠️ Not sharing the room key with user {}: {}", user_id, e),
+                }
+            }
+            ProtocolMessage::RoomKeyShare { from, to, sealed_key } => {
+                if let Err(e) = self.e2e.handle_share(from, to, &sealed_key) {
+                    warn!("Failed to open room key from user {}: {}", from, e);
+                }
+            }
             ProtocolMessage::UserListUpdate { users } => {
                 println!("Users online: {:?}", users);
+                let online: Vec<u64> = users.iter().map(|(id, _)| *id).collect();
+                match self.e2e.handle_members(&online) {
+                    Ok(shares) => return shares,
+                    Err(e) => warn!("Failed to rotate the room key: {}", e),
+                }
             }
             ProtocolMessage::Error { code, message } => {
                 println!("Error {}: {}", code, message);
             }
+            ProtocolMessage::ServerShutdown { reason } => {
+                println!("Server shutting down: {}", reason);
+            }
             ProtocolMessage::Pong => {
                 // Handle pong if needed for keep-alive
             }
@@ -118,18 +242,77 @@ impl ChatClient {
                 warn!("Unhandled server message: {:?}", msg);
             }
         }
+        Vec::new()
+    }
+
+    /// Verify a chain proof against the mirror's own public inputs for the batch, then gossip the signed head
+    fn check_chain_proof(&mut self, proof: CachedProof) -> Vec<ProtocolMessage> {
+        let Some(mirror) = self.mirror.as_mut() else {
+            return Vec::new();
+        };
+        let batch = std::mem::take(&mut self.pending_batch);
+        let (message_count, final_hash) = (proof.message_count, proof.final_hash);
+        if message_count <= mirror.message_count() {
+            // Already covered by the head we joined at
+            return Vec::new();
+        }
+        let reason = if proof.start_count > mirror.message_count() {
+            // Missed broadcasts: the server's head cannot be derived from here, so stop mirroring
+            // rather than adopting it; rejoining re-anchors the mirror
+            let reason = format!("missed messages {}..{}", mirror.message_count() + 1, proof.start_count + 1);
+            self.mirror = None;
+            reason
+        } else if let Err(e) = mirror.apply_proven_batch(&batch, &proof, self.min_profile) {
+            e.to_string()
+        } else {
+            println!("Chain proof verified for {} messages (head {}, {} bytes)", message_count, final_hash, proof.proof.len());
+            return self.gossip_head();
+        };
+        println!("⚠️ Chain head {} at {} messages not signed: {}", final_hash, message_count, reason);
+        vec![ProtocolMessage::ChainMismatch { message_count, chain_hash: final_hash, reason }]
+    }
+
+    /// Sign the head the mirror derived itself and gossip it, with fork evidence if it contradicts another
+    fn gossip_head(&mut self) -> Vec<ProtocolMessage> {
+        let Some(mirror) = self.mirror.as_ref() else {
+            return Vec::new();
+        };
+        let head = self.head_signer.sign(self.user_id, mirror.salt(), mirror.message_count(), mirror.head());
+        match self.forks.observe(head.clone()) {
+            Ok(Some(evidence)) => vec![ProtocolMessage::ForkAlert { evidence }, ProtocolMessage::HeadGossip { head }],
+            _ => vec![ProtocolMessage::HeadGossip { head }],
+        }
+    }
+
+    /// Verify a server checkpoint against the pinned key and the local mirror
+    fn check_checkpoint(&mut self, checkpoint: &SignedCheckpoint) {
+        let Some(verifier) = self.checkpoints.as_mut() else {
+            warn!("Ignoring checkpoint at {} messages: no pinned server key", checkpoint.message_count);
+            return;
+        };
+        if let Err(e) = verifier.check(checkpoint) {
+            println!("⚠️ Server checkpoint rejected: {}", e);
+            return;
+        }
+        if let Some(mirror) = &self.mirror {
+            if mirror.message_count() == checkpoint.message_count && mirror.head() != checkpoint.chain_hash {
+                println!("⚠️ Server signed head {} but our mirror holds {}", checkpoint.chain_hash, mirror.head());
+            }
+        }
     }
 
-    /// Send a message to the server
-    pub fn send_message(&mut self, content: &str) -> Result<ProtocolMessage> {
+    /// Encrypt a message for the room; returns any room key shares to send before the message itself
+    pub fn send_message(&mut self, content: &str) -> Result<Vec<ProtocolMessage>> {
+        let (ciphertext, mut outgoing) = self.e2e.encrypt(content)?;
         let (message, proof) = create_message_with_proof(
             self.user_id, 
-            content, 
+            &ciphertext, 
             &mut self.message_counter, 
             &mut self.prover
         )?;
         
-        Ok(ProtocolMessage::SendMessage { message, proof })
+        outgoing.push(ProtocolMessage::SendMessage { message, proof });
+        Ok(outgoing)
     }
 }
 
@@ -159,6 +342,6 @@ fn create_message_with_proof(
 fn format_timestamp(timestamp: u64) -> String {
     use chrono::{DateTime, Utc};
     let dt = DateTime::from_timestamp(timestamp as i64, 0)
-        .unwrap_or_else(|| Utc::now());
+        .unwrap_or_else(Utc::now);
     dt.format("%H:%M:%S").to_string()
 }
\ No newline at end of file
@@ -1,11 +1,33 @@
-use crate::{Message, Result, ZkChatError};
+use crate::{Message, Result, ZkChatError, registry::RegisteredUser, zk::{compression::CachedProof, consistency::{ForkEvidence, SignedCheckpoint, SignedHead}, Digest}};
 use serde::{Deserialize, Serialize};
 
 /// Protocol messages for WebSocket communication
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ProtocolMessage {
-    /// Client joins the chat room
+    /// Client registers a new account with its account key; the server allocates its id
+    Register {
+        username: String,
+        #[serde(default)]
+        public_key: Option<String>,
+    },
+
+    /// Joined client changes its username
+    Rename { username: String },
+
+    /// Joined client deactivates its own account
+    Deactivate,
+
+    /// Server replies to `Register`, `Rename` and `Deactivate` with the stored record
+    Registered { user: RegisteredUser },
+
+    /// Client joins the chat room with a registered id; the server uses the registered username
     Join { user_id: u64, username: String },
+
+    /// Server answers `Join` with a nonce to sign with the registered account key
+    JoinChallenge { nonce: String },
+
+    /// Client answers `JoinChallenge` with its signature (base64 r || s); the join completes once it verifies
+    JoinProof { signature: String },
     
     /// Client leaves the chat room
     Leave { user_id: u64 },
@@ -22,8 +44,67 @@ pub enum ProtocolMessage {
         verified: bool,
         // Per-sender local sequential ID (starts at 1 per sender)
         local_id: u64,
+        // Shared batch proof covering this message: the `message_count` of its `ChainProof`
+        #[serde(default)]
+        proof_ref: Option<usize>,
+    },
+    
+    /// Server publishes the latest compressed chain proof (once per batch of messages): it proves the
+    /// segment from `start_count` messages (chain hash `initial_hash`, last timestamp `initial_timestamp`)
+    /// to `message_count` messages
+    ChainProof {
+        #[serde(default)]
+        start_count: usize,
+        #[serde(default)]
+        initial_hash: Digest,
+        #[serde(default)]
+        initial_timestamp: u64,
+        message_count: usize,
+        final_hash: Digest,
+        message_root: Digest,
+        proof: Vec<u8>, // Headered, compressed StarkProof (see zk::compression)
+    },
+    
+    /// Client publishes its E2E identity key (base64 SEC1 P-256 point); relayed to all clients
+    KeyAnnounce { user_id: u64, public_key: String },
+
+    /// Server replies to `KeyAnnounce` with every known identity key
+    KeyDirectory { keys: Vec<(u64, String)> },
+
+    /// Room key sealed from one client to another; the server relays it without being able to open it
+    RoomKeyShare { from: u64, to: u64, sealed_key: String },
+    
+    /// Server tells a joining client where the chain stands, so it can mirror it from here
+    ChainState {
+        #[serde(with = "crate::u64_string")]
+        salt: u64, // session salt bound into every chain step
+        message_count: usize,
+        chain_hash: Digest,
+    },
+
+    /// Client gossips a signed chain head it observed; relayed to all clients
+    HeadGossip { head: SignedHead },
+
+    /// Client reports two signed heads for the same message count that disagree
+    ForkAlert { evidence: ForkEvidence },
+
+    /// Client reports a published chain head its mirror could not derive; it signs no head for it
+    ChainMismatch { message_count: usize, chain_hash: Digest, reason: String },
+    
+    /// Server periodically signs the chain head with its long-term key
+    Checkpoint {
+        message_count: usize,
+        chain_hash: Digest,
+        message_root: Digest,
+        #[serde(with = "crate::u64_string")]
+        salt: u64,
+        proof_ref: Option<usize>,
+        signature: String, // base64 ECDSA P-256 / SHA-256 (r || s)
     },
     
+    /// Server is shutting down: no new messages are accepted and the connection closes shortly
+    ServerShutdown { reason: String },
+
     /// Server sends user list update
     UserListUpdate {
         users: Vec<(u64, String)>,
@@ -70,6 +151,10 @@ pub mod error_codes {
     pub const PROOF_VERIFICATION_FAILED: u32 = 1004;
     pub const UNAUTHORIZED: u32 = 1005;
     pub const RATE_LIMITED: u32 = 1006;
+    pub const USERNAME_TAKEN: u32 = 1007;
+    pub const INVALID_USERNAME: u32 = 1008;
+    pub const UNKNOWN_USER: u32 = 1009;
+    pub const SHUTTING_DOWN: u32 = 1010;
     pub const INTERNAL_ERROR: u32 = 5000;
 }
 
@@ -80,6 +165,7 @@ impl From<ZkChatError> for ProtocolMessage {
             ZkChatError::InvalidMessageHash => {
                 Self::error(error_codes::INVALID_MESSAGE_HASH, "Invalid message hash")
             }
+            ZkChatError::HashMismatch { .. } => Self::error(error_codes::INVALID_MESSAGE_HASH, error.to_string()),
             ZkChatError::InvalidTimestamp => {
                 Self::error(error_codes::INVALID_TIMESTAMP, "Invalid timestamp sequence")
             }
@@ -89,7 +175,48 @@ impl From<ZkChatError> for ProtocolMessage {
             ZkChatError::ProofVerificationFailed => {
                 Self::error(error_codes::PROOF_VERIFICATION_FAILED, "Proof verification failed")
             }
+            ZkChatError::UsernameTaken(_) | ZkChatError::InvalidUsername(_) | ZkChatError::UnknownUser(_) => {
+                let code = match error {
+                    ZkChatError::UsernameTaken(_) => error_codes::USERNAME_TAKEN,
+                    ZkChatError::InvalidUsername(_) => error_codes::INVALID_USERNAME,
+                    _ => error_codes::UNKNOWN_USER,
+                };
+                Self::error(code, error.to_string())
+            }
+            ZkChatError::UserBanned(_) | ZkChatError::AuthenticationFailed(_) => Self::error(error_codes::UNAUTHORIZED, error.to_string()),
+            ZkChatError::InvalidPublicKey => Self::error(error_codes::UNAUTHORIZED, "Invalid public key"),
+            ZkChatError::RateLimited => Self::error(error_codes::RATE_LIMITED, "Rate limit exceeded"),
+            ZkChatError::ShuttingDown => Self::error(error_codes::SHUTTING_DOWN, "Server is shutting down"),
             _ => Self::error(error_codes::INTERNAL_ERROR, "Internal server error"),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Convert a cached chain proof into its broadcast message
+impl From<CachedProof> for ProtocolMessage {
+    fn from(cached: CachedProof) -> Self {
+        Self::ChainProof {
+            start_count: cached.start_count,
+            initial_hash: cached.initial_hash,
+            initial_timestamp: cached.initial_timestamp,
+            message_count: cached.message_count,
+            final_hash: cached.final_hash,
+            message_root: cached.message_root,
+            proof: cached.proof,
+        }
+    }
+}
+
+/// Convert a signed checkpoint into its broadcast message
+impl From<SignedCheckpoint> for ProtocolMessage {
+    fn from(checkpoint: SignedCheckpoint) -> Self {
+        Self::Checkpoint {
+            message_count: checkpoint.message_count,
+            chain_hash: checkpoint.chain_hash,
+            message_root: checkpoint.message_root,
+            salt: checkpoint.salt,
+            proof_ref: checkpoint.proof_ref,
+            signature: checkpoint.signature,
+        }
+    }
+}
@@ -1,16 +1,23 @@
 use crate::{
+    config::HeartbeatConfig,
+    tls::TlsReloader,
     websocket::{ProtocolMessage, error_codes},
-    zk::{MessageChain, prover::{MessageProver, verify_proof}},
+    zk::{MessageChain, prover::{MessageProver, verify_proof}, restore_session_salt, storage::StoredChain},
     Result, ZkChatError, Message,
 };
 use futures_util::{SinkExt, StreamExt};
 use std::{
     collections::HashMap,
+    future::Future,
     net::SocketAddr,
+    path::PathBuf,
     sync::{Arc, Mutex},
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tokio::net::{TcpListener, TcpStream};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
 use tokio_tungstenite::{accept_async, tungstenite::Message as WsMessage};
 use tracing::{error, info, warn};
 use winterfell::ProofOptions;
@@ -42,7 +49,7 @@ impl ServerState {
                 54, // num_queries (increased for higher soundness)
                 16, // blowup_factor (greater domain expansion)
                 16, // grinding_factor (adds PoW resistance)
-                winterfell::FieldExtension::None,
+                crate::zk::field::FIELD_EXTENSION,
                 4,  // fri_folding_factor (smaller folds => more rounds)
                 31, // fri_max_remainder_size
             ),
@@ -50,11 +57,53 @@ impl ServerState {
     }
 }
 
+impl Default for ServerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 type SharedState = Arc<Mutex<ServerState>>;
 
+/// How long `ChatServer` waits for open connections to finish their current message on shutdown
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Resolves on Ctrl-C, or SIGTERM on Unix
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
 /// WebSocket chat server with ZK proof verification
 pub struct ChatServer {
     state: SharedState,
+    tls: Option<TlsReloader>,
+    chain_path: Option<PathBuf>,
+    shutdown_timeout: Duration,
+    heartbeat: HeartbeatConfig,
 }
 
 impl ChatServer {
@@ -62,41 +111,174 @@ impl ChatServer {
     pub fn new() -> Self {
         Self {
             state: Arc::new(Mutex::new(ServerState::new())),
+            tls: None,
+            chain_path: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            heartbeat: HeartbeatConfig::default(),
+        }
+    }
+
+    /// Restore the chain stored at `path`, if any, and write it back there on shutdown
+    pub fn with_chain_store(self, path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(stored) = StoredChain::load(&path)? {
+            restore_session_salt(stored.salt);
+            let chain = stored.restore()?;
+            info!("Restored {} messages from {}", chain.len(), path.display());
+            self.state.lock().unwrap().message_chain = chain;
         }
+        Ok(Self { chain_path: Some(path), ..self })
+    }
+
+    /// How long shutdown waits for open connections (default `DEFAULT_SHUTDOWN_TIMEOUT`)
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
     }
 
-    /// Start the server on the specified address
+    /// Ping interval and idle timeout for connections (default `HeartbeatConfig::default()`)
+    pub fn with_heartbeat(mut self, heartbeat: HeartbeatConfig) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Serve wss:// connections using the given certificate (watch it with `TlsReloader::watch` for hot reload)
+    pub fn with_tls(mut self, tls: TlsReloader) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Start the server on the specified address and run until Ctrl-C or SIGTERM
     pub async fn start(&self, addr: impl Into<SocketAddr>) -> Result<()> {
+        self.start_with_shutdown(addr, shutdown_signal()).await
+    }
+
+    /// Start the server and shut it down gracefully once `signal` resolves: stop accepting,
+    /// send `ServerShutdown` to every connection, let in-flight proofs finish (up to the
+    /// shutdown timeout) and flush the chain to its store
+    pub async fn start_with_shutdown(&self, addr: impl Into<SocketAddr>, signal: impl Future<Output = ()>) -> Result<()> {
         let addr = addr.into();
         let listener = TcpListener::bind(&addr).await?;
         info!("ZK Chat Server started on {}", addr);
 
-        while let Ok((stream, peer_addr)) = listener.accept().await {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let mut connections = JoinSet::new();
+        tokio::pin!(signal);
+        loop {
+            let (stream, peer_addr) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Failed to accept connection: {}", e);
+                        continue;
+                    }
+                },
+                Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+                _ = &mut signal => break,
+            };
             info!("New connection from {}", peer_addr);
             let state = self.state.clone();
-            tokio::spawn(async move {
-                if let Err(e) = handle_connection(stream, peer_addr, state).await {
+            let tls = self.tls.clone();
+            let shutdown = shutdown_rx.clone();
+            let heartbeat = self.heartbeat;
+            connections.spawn(async move {
+                let result = match tls {
+                    Some(tls) => match tls.accept(stream).await {
+                        Ok(stream) => handle_connection(stream, peer_addr, state, shutdown, heartbeat).await,
+                        Err(e) => Err(e),
+                    },
+                    None => handle_connection(stream, peer_addr, state, shutdown, heartbeat).await,
+                };
+                if let Err(e) = result {
                     error!("Connection error for {}: {}", peer_addr, e);
                 }
             });
         }
 
+        drop(listener);
+        info!("Shutting down: waiting for {} connections", connections.len());
+        let _ = shutdown_tx.send(true);
+        let drained = tokio::time::timeout(self.shutdown_timeout, async {
+            while connections.join_next().await.is_some() {}
+        })
+        .await;
+        if drained.is_err() {
+            warn!("Shutdown timed out; closing {} connections", connections.len());
+            connections.abort_all();
+        }
+
+        if let Some(path) = &self.chain_path {
+            let state = self.state.lock().unwrap_or_else(|p| p.into_inner());
+            StoredChain::new(&state.message_chain, 0).save(path)?;
+            info!("Saved {} messages to {}", state.message_chain.len(), path.display());
+        }
         Ok(())
     }
 }
 
+impl Default for ChatServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Handle a WebSocket connection
-async fn handle_connection(
-    stream: TcpStream,
+async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
     peer_addr: SocketAddr,
     state: SharedState,
+    mut shutdown: watch::Receiver<bool>,
+    heartbeat: HeartbeatConfig,
 ) -> Result<()> {
     let ws_stream = accept_async(stream).await?;
     let (mut ws_sender, mut ws_receiver) = ws_stream.split();
 
     let mut user_id: Option<u64> = None;
+    let mut pings = heartbeat
+        .interval()
+        .map(|period| tokio::time::interval_at(tokio::time::Instant::now() + period, period));
+    let mut last_seen = tokio::time::Instant::now();
 
-    while let Some(msg) = ws_receiver.next().await {
+    loop {
+        let idle = async {
+            match heartbeat.idle_timeout() {
+                Some(timeout) => tokio::time::sleep_until(last_seen + timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let ping = async {
+            match pings.as_mut() {
+                Some(pings) => { pings.tick().await; }
+                None => std::future::pending().await,
+            }
+        };
+        // A message being handled (and proven) always completes before shutdown is noticed
+        let msg = tokio::select! {
+            msg = ws_receiver.next() => match msg {
+                Some(msg) => msg,
+                None => break,
+            },
+            _ = ping => {
+                if ws_sender.send(WsMessage::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            _ = idle => {
+                info!("Dropping idle connection from {} (silent for {}s)", peer_addr, heartbeat.idle_timeout_secs);
+                break;
+            }
+            _ = async { let _ = shutdown.wait_for(|stopping| *stopping).await; } => {
+                let notice = ProtocolMessage::ServerShutdown { reason: "Server is shutting down".into() };
+                if let Ok(bytes) = notice.to_bytes() {
+                    let _ = ws_sender.send(WsMessage::Text(String::from_utf8_lossy(&bytes).to_string())).await;
+                }
+                let _ = ws_sender.send(WsMessage::Close(None)).await;
+                break;
+            }
+        };
+        // Any frame, including the pong answering a heartbeat, counts as a sign of life
+        last_seen = tokio::time::Instant::now();
         match msg {
             Ok(WsMessage::Text(text)) => {
                 let protocol_msg = match ProtocolMessage::from_bytes(text.as_bytes()) {
@@ -248,12 +430,7 @@ async fn handle_protocol_message(
             
             // Verify the server-generated proof to ensure correctness
             let verification_result = if let Ok(server_proof) = server_proof_result {
-                let pub_inputs = crate::zk::air::PublicInputs {
-                    initial_hash: [0u8; 32], // Empty initial hash
-                    final_hash: temp_chain.chain_hash,
-                    message_count: temp_chain.len(),
-                };
-                verify_proof(&server_proof, pub_inputs)
+                verify_proof(&server_proof, temp_chain.public_inputs(), crate::zk::prover::ProofProfile::Standard)
             } else {
                 Err(crate::ZkChatError::ProofVerificationFailed)
             };
@@ -263,7 +440,7 @@ async fn handle_protocol_message(
             let final_verification = is_verified && hash_valid && server_proof_valid;
             
             if final_verification {
-                info!("Message verified with ZK proof from user {}: {}", uid, server_message.content);
+                info!("Message {} verified with ZK proof from user {} ({} content bytes)", server_message.id, uid, server_message.content.len());
             } else {
                 if !is_verified {
                     warn!("ZK proof verification failed for user {}: {:?}", uid, verification_result.err());
@@ -271,7 +448,7 @@ async fn handle_protocol_message(
                 if !hash_valid {
                     warn!("Message hash verification failed for user {}", uid);
                 }
-                warn!("Message failed verification from user {}: {}", uid, server_message.content);
+                warn!("Message {} failed verification from user {}", server_message.id, uid);
             }
 
             // If verification passed or we allow unverified messages, add to chain
@@ -283,6 +460,7 @@ async fn handle_protocol_message(
                 message: server_message,
                 verified: final_verification,
                 local_id: local_id_value, // legacy server uses message id as local sequence
+                proof_ref: None, // legacy server does not distribute chain proofs
             }))
         }
 
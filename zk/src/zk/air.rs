@@ -1,30 +1,118 @@
-use crate::{Message, zk::{hash_to_elements, zk_hash, SESSION_SALT, hash::{pack_content, truncate_elements, message_hash_inputs}}};
+use crate::{Message, zk::{field::{element, element_to_u64, BaseElement}, Digest, hasher::{default_hash, HashId}, segment::ChainBoundary, session_salt, hash::message_hash_inputs, poseidon::{self, SBOX_OUTPUTS, WIDTH}}};
 use winterfell::{
-    math::{fields::f128::BaseElement, FieldElement, ToElements},
+    math::{ExtensionOf, FieldElement, StarkField, ToElements},
     Air, AirContext, Assertion, EvaluationFrame, ProofOptions, TraceInfo,
     TransitionConstraintDegree,
 };
 
-/// Helper function to convert message content string to field elements.
+// Helper function to convert message content string to field elements.
 // content packing now lives in zk::hash::pack_content
 
 // --- AIR Definition ---
 
-/// Number of columns in the execution trace - PRODUCTION-READY ZK-STARK
-/// 0-3: previous message chain hash (4 field elements)
-/// 4-7: current message hash (4 field elements, calculated using the full 60-round zk_hash)
-/// 8: previous timestamp (for chaining)
-/// 9: current timestamp 
-/// 10: sender ID (value being validated - must be 0 or 1)
-/// 11-17: Message data inputs (ID, Sender, Timestamp, 4 Content elements)
-pub const TRACE_WIDTH: usize = 19; // Added column 18: partial hash state for in-circuit reduced hashing
+/// Trace steps per message, one Poseidon permutation each: four for the message hash over the row's
+/// inputs, then four for the chain step H(prev_chain_hash || message_hash || salt)
+pub const BLOCK_LENGTH: usize = 8;
+
+/// Bits of a timestamp increase: each timestamp exceeds the previous one by 1 to `MAX_TIMESTAMP_STEP`
+pub const TIMESTAMP_BITS: usize = 32;
+
+/// Largest increase between consecutive timestamps a proof can carry
+pub const MAX_TIMESTAMP_STEP: u64 = 1 << TIMESTAMP_BITS;
+
+/// Columns 0-3: chain hash before the block's message
+pub const PREV_HASH: usize = 0;
+/// Columns 4-7: chain hash after the block's message (the previous one on padding blocks), checked on the block's last step
+pub const CHAIN_HASH: usize = 4;
+/// Column 8: timestamp of the previous message
+pub const PREV_TIMESTAMP: usize = 8;
+/// Columns 9-15: message hash inputs (ID, sender, timestamp, 4 payload elements)
+pub const INPUTS: usize = 9;
+/// Column 16: selector, 1 on message blocks and 0 on padding blocks
+pub const SELECTOR: usize = 16;
+/// Columns 17-18: global IDs (sender, ID) in published index order, sorted by sender then ID
+pub const SORTED: usize = 17;
+/// Column 19: inverse of the sorted sender's change to the next block, on the block's last step (0 when the sender stays)
+pub const SENDER_INVERSE: usize = 19;
+/// Columns 20-23: message hash, carried from the step computing it to the steps absorbing it
+pub const MESSAGE_HASH: usize = 20;
+/// Columns 24-55: bits of the timestamp increase minus one, least significant first
+pub const TIMESTAMP_DELTA: usize = 24;
+/// Columns 56-59: input state of the step's Poseidon permutation
+pub const PERMUTATION: usize = TIMESTAMP_DELTA + TIMESTAMP_BITS;
+/// Columns 60 on: the permutation's S-box outputs, ordered as by `poseidon::sbox_outputs`
+pub const SBOX: usize = PERMUTATION + WIDTH;
+
+/// Number of columns in the execution trace. Apart from the permutation columns and the sender inverse,
+/// every column holds one value per block
+pub const TRACE_WIDTH: usize = SBOX + SBOX_OUTPUTS;
+
+/// Auxiliary columns, built after the main trace is committed; both absorb on the first step of each block
+/// 0: permutation grand product over the global IDs absorbed so far
+/// 1: index accumulator over the sorted global IDs absorbed so far
+pub const AUX_TRACE_WIDTH: usize = 2;
+
+/// Verifier challenges for the auxiliary segment: gamma and delta shift and combine the global IDs
+/// for the permutation argument, epsilon weighs the sorted global IDs for the index accumulator
+pub const AUX_RAND_ELEMENTS: usize = 3;
+
+/// Trace layout of a message proof with `length` steps
+pub fn trace_info(length: usize) -> TraceInfo {
+    TraceInfo::new_multi_segment(TRACE_WIDTH, AUX_TRACE_WIDTH, AUX_RAND_ELEMENTS, length, Vec::new())
+}
+
+/// Whether a message stamped `timestamp` may follow one stamped `previous` in a proof
+pub fn timestamp_follows(previous: u64, timestamp: u64) -> bool {
+    timestamp > previous && timestamp - previous <= MAX_TIMESTAMP_STEP
+}
 
 /// Public inputs for the message AIR
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct PublicInputs {
-    pub initial_hash: [u8; 32],
-    pub final_hash: [u8; 32],
+    pub initial_hash: Digest,
+    /// Timestamp of the last message before this segment; 0 for a chain proven from its start
+    #[serde(default)]
+    pub initial_timestamp: u64,
+    /// Chain hash after the last message; the AIR derives it from the rows, so it is the public digest of them
+    pub final_hash: Digest,
+    /// Timestamp of the last message, where the next segment continues from
+    pub final_timestamp: u64,
     pub message_count: usize,
+    /// Merkle root over the message hashes (see `zk::merkle`); bound into the proof transcript
+    /// so single messages can later be disclosed with an inclusion path
+    #[serde(default)]
+    pub message_root: Digest,
+    /// Hash the chain was built with; transcripts from before hash selection use the default
+    #[serde(default)]
+    pub hash_id: HashId,
+    /// Session salt bound into every chain step
+    #[serde(with = "crate::u64_string")]
+    pub salt: u64,
+    /// Published index of the proven messages, sorted (see `message_index`); the trace's messages are
+    /// proven to be a permutation of it, so none were dropped or duplicated
+    #[serde(default)]
+    pub message_index: Vec<GlobalId>,
+}
+
+/// Chain-wide identity of a message: its sender and that sender's message ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct GlobalId {
+    pub sender_id: u64,
+    pub id: u64,
+}
+
+impl GlobalId {
+    /// Global ID of a chain message
+    pub fn of(message: &Message) -> Self {
+        Self { sender_id: message.sender_id, id: message.id }
+    }
+}
+
+/// Canonical published index: the global IDs sorted, independent of chain order
+pub fn message_index<I: IntoIterator<Item = GlobalId>>(ids: I) -> Vec<GlobalId> {
+    let mut index: Vec<GlobalId> = ids.into_iter().collect();
+    index.sort_unstable();
+    index
 }
 
 impl ToElements<BaseElement> for PublicInputs {
@@ -32,13 +120,30 @@ impl ToElements<BaseElement> for PublicInputs {
         let mut result = Vec::new();
         
         // Add initial hash elements
-        result.extend_from_slice(&hash_to_elements(&self.initial_hash));
+        result.extend_from_slice(&self.initial_hash.elements());
+        result.push(element(self.initial_timestamp));
         
-        // Add final hash elements 
-        result.extend_from_slice(&hash_to_elements(&self.final_hash));
+        // Add final hash elements and timestamp
+        result.extend_from_slice(&self.final_hash.elements());
+        result.push(element(self.final_timestamp));
         
         // Add message count
-        result.push(BaseElement::from(self.message_count as u64));
+        result.push(element(self.message_count as u64));
+
+        // Add message Merkle root
+        result.extend_from_slice(&self.message_root.elements());
+
+        // Add hash id
+        result.push(element(self.hash_id.code().into()));
+
+        // Add session salt
+        result.push(element(self.salt));
+
+        // Add published message index
+        for global_id in &self.message_index {
+            result.push(element(global_id.sender_id));
+            result.push(element(global_id.id));
+        }
         
         result
     }
@@ -48,10 +153,25 @@ impl ToElements<BaseElement> for PublicInputs {
 pub struct MessageAir {
     context: AirContext<BaseElement>,
     initial_hash: [BaseElement; 4],
+    initial_timestamp: BaseElement,
     final_hash: [BaseElement; 4],
+    final_timestamp: BaseElement,
     message_count: usize,
+    capacities: [BaseElement; 2],
+    salt: BaseElement,
+    message_index: Vec<GlobalId>,
 }
 
+/// Transition constraints after the permutation rounds, which come first (see `evaluate_transition`)
+const BLOCK_CONSTRAINTS: usize = 58 + COPIED_COLUMNS.len();
+
+/// Columns holding one value per block, copied from each step to the next within a block
+const COPIED_COLUMNS: [usize; 15] = [
+    PREV_HASH, PREV_HASH + 1, PREV_HASH + 2, PREV_HASH + 3, PREV_TIMESTAMP,
+    INPUTS, INPUTS + 1, INPUTS + 2, INPUTS + 3, INPUTS + 4, INPUTS + 5, INPUTS + 6,
+    SELECTOR, SORTED, SORTED + 1,
+];
+
 impl Air for MessageAir {
     type BaseField = BaseElement;
     type PublicInputs = PublicInputs;
@@ -59,27 +179,46 @@ impl Air for MessageAir {
     type GkrVerifier = ();
 
     fn new(trace_info: TraceInfo, pub_inputs: PublicInputs, options: ProofOptions) -> Self {
-        let initial_hash = hash_to_elements(&pub_inputs.initial_hash);
-        let final_hash = hash_to_elements(&pub_inputs.final_hash);
-        
-        // Define constraint degrees - PRODUCTION READY
-        // We only constrain what can be verified at low degree
-        // The 60-round hash is computed off-circuit and verified via public inputs
-        let degrees = vec![
-            TransitionConstraintDegree::new(1), // 0 Hash chaining
-            TransitionConstraintDegree::new(1), // 1 Hash chaining
-            TransitionConstraintDegree::new(1), // 2 Hash chaining
-            TransitionConstraintDegree::new(1), // 3 Hash chaining
-            TransitionConstraintDegree::new(1), // 4 Timestamp chaining
-            TransitionConstraintDegree::new(3), // 5 Partial hash cubic transition
+        let initial_hash = pub_inputs.initial_hash.elements();
+        let final_hash = pub_inputs.final_hash.elements();
+
+        // Constraint degrees, in the order of `evaluate_transition`. Steps are selected by periodic
+        // columns of cycle 8 (one step of the block) or 4 (the same step of both sponges); a sum of
+        // gated terms has the degree of its highest-degree term
+        let sbox = TransitionConstraintDegree::new(poseidon::ALPHA as usize);
+        let block = |base| TransitionConstraintDegree::with_cycles(base, vec![BLOCK_LENGTH]);
+        let sponge = || TransitionConstraintDegree::with_cycles(1, vec![BLOCK_LENGTH / 2]);
+        let mut degrees = vec![sbox; SBOX_OUTPUTS]; // Permutation rounds
+        degrees.extend([block(1), block(1), block(1), sponge()]); // 0-3 Sponge starts
+        degrees.extend([block(1), block(1), block(1), sponge()]); // 4-7 Absorption
+        degrees.extend((0..4).map(|_| block(1))); // 8-11 Message hash carried
+        degrees.extend((0..4).map(|_| block(2))); // 12-15 Chain hash from the chain step
+        degrees.extend((0..4).map(|_| block(1))); // 16-19 Hash chaining
+        degrees.push(block(1)); // 20 Timestamp chaining
+        degrees.push(TransitionConstraintDegree::new(1)); // 21 Timestamp increase
+        degrees.extend((0..TIMESTAMP_BITS).map(|_| TransitionConstraintDegree::new(2))); // 22-53 Bits are binary
+        degrees.push(TransitionConstraintDegree::new(2)); // 54 Selector is binary
+        degrees.push(TransitionConstraintDegree::new(2)); // 55 Selector never returns to 1
+        degrees.extend([block(3), block(3)]); // 56-57 Same sender: ID increases by one; sender change inverse
+        degrees.extend(COPIED_COLUMNS.iter().map(|_| block(1))); // 58- Block columns stay constant
+        debug_assert_eq!(degrees.len(), SBOX_OUTPUTS + BLOCK_CONSTRAINTS);
+        let aux_degrees = vec![
+            block(2), // 0 Permutation grand product
+            block(1), // 1 Index accumulator
         ];
-        
+
         Self {
-            // We have 8 assertions (4 initial hash + 4 final hash)
-            context: AirContext::new(trace_info, degrees, 8, options),
+            // We have 12 main assertions (4 initial hash + initial timestamp + 4 final hash + final timestamp + 2 selector)
+            // and 4 aux assertions (start + end of the grand product and the index accumulator)
+            context: AirContext::new_multi_segment(trace_info, degrees, aux_degrees, 12, 4, None, options),
             initial_hash,
+            initial_timestamp: element(pub_inputs.initial_timestamp),
             final_hash,
+            final_timestamp: element(pub_inputs.final_timestamp),
             message_count: pub_inputs.message_count,
+            capacities: sponge_capacities(pub_inputs.hash_id),
+            salt: element(pub_inputs.salt),
+            message_index: pub_inputs.message_index,
         }
     }
 
@@ -87,189 +226,369 @@ impl Air for MessageAir {
         &self.context
     }
 
+    /// One value per step of a block: 1 on the steps a constraint applies to
+    fn get_periodic_column_values(&self) -> Vec<Vec<Self::BaseField>> {
+        let steps = |cycle: usize, on: &[usize]| {
+            (0..cycle).map(|step| if on.contains(&step) { BaseElement::ONE } else { BaseElement::ZERO }).collect()
+        };
+        vec![
+            steps(BLOCK_LENGTH, &[0]), // message sponge starts
+            steps(BLOCK_LENGTH, &[1]), // message sponge absorbs its last input next
+            steps(BLOCK_LENGTH, &[3]), // message hash is output
+            steps(BLOCK_LENGTH, &[4]), // chain sponge starts
+            steps(BLOCK_LENGTH, &[5]), // chain sponge absorbs its last inputs next
+            steps(BLOCK_LENGTH, &[7]), // chain hash is output, next step starts the next block
+            steps(BLOCK_LENGTH / 2, &[0]), // either sponge starts
+            steps(BLOCK_LENGTH / 2, &[0, 1, 2]), // next step continues this step's sponge
+        ]
+    }
+
     fn evaluate_transition<E: FieldElement + From<Self::BaseField>>(
         &self,
         frame: &EvaluationFrame<E>,
-        _periodic_values: &[E],
+        periodic_values: &[E],
         result: &mut [E],
     ) {
         let current = frame.current();
         let next = frame.next();
+        let &[message_start, message_tail, message_end, chain_start, chain_tail, block_end, sponge_start, chained] = periodic_values else {
+            unreachable!("the message AIR has 8 periodic columns")
+        };
 
-        // PRODUCTION-READY CONSTRAINT SYSTEM
-        // The 60-round Poseidon hash is computed OFF-CIRCUIT in the trace builder
-        // The hash integrity is verified via:
-        // 1. Hash chaining constraints (below)
-        // 2. Public input assertions (initial & final hash at specific steps)
-        // 3. The prover cannot forge the hash chain without breaking cryptography
-        
-        // Constraint 0-3: Hash chaining (CRITICAL SECURITY CONSTRAINT)
-        // Ensures each message's hash flows into the next message's prev_hash
-        // next_prev_hash (next[0-3]) = current_hash (current[4-7])
+        // Permutation rounds: every step permutes its input state; with each S-box output in its own column
+        // the rounds are checked at the S-box degree and the output is linear in the step's columns
+        let state: [E; WIDTH] = std::array::from_fn(|i| current[PERMUTATION + i]);
+        let (rounds, result) = result.split_at_mut(SBOX_OUTPUTS);
+        let output = poseidon::evaluate_rounds(state, &current[SBOX..TRACE_WIDTH], rounds);
+
+        // Constraints 0-3: step 0 starts the message hash from inputs 0-2, step 4 the chain step from
+        // previous hash elements 0-2, each with its sponge's capacity (see `poseidon::poseidon_hash`)
+        let [message_capacity, chain_capacity] = self.capacities.map(E::from);
+        for i in 0..3 {
+            result[i] = sponge_start * state[i] - message_start * current[INPUTS + i] - chain_start * current[PREV_HASH + i];
+        }
+        result[3] = sponge_start * state[3] - message_start * message_capacity - chain_start * chain_capacity;
+
+        // Constraints 4-7: otherwise the next step continues from this step's output plus what it absorbs:
+        // inputs 3-5, then input 6; previous hash element 3 and message hash elements 0-1, then message hash
+        // elements 2-3 and the salt. The last step of each sponge absorbs nothing
+        let absorbed = [
+            message_start * current[INPUTS + 3] + message_tail * current[INPUTS + 6]
+                + chain_start * current[PREV_HASH + 3] + chain_tail * current[MESSAGE_HASH + 2],
+            message_start * current[INPUTS + 4] + chain_start * current[MESSAGE_HASH] + chain_tail * current[MESSAGE_HASH + 3],
+            message_start * current[INPUTS + 5] + chain_start * current[MESSAGE_HASH + 1] + chain_tail * E::from(self.salt),
+            E::ZERO,
+        ];
+        for i in 0..WIDTH {
+            result[4 + i] = chained * (next[PERMUTATION + i] - output[i]) - absorbed[i];
+        }
+
+        // Constraints 8-11: the message hash is step 3's output, carried through the steps absorbing it
         for i in 0..4 {
-            result[i] = next[i] - current[i + 4];
+            result[8 + i] = message_end * (next[MESSAGE_HASH + i] - output[i]) + chain_start * (next[MESSAGE_HASH + i] - current[MESSAGE_HASH + i]);
+        }
+
+        // Constraints 12-15: on message blocks the chain hash is step 7's output; padding blocks cannot
+        // extend the chain and keep the previous hash
+        //   hash - prev - s * (output - prev) = 0
+        let selector = current[SELECTOR];
+        for i in 0..4 {
+            let prev = current[PREV_HASH + i];
+            result[12 + i] = block_end * (current[CHAIN_HASH + i] - prev - selector * (output[i] - prev));
+        }
+
+        // Constraints 16-20: each block's chain hash and timestamp flow into the next block's previous ones
+        for i in 0..4 {
+            result[16 + i] = block_end * (next[PREV_HASH + i] - current[CHAIN_HASH + i]);
+        }
+        result[20] = block_end * (next[PREV_TIMESTAMP] - current[INPUTS + 2]);
+
+        // Constraints 21-53: the timestamp increase minus one is the binary number in the delta columns,
+        // so timestamps strictly increase; with far fewer messages than the field's size they cannot wrap around
+        let bits = &current[TIMESTAMP_DELTA..TIMESTAMP_DELTA + TIMESTAMP_BITS];
+        let delta = bits.iter().rev().fold(E::ZERO, |acc, &bit| acc.double() + bit);
+        result[21] = current[INPUTS + 2] - current[PREV_TIMESTAMP] - E::ONE - delta;
+        for (i, &bit) in bits.iter().enumerate() {
+            result[22 + i] = bit * (bit - E::ONE);
+        }
+
+        // Constraints 54-55: the selector is 1 on message blocks and 0 on padding blocks, in one run each
+        //   s * (s - 1) = 0 and s_next * (1 - s) = 0
+        let next_selector = next[SELECTOR];
+        result[54] = selector * (selector - E::ONE);
+        result[55] = next_selector * (E::ONE - selector);
+
+        // Constraints 56-57: in the sorted columns, a sender's next ID is its previous ID plus one
+        //   same = 1 - Δsender * inv, which is 1 exactly when the sender stays (57 forces inv = 1/Δsender otherwise)
+        //   same * (Δid - 1) = 0 and Δsender * same = 0, between the last step of a block and the next block
+        let sender_change = next[SORTED] - current[SORTED];
+        let same_sender = E::ONE - sender_change * current[SENDER_INVERSE];
+        result[56] = block_end * same_sender * (next[SORTED + 1] - current[SORTED + 1] - E::ONE);
+        result[57] = block_end * sender_change * same_sender;
+
+        // Constraints 58-72: the per-block columns stay constant within the block
+        for (i, &column) in COPIED_COLUMNS.iter().enumerate() {
+            result[58 + i] = (E::ONE - block_end) * (next[column] - current[column]);
         }
-        
-        // Constraint 4: Timestamp chaining
-        // Ensures timestamp flows correctly between rows
-        // next_prev_timestamp (next[8]) = current_timestamp (current[9])
-        result[4] = next[8] - current[9];
-
-        // Constraint 5: Partial in-circuit reduced-round hash chaining.
-        // We maintain a single partial hash state in column 18 across rows:
-        //   partial_next = partial_current^3 + Σ(next message inputs)
-        // where Σ(next message inputs) = id_next + sender_next + timestamp_next + content0_next + content1_next + content2_next + content3_next.
-        // This provides an in-circuit binding of message data, increasing difficulty of arbitrary data forgery without full lookup support.
-        let sum_next_inputs = next[11] + next[12] + next[13]
-            + next[14] + next[15] + next[16] + next[17];
-        result[5] = next[18] - (current[18] * current[18] * current[18] + sum_next_inputs);
-
-        // Timestamp monotonicity enforced off-circuit in MessageChain::add_message()
-        
-        // NOTE: The message hash (columns 4-7) is computed using the FULL 60-round
-        // Poseidon hash in build_trace(). We do NOT constrain it here because:
-        // 1. 60 rounds would exceed degree limits (degree ~3^60)
-        // 2. The hash chaining + public input assertions provide security:
-        //    - Initial hash is asserted at step 0
-        //    - Final hash is asserted at the last message step
-        //    - The prover cannot forge intermediate hashes without breaking the chain
-        // 3. This is the STANDARD approach for hash-heavy STARKs (e.g., StarkWare)
-        // 4. Lookup-style in-circuit hash binding deferred; Winterfell lacks native lookup arguments.
     }
 
     fn get_assertions(&self) -> Vec<Assertion<Self::BaseField>> {
         let mut assertions = Vec::new();
 
-        // Initial hash assertion (start of the chain) - columns 0-3 at step 0
-        // This should be [0,0,0,0] for the first message's prev_hash
+        // Initial hash assertion (start of the segment) - columns 0-3 at step 0
+        // This is [0,0,0,0] for a chain proven from its start, or the previous segment's final hash
         for (i, &element) in self.initial_hash.iter().enumerate() {
-            assertions.push(Assertion::single(i, 0, element));
+            assertions.push(Assertion::single(PREV_HASH + i, 0, element));
         }
+        // The first message's previous timestamp is the segment's initial timestamp
+        assertions.push(Assertion::single(PREV_TIMESTAMP, 0, self.initial_timestamp));
 
-        // Final hash assertion - columns 4-7 at the LAST ACTUAL MESSAGE step
-        // CRITICAL: We assert at (message_count - 1), NOT at the padded trace length
-        // The final hash is the hash of the last real message, before any padding
-        let last_message_step = self.message_count - 1;
+        // Final hash and timestamp are asserted on the LAST ACTUAL MESSAGE block, before any padding:
+        // the hash on its last step, where the chain step outputs it
+        let last_block = (self.message_count - 1) * BLOCK_LENGTH;
         for (i, &element) in self.final_hash.iter().enumerate() {
-            assertions.push(Assertion::single(i + 4, last_message_step, element));
+            assertions.push(Assertion::single(CHAIN_HASH + i, last_block + BLOCK_LENGTH - 1, element));
         }
+        assertions.push(Assertion::single(INPUTS + 2, last_block, self.final_timestamp));
+
+        // Selector switches from message blocks to padding exactly after message_count blocks;
+        // with the selector constraints this pins every block before it as a message block
+        assertions.push(Assertion::single(SELECTOR, last_block, BaseElement::ONE));
+        assertions.push(Assertion::single(SELECTOR, last_block + BLOCK_LENGTH, BaseElement::ZERO));
 
         assertions
     }
+
+    fn evaluate_aux_transition<F, E>(
+        &self,
+        main_frame: &EvaluationFrame<F>,
+        aux_frame: &EvaluationFrame<E>,
+        periodic_values: &[F],
+        aux_rand_elements: &[E],
+        result: &mut [E],
+    ) where
+        F: FieldElement<BaseField = Self::BaseField>,
+        E: FieldElement<BaseField = Self::BaseField> + ExtensionOf<F>,
+    {
+        let current = main_frame.current();
+        let (gamma, delta, epsilon) = (aux_rand_elements[0], aux_rand_elements[1], aux_rand_elements[2]);
+        // Both columns absorb once per block, on its first step, and hold their value otherwise
+        let message_start = E::from(periodic_values[0]);
+
+        // Aux constraint 0: grand product absorbs the block's global ID
+        //   z_next = z * (gamma - (id + delta * sender))
+        // Equal products over the trace and the published index mean equal multisets, except with negligible probability
+        let term = gamma - global_id_term(current[INPUTS], current[INPUTS + 1], delta);
+        let product = aux_frame.current()[0];
+        result[0] = aux_frame.next()[0] - product - message_start * product * (term - E::ONE);
+
+        // Aux constraint 1: index accumulator absorbs the block's sorted global ID
+        //   acc_next = acc * epsilon + (id + delta * sender)
+        // Its final value pins the sorted columns to the published index, entry by entry
+        let sorted = global_id_term(current[SORTED + 1], current[SORTED], delta);
+        let accumulator = aux_frame.current()[1];
+        result[1] = aux_frame.next()[1] - accumulator - message_start * (accumulator * (epsilon - E::ONE) + sorted);
+    }
+
+    fn get_aux_assertions<E: FieldElement<BaseField = Self::BaseField>>(
+        &self,
+        aux_rand_elements: &[E],
+    ) -> Vec<Assertion<E>> {
+        // The grand product starts at one and, once the last message block is absorbed, must equal the product
+        // over the published index
+        let product = index_product(&self.message_index, aux_rand_elements[0], aux_rand_elements[1]);
+        // The index accumulator starts empty and must end at the fold over the published index in order
+        let sorted = index_commitment(&self.message_index, aux_rand_elements[1], aux_rand_elements[2]);
+        let absorbed = self.message_count * BLOCK_LENGTH;
+        vec![
+            Assertion::single(0, 0, E::ONE),
+            Assertion::single(0, absorbed, product),
+            Assertion::single(1, 0, E::ZERO),
+            Assertion::single(1, absorbed, sorted),
+        ]
+    }
+}
+
+/// Capacities the message and chain sponges start from under `hash_id`: the input counts for the
+/// length-separated sponge, zero otherwise
+fn sponge_capacities(hash_id: HashId) -> [BaseElement; 2] {
+    match hash_id {
+        HashId::PoseidonSeparated => [element(MESSAGE_INPUTS as u64), element(CHAIN_INPUTS as u64)],
+        _ => [BaseElement::ZERO; 2],
+    }
+}
+
+/// Combine a message ID and sender into one element: id + delta * sender
+pub fn global_id_term<F: FieldElement, E: FieldElement + ExtensionOf<F>>(id: F, sender: F, delta: E) -> E {
+    E::from(id) + delta * E::from(sender)
+}
+
+/// Final value of the permutation grand product over `index` for the given challenges
+pub fn index_product<E: FieldElement<BaseField = BaseElement>>(index: &[GlobalId], gamma: E, delta: E) -> E {
+    index.iter().fold(E::ONE, |product, global_id| {
+        product * (gamma - global_id_term(element(global_id.id), element(global_id.sender_id), delta))
+    })
+}
+
+/// Final value of the index accumulator over `index`, in order, for the given challenges
+pub fn index_commitment<E: FieldElement<BaseField = BaseElement>>(index: &[GlobalId], delta: E, epsilon: E) -> E {
+    index.iter().fold(E::ZERO, |acc, global_id| {
+        acc * epsilon + global_id_term(element(global_id.id), element(global_id.sender_id), delta)
+    })
+}
+
+/// Inputs of the message hash: a row
+const MESSAGE_INPUTS: usize = 7;
+
+/// Inputs of a chain step: previous chain hash, message hash and salt
+const CHAIN_INPUTS: usize = 9;
+
+/// Hash inputs of one trace row: ID, sender, timestamp and four payload elements
+/// (packed content for full messages, the content commitment for redacted ones)
+pub type MessageRow = [BaseElement; MESSAGE_INPUTS];
+
+/// Trace row inputs for a full message
+pub fn message_row(message: &Message) -> MessageRow {
+    let inputs = message_hash_inputs(message.id, message.sender_id, message.timestamp, &message.content);
+    inputs.try_into().expect("message hash inputs are 7 elements")
 }
 
 /// Build execution trace for a sequence of messages
 pub fn build_trace(messages: &[Message]) -> Vec<Vec<BaseElement>> {
-    // Winterfell requires minimum 8 trace steps for cryptographic security
-    let base_length = messages.len().max(1);
-    let trace_length = if base_length < 8 {
-        8
-    } else {
-        base_length
-    };
-    
-    let mut trace = vec![vec![BaseElement::ZERO; trace_length]; TRACE_WIDTH];
+    let rows: Vec<MessageRow> = messages.iter().map(message_row).collect();
+    let hash_id = messages.first().map_or_else(default_hash, |m| m.hash_id);
+    build_trace_from_rows(&rows, hash_id)
+}
 
-    // Process actual messages first
-    for (step, message) in messages.iter().enumerate() {
-        // 0-3: Previous message hash (for chaining)
-        // For step 0, this is [0,0,0,0]. For step > 0, this is the hash from previous row
-        if step > 0 {
-            // Copy the hash from the previous row (columns 4-7) to current row (columns 0-3)
-            // This satisfies the constraint: next[0-3] = current[4-7]
-            for i in 0..4 {
-                trace[i][step] = trace[4 + i][step - 1];
-            }
-        } else {
-            // Initial prev_hash is all zeros
-            for i in 0..4 {
-                trace[i][step] = BaseElement::ZERO;
-            }
-        }
+/// Build execution trace from per-message hash inputs, hashing with `hash_id`
+pub fn build_trace_from_rows(rows: &[MessageRow], hash_id: HashId) -> Vec<Vec<BaseElement>> {
+    build_segment_trace(rows, hash_id, &ChainBoundary::GENESIS, session_salt())
+}
 
-        // Message content elements (for hash input)
-        let content_elements: [BaseElement; 4] = pack_content(&message.content);
-        
-        // 11: Message ID (Hash Input)
-        trace[11][step] = BaseElement::from(message.id);
-        // 12: Sender ID (Hash Input)
-        trace[12][step] = BaseElement::from(message.sender_id);
-        // 13: Timestamp (Hash Input)
-        trace[13][step] = BaseElement::from(message.timestamp);
-        // 14-17: Message content (Hash Input)
-        for i in 0..4 {
-            trace[14 + i][step] = content_elements[i];
-        }
+/// Build execution trace for a segment continuing the chain from `start`, chaining under `salt`.
+/// `hash_id` must be provable (see `HashId::provable`)
+pub fn build_segment_trace(rows: &[MessageRow], hash_id: HashId, start: &ChainBoundary, salt: u64) -> Vec<Vec<BaseElement>> {
+    assert!(hash_id.provable(), "{} chains cannot be proven", hash_id);
+    let [message_capacity, chain_capacity] = sponge_capacities(hash_id);
+    // One block per message and at least one padding block, a power of two in all
+    let blocks = (rows.len() + 1).next_power_of_two();
+    let trace_length = blocks * BLOCK_LENGTH;
+
+    let mut trace = vec![vec![BaseElement::ZERO; trace_length]; TRACE_WIDTH];
 
-        // 4-7: Current CHAIN hash (prev_chain_hash || message_hash) using 60-round zk_hash
-        // First compute the per-message hash from message fields
-        let message_inputs = message_hash_inputs(message.id, message.sender_id, message.timestamp, &message.content);
-        let message_hash_full = zk_hash(&message_inputs);
-        let message_hash_trunc = truncate_elements(&message_hash_full);
-
-        // Then compute the chain hash as Poseidon(prev_hash || message_hash_trunc)
-        let mut chain_inputs = Vec::with_capacity(9);
-        for i in 0..4 { chain_inputs.push(trace[i][step]); }
-        chain_inputs.extend_from_slice(&message_hash_trunc);
-        chain_inputs.push(*SESSION_SALT); // bind session salt into chain hash (matches MessageChain)
-        let chain_hash_full = zk_hash(&chain_inputs);
-        let chain_hash_trunc = truncate_elements(&chain_hash_full);
-        for i in 0..4 { trace[4 + i][step] = chain_hash_trunc[i]; }
-
-        // 18: partial hash state. For step 0 we initialize with sum of current inputs.
-        // For subsequent steps we apply: partial_next = partial_current^3 + Σ(current inputs)
-        let input_sum = trace[11][step] + trace[12][step] + trace[13][step]
-            + trace[14][step] + trace[15][step] + trace[16][step] + trace[17][step];
-        if step == 0 {
-            trace[18][step] = input_sum;
+    // The segment continues from its starting hash and timestamp (all zeros for a fresh chain)
+    let mut prev_hash = start.hash.elements();
+    let mut prev_timestamp = element(start.timestamp);
+    let mut inputs = rows[0];
+    let mut deltas = Vec::with_capacity(blocks);
+    let mut message_output = prev_hash;
+    for block in 0..blocks {
+        let is_message = block < rows.len();
+        let delta = if is_message {
+            inputs = rows[block];
+            element_to_u64(inputs[2]).wrapping_sub(element_to_u64(prev_timestamp)).wrapping_sub(1) % MAX_TIMESTAMP_STEP
         } else {
-            let prev = trace[18][step - 1];
-            trace[18][step] = prev * prev * prev + input_sum;
-        }
+            // Padding continues from the previous block with every input moved by the previous message hash,
+            // so no column is constant or repeats with the block count
+            inputs = std::array::from_fn(|i| inputs[i] + message_output[i % 4]);
+            let delta = if block + 1 == blocks { last_padding_delta(&deltas) } else { !deltas[block - 1] % MAX_TIMESTAMP_STEP };
+            inputs[2] = prev_timestamp + element(delta + 1);
+            delta
+        };
+        deltas.push(delta);
 
+        // Steps 0-3 hash the row, steps 4-7 extend the chain: H(prev_hash || message_hash || salt), as in MessageChain
+        let (message_steps, message_hash) = sponge_steps(&inputs, message_capacity);
+        message_output = message_hash;
+        let mut chain_inputs = Vec::with_capacity(CHAIN_INPUTS);
+        chain_inputs.extend_from_slice(&prev_hash);
+        chain_inputs.extend_from_slice(&message_hash);
+        chain_inputs.push(element(salt));
+        let (chain_steps, chain_hash) = sponge_steps(&chain_inputs, chain_capacity);
+        // Padding blocks keep the previous hash
+        let hash = if is_message { chain_hash } else { prev_hash };
 
-        // 8-9: Timestamps
-        if step > 0 {
-            trace[8][step] = BaseElement::from(messages[step - 1].timestamp); // Previous timestamp
-        } else {
-            trace[8][step] = BaseElement::ZERO; // Initial timestamp (t-1)
+        for (k, permutation) in message_steps.into_iter().chain(chain_steps).enumerate() {
+            let step = block * BLOCK_LENGTH + k;
+            for i in 0..4 {
+                trace[PREV_HASH + i][step] = prev_hash[i];
+                trace[CHAIN_HASH + i][step] = hash[i];
+                trace[MESSAGE_HASH + i][step] = message_hash[i];
+            }
+            trace[PREV_TIMESTAMP][step] = prev_timestamp;
+            for (i, &input) in inputs.iter().enumerate() {
+                trace[INPUTS + i][step] = input;
+            }
+            if is_message {
+                trace[SELECTOR][step] = BaseElement::ONE;
+            }
+            for bit in 0..TIMESTAMP_BITS {
+                trace[TIMESTAMP_DELTA + bit][step] = element((delta >> bit) & 1);
+            }
+            for (i, &value) in permutation.iter().enumerate() {
+                trace[PERMUTATION + i][step] = value;
+            }
+            for (i, output) in poseidon::sbox_outputs(permutation).into_iter().enumerate() {
+                trace[SBOX + i][step] = output;
+            }
         }
-        trace[9][step] = BaseElement::from(message.timestamp); // Current timestamp (t)
 
-        // 10: Sender ID (for validation)
-        trace[10][step] = BaseElement::from(message.sender_id);
+        prev_hash = hash;
+        prev_timestamp = inputs[2];
     }
-    
-    // Fill remaining trace steps to meet minimum length requirement
-    if messages.len() < 8 {
-        let last_message_step = messages.len() - 1;
-        
-        for step in messages.len()..trace_length {
-            // Copy previous row's hash (4-7) to current row's prev_hash (0-3)
-            // This maintains the constraint: next[0-3] = current[4-7]
-            for i in 0..4 { trace[i][step] = trace[4 + i][step - 1]; }
-            
-            // Keep hash the same for padding rows
-            for i in 0..4 { trace[i + 4][step] = trace[i + 4][step - 1]; }
-            
-            // Timestamp progression
-            trace[8][step] = trace[9][step - 1]; // Previous timestamp
-            trace[9][step] = trace[9][step - 1] + BaseElement::ONE; // Increment timestamp
-            
-            // Use last message's sender and data for padding
-            trace[10][step] = trace[10][last_message_step];
-            for i in 11..18 {
-                trace[i][step] = trace[i][last_message_step];
-            }
 
-            // Maintain partial hash evolution over padding rows using same rule.
-            let input_sum = trace[11][step] + trace[12][step] + trace[13][step]
-                + trace[14][step] + trace[15][step] + trace[16][step] + trace[17][step];
-            let prev = trace[18][step - 1];
-            trace[18][step] = prev * prev * prev + input_sum;
+    // Sorted global IDs like the published index, one per block; padding blocks sort after it as their own
+    // global IDs, whose senders are fresh except with negligible probability
+    let mut sorted: Vec<(BaseElement, BaseElement)> = rows.iter().map(|row| (row[1], row[0])).collect();
+    sorted.sort_unstable_by_key(|&(sender, id)| (element_to_u64(sender), element_to_u64(id)));
+    for block in 0..blocks {
+        let first = block * BLOCK_LENGTH;
+        let (sender, id) = sorted.get(block).copied().unwrap_or((trace[INPUTS + 1][first], trace[INPUTS][first]));
+        trace[SORTED][first..first + BLOCK_LENGTH].fill(sender);
+        trace[SORTED + 1][first..first + BLOCK_LENGTH].fill(id);
+    }
 
+    // Inverse of the sender change on each block's last step, 0 where the sender stays and on the last block
+    for block in 0..blocks - 1 {
+        let step = (block + 1) * BLOCK_LENGTH - 1;
+        let sender_change = trace[SORTED][step + 1] - trace[SORTED][step];
+        if sender_change != BaseElement::ZERO {
+            trace[SENDER_INVERSE][step] = sender_change.inv();
         }
     }
 
     trace
-}
\ No newline at end of file
+}
+
+/// Timestamp increase of the last padding block, after blocks with the increases `deltas`. Each bit is set
+/// exactly when its column's block values, weighted by the powers of a primitive root of unity of the block
+/// count, would otherwise sum to zero: the column would then interpolate below the trace degree, and
+/// its constraint below its declared degree
+fn last_padding_delta(deltas: &[u64]) -> u64 {
+    let root = BaseElement::get_root_of_unity((deltas.len() + 1).trailing_zeros());
+    (0..TIMESTAMP_BITS)
+        .filter(|&bit| {
+            let (sum, _) = deltas.iter().fold((BaseElement::ZERO, BaseElement::ONE), |(sum, power), &delta| {
+                (sum + power * element((delta >> bit) & 1), power * root)
+            });
+            sum == BaseElement::ZERO
+        })
+        .fold(0, |delta, bit| delta | 1 << bit)
+}
+
+/// Permutation inputs of a Poseidon sponge over `inputs` from `capacity` (see `poseidon::poseidon_hash`),
+/// one per absorbed chunk plus the final permutation, and the sponge's output
+fn sponge_steps(inputs: &[BaseElement], capacity: BaseElement) -> (Vec<[BaseElement; WIDTH]>, [BaseElement; WIDTH]) {
+    let mut state = [BaseElement::ZERO; WIDTH];
+    state[WIDTH - 1] = capacity;
+    let mut steps = Vec::with_capacity(BLOCK_LENGTH / 2);
+    for chunk in inputs.chunks(WIDTH - 1) {
+        for (s, &input) in state.iter_mut().zip(chunk) {
+            *s += input;
+        }
+        steps.push(state);
+        state = poseidon::poseidon_permutation(state);
+    }
+    steps.push(state);
+    (steps, poseidon::poseidon_permutation(state))
+}
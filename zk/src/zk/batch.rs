@@ -0,0 +1,193 @@
+use super::{prover::{verify_proof, MessageProver, ProofProfile}, segment::SegmentProof, MessageChain};
+use crate::{Message, ZkChatError};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Batching parameters: a batch is proven once it holds `max_batch_size`
+/// messages or the oldest queued message has waited `max_wait`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchConfig {
+    pub max_batch_size: usize,
+    pub max_wait: Duration,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 16,
+            max_wait: Duration::from_millis(200),
+        }
+    }
+}
+
+/// Queue of messages awaiting a shared batch proof
+#[derive(Debug, Clone)]
+pub struct BatchProver {
+    config: BatchConfig,
+    pending: Vec<Message>,
+    first_queued_at: Option<Instant>,
+}
+
+impl BatchProver {
+    /// Create an empty batch queue
+    pub fn new(config: BatchConfig) -> Self {
+        Self { config, pending: Vec::new(), first_queued_at: None }
+    }
+
+    /// Queue a message; returns true once the batch is full
+    pub fn enqueue(&mut self, message: Message) -> bool {
+        if self.pending.is_empty() {
+            self.first_queued_at = Some(Instant::now());
+        }
+        self.pending.push(message);
+        self.pending.len() >= self.config.max_batch_size
+    }
+
+    /// Time left before the pending batch should be proven (`None` when nothing is queued)
+    pub fn time_until_ready(&self) -> Option<Duration> {
+        let first = self.first_queued_at?;
+        if self.pending.len() >= self.config.max_batch_size {
+            return Some(Duration::ZERO);
+        }
+        Some(self.config.max_wait.saturating_sub(first.elapsed()))
+    }
+
+    /// Whether the pending batch should be proven now
+    pub fn is_ready(&self) -> bool {
+        self.time_until_ready() == Some(Duration::ZERO)
+    }
+
+    /// Take up to `max_batch_size` queued messages, oldest first
+    pub fn take_batch(&mut self) -> Vec<Message> {
+        let count = self.pending.len().min(self.config.max_batch_size);
+        let batch: Vec<Message> = self.pending.drain(..count).collect();
+        self.first_queued_at = if self.pending.is_empty() { None } else { Some(Instant::now()) };
+        batch
+    }
+
+    /// Put messages back at the head of the queue (e.g. a batch whose proof was discarded)
+    pub fn requeue(&mut self, messages: Vec<Message>) {
+        if messages.is_empty() {
+            return;
+        }
+        self.pending.splice(0..0, messages);
+        self.first_queued_at.get_or_insert_with(Instant::now);
+    }
+
+    /// Move queued messages down past their senders' dropped ones (see `BatchOutcome::dropped`),
+    /// so each sender's sequence stays gap-free
+    pub fn close_gaps(&mut self, dropped: &HashMap<u64, u64>) {
+        for message in &mut self.pending {
+            if let Some(&count) = dropped.get(&message.sender_id) {
+                *message = renumbered(message, count);
+            }
+        }
+    }
+
+    /// Queued messages, oldest first
+    pub fn pending(&self) -> &[Message] {
+        &self.pending
+    }
+
+    /// Number of queued messages
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Batching parameters
+    pub fn config(&self) -> &BatchConfig {
+        &self.config
+    }
+}
+
+/// Result of proving one batch
+#[derive(Debug)]
+pub struct BatchOutcome {
+    /// Chain to adopt: extended by the accepted messages if the batch proof verified, unchanged otherwise
+    pub chain: MessageChain,
+    /// Messages the chain accepted, in order (appended only when `verified`); ids after a rejected
+    /// message of the same sender are moved down to close the gap
+    pub accepted: Vec<Message>,
+    /// Messages the chain refused (bad hash, replayed id, non-monotonic timestamp)
+    pub rejected: Vec<(Message, ZkChatError)>,
+    /// Single proof of the accepted messages, as one segment continuing from the chain's previous end
+    pub proof: Option<SegmentProof>,
+    /// Whether the batch proof was generated and verified
+    pub verified: bool,
+}
+
+impl BatchOutcome {
+    /// Reference to the shared proof: the chain length it covers
+    pub fn proof_ref(&self) -> Option<usize> {
+        self.verified.then(|| self.chain.len())
+    }
+
+    /// Messages per sender that did not enter the chain: the rejected ones, and the accepted ones too
+    /// when the batch proof failed. The sender's later queued messages must move down by as many.
+    pub fn dropped(&self) -> HashMap<u64, u64> {
+        let mut dropped = HashMap::new();
+        let lost = self.rejected.iter().map(|(message, _)| message);
+        let unproven = self.accepted.iter().filter(|_| !self.verified);
+        for message in lost.chain(unproven) {
+            *dropped.entry(message.sender_id).or_insert(0) += 1;
+        }
+        dropped
+    }
+}
+
+/// `message` with its id moved down by `by`, rehashed; messages whose hash does not verify are left to be rejected
+fn renumbered(message: &Message, by: u64) -> Message {
+    if by == 0 || !message.verify_hash() {
+        return message.clone();
+    }
+    Message::hashed_with(message.hash_id, message.id.saturating_sub(by), message.sender_id, message.content.clone(), message.timestamp)
+}
+
+/// Append a batch to a copy of `chain` and produce one proof covering the new messages only
+pub fn prove_batch(chain: &MessageChain, messages: Vec<Message>) -> BatchOutcome {
+    prove_batch_with_profile(chain, messages, ProofProfile::default())
+}
+
+/// `prove_batch` with the given proof parameter preset
+pub fn prove_batch_with_profile(chain: &MessageChain, messages: Vec<Message>, profile: ProofProfile) -> BatchOutcome {
+    let mut extended = chain.clone();
+    let mut accepted = Vec::with_capacity(messages.len());
+    let mut rejected = Vec::new();
+    // A rejected message leaves a hole in its sender's sequence; that sender's later messages close it
+    let mut dropped: HashMap<u64, u64> = HashMap::new();
+    for message in messages {
+        let message = renumbered(&message, dropped.get(&message.sender_id).copied().unwrap_or(0));
+        match extended.add_message(message.clone()) {
+            Ok(()) => accepted.push(message),
+            Err(e) => {
+                *dropped.entry(message.sender_id).or_insert(0) += 1;
+                rejected.push((message, e));
+            }
+        }
+    }
+
+    if accepted.is_empty() {
+        return BatchOutcome { chain: chain.clone(), accepted, rejected, proof: None, verified: false };
+    }
+
+    // Earlier messages are covered by earlier proofs: prove only this batch, from where the chain ended
+    let mut segment = MessageChain::resume(chain.end(), chain.hash_id);
+    for message in &accepted {
+        if let Err(e) = segment.add_message(message.clone()) {
+            tracing::warn!("Batch segment refused message {} from {}: {}", message.id, message.sender_id, e);
+            return BatchOutcome { chain: chain.clone(), accepted, rejected, proof: None, verified: false };
+        }
+    }
+    let public_inputs = segment.public_inputs();
+    let mut prover = MessageProver::with_profile(profile);
+    let proof = prover.prove_segment(&segment.start, &segment.messages)
+        .and_then(|proof| verify_proof(&proof, public_inputs.clone(), profile).map(|_| proof));
+
+    match proof {
+        Ok(proof) => BatchOutcome { chain: extended, accepted, rejected, proof: Some(SegmentProof { public_inputs, proof }), verified: true },
+        Err(e) => {
+            tracing::warn!("Batch proof failed for {} messages: {}", accepted.len(), e);
+            BatchOutcome { chain: chain.clone(), accepted, rejected, proof: None, verified: false }
+        }
+    }
+}
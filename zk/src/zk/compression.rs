@@ -0,0 +1,187 @@
+use super::{air::PublicInputs, Digest};
+use crate::{Result, ZkChatError};
+use winterfell::{Deserializable, ProofOptions, Serializable};
+
+/// Magic bytes identifying a compressed ZK Chat proof
+pub const PROOF_MAGIC: [u8; 4] = *b"ZKCP";
+
+/// Current version of the compressed proof format
+pub const PROOF_FORMAT_VERSION: u8 = 1;
+
+/// Header length: magic (4) + version (1) + codec (1) + proof options (6) + raw length (4)
+pub const PROOF_HEADER_LEN: usize = 16;
+
+/// Largest raw proof accepted; a header claiming more is rejected before anything is allocated
+pub const MAX_PROOF_BYTES: usize = 16 * 1024 * 1024;
+
+/// zstd compression level used for proofs (zstd default)
+const ZSTD_LEVEL: i32 = 3;
+
+/// Codec applied to the proof payload following the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ProofCodec {
+    /// Payload is the raw `Proof::to_bytes` output
+    None = 0,
+    /// Payload is zstd-compressed `Proof::to_bytes` output
+    Zstd = 1,
+}
+
+impl ProofCodec {
+    fn from_u8(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Zstd),
+            other => Err(ZkChatError::ProofCompression(format!("Unknown proof codec {other}"))),
+        }
+    }
+}
+
+/// Header prepended to every compressed proof.
+/// Carries the proof option set so receivers can reject unacceptable parameters before decompressing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofHeader {
+    pub version: u8,
+    pub codec: ProofCodec,
+    pub options: ProofOptions,
+    pub raw_len: u32,
+}
+
+impl ProofHeader {
+    /// Parse the header from the start of a compressed proof
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < PROOF_HEADER_LEN {
+            return Err(ZkChatError::ProofCompression("Compressed proof too short".into()));
+        }
+        if data[0..4] != PROOF_MAGIC {
+            return Err(ZkChatError::ProofCompression("Invalid compressed proof magic".into()));
+        }
+        let version = data[4];
+        if version != PROOF_FORMAT_VERSION {
+            return Err(ZkChatError::ProofCompression(format!("Unsupported proof format version {version}")));
+        }
+        let codec = ProofCodec::from_u8(data[5])?;
+        let options = ProofOptions::read_from_bytes(&data[6..12])
+            .map_err(|e| ZkChatError::ProofCompression(format!("Invalid proof options in header: {e}")))?;
+        let raw_len = u32::from_le_bytes(data[12..16].try_into().unwrap());
+        Ok(Self { version, codec, options, raw_len })
+    }
+
+    fn write_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&PROOF_MAGIC);
+        out.push(self.version);
+        out.push(self.codec as u8);
+        out.extend_from_slice(&self.options.to_bytes());
+        out.extend_from_slice(&self.raw_len.to_le_bytes());
+    }
+}
+
+/// Compress serialized proof bytes (output of `MessageProver::prove`) into the headered zstd format
+pub fn compress_proof(proof_bytes: &[u8]) -> Result<Vec<u8>> {
+    encode_proof(proof_bytes, ProofCodec::Zstd)
+}
+
+/// Wrap serialized proof bytes in the headered format using the given codec
+pub fn encode_proof(proof_bytes: &[u8], codec: ProofCodec) -> Result<Vec<u8>> {
+    // Parse the proof to pull its option set into the header
+    let proof = winterfell::Proof::from_bytes(proof_bytes)
+        .map_err(|e| ZkChatError::ProofCompression(format!("Proof deserialization failed: {:?}", e)))?;
+    if proof_bytes.len() > MAX_PROOF_BYTES {
+        return Err(ZkChatError::ProofCompression("Proof too large".into()));
+    }
+    let raw_len = proof_bytes.len() as u32;
+    let header = ProofHeader {
+        version: PROOF_FORMAT_VERSION,
+        codec,
+        options: proof.options().clone(),
+        raw_len,
+    };
+
+    let mut out = Vec::with_capacity(PROOF_HEADER_LEN + proof_bytes.len());
+    header.write_into(&mut out);
+    match codec {
+        ProofCodec::None => out.extend_from_slice(proof_bytes),
+        ProofCodec::Zstd => {
+            let compressed = zstd::bulk::compress(proof_bytes, ZSTD_LEVEL)?;
+            out.extend_from_slice(&compressed);
+        }
+    }
+    Ok(out)
+}
+
+/// Decompress a headered proof back into raw proof bytes suitable for `verify_proof`
+pub fn decompress_proof(data: &[u8]) -> Result<Vec<u8>> {
+    let header = ProofHeader::parse(data)?;
+    if header.raw_len as usize > MAX_PROOF_BYTES {
+        return Err(ZkChatError::ProofCompression(format!("Proof of {} bytes exceeds the {} byte limit", header.raw_len, MAX_PROOF_BYTES)));
+    }
+    let payload = &data[PROOF_HEADER_LEN..];
+    let raw = match header.codec {
+        ProofCodec::None => payload.to_vec(),
+        ProofCodec::Zstd => zstd::bulk::decompress(payload, header.raw_len as usize)?,
+    };
+    if raw.len() != header.raw_len as usize {
+        return Err(ZkChatError::ProofCompression("Decompressed proof length mismatch".into()));
+    }
+
+    // The header must describe the proof it wraps
+    let proof = winterfell::Proof::from_bytes(&raw)
+        .map_err(|e| ZkChatError::ProofCompression(format!("Proof deserialization failed: {:?}", e)))?;
+    if proof.options() != &header.options {
+        return Err(ZkChatError::ProofCompression("Proof options do not match header".into()));
+    }
+    Ok(raw)
+}
+
+/// Compressed proof of the chain segment that ends after its first `message_count` messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedProof {
+    /// Chain length the proven segment starts after, and the chain hash and last timestamp there
+    pub start_count: usize,
+    pub initial_hash: Digest,
+    pub initial_timestamp: u64,
+    pub message_count: usize,
+    pub final_hash: Digest,
+    pub message_root: Digest,
+    pub proof: Vec<u8>,
+}
+
+/// Caches the latest segment proof; the prover publishes each one once, right after proving it
+#[derive(Debug, Clone, Default)]
+pub struct ProofCache {
+    latest: Option<CachedProof>,
+    published_count: usize,
+}
+
+impl ProofCache {
+    /// Replace the cached proof with a newer one (raw `Proof::to_bytes` output) of the segment
+    /// following the first `start_count` messages; 0 for a proof of the whole chain
+    pub fn update(&mut self, start_count: usize, pub_inputs: &PublicInputs, proof_bytes: &[u8]) -> Result<()> {
+        let proof = compress_proof(proof_bytes)?;
+        self.latest = Some(CachedProof {
+            start_count,
+            initial_hash: pub_inputs.initial_hash,
+            initial_timestamp: pub_inputs.initial_timestamp,
+            message_count: start_count + pub_inputs.message_count,
+            final_hash: pub_inputs.final_hash,
+            message_root: pub_inputs.message_root,
+            proof,
+        });
+        Ok(())
+    }
+
+    /// Latest cached proof, whether or not it has been published
+    pub fn latest(&self) -> Option<&CachedProof> {
+        self.latest.as_ref()
+    }
+
+    /// Return the cached proof if it covers messages that have not been published yet
+    pub fn flush(&mut self) -> Option<CachedProof> {
+        let latest = self.latest.as_ref()?;
+        if latest.message_count <= self.published_count {
+            return None;
+        }
+        self.published_count = latest.message_count;
+        Some(latest.clone())
+    }
+}
@@ -0,0 +1,497 @@
+use crate::{Message, Result, ZkChatError};
+use base64::{engine::general_purpose, Engine as _};
+use p256::ecdsa::{signature::{Signer, Verifier}, Signature, SigningKey, VerifyingKey};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use super::{
+    air::{message_index, GlobalId, PublicInputs},
+    compression::{decompress_proof, CachedProof},
+    digest::DIGEST_BYTES,
+    field::element,
+    merkle::{MerklePath, MerkleTree},
+    prover::{verify_proof, ProofProfile},
+    Digest,
+};
+
+/// Domain separator for signed chain heads
+const HEAD_SIGNATURE_DOMAIN: &[u8] = b"zk-chat head v2";
+
+/// Number of message counts a `ForkMonitor` remembers heads for
+pub const FORK_MONITOR_WINDOW: usize = 1024;
+
+fn inconsistency(what: String) -> ZkChatError {
+    ZkChatError::ChainInconsistency(what)
+}
+
+/// Client-side mirror of the server's message chain.
+/// Recomputes the chain head from broadcasts and checks it against the heads the server publishes.
+#[derive(Debug, Clone)]
+pub struct ChainMirror {
+    salt: u64,
+    message_count: usize,
+    head: Digest,
+    last_timestamp: Option<u64>,
+}
+
+impl ChainMirror {
+    /// Mirror a chain from its start, under the server's session salt
+    pub fn new(salt: u64) -> Self {
+        Self::resume(salt, 0, Digest::ZERO)
+    }
+
+    /// Mirror a chain from a known head (e.g. when joining mid-session)
+    pub fn resume(salt: u64, message_count: usize, head: Digest) -> Self {
+        Self { salt, message_count, head, last_timestamp: None }
+    }
+
+    /// Append one broadcast message, checking its hash and timestamp order
+    pub fn apply(&mut self, message: &Message) -> Result<()> {
+        if !message.verify_hash() {
+            return Err(ZkChatError::InvalidMessageHash);
+        }
+        if self.last_timestamp.is_some_and(|last| message.timestamp <= last) {
+            return Err(ZkChatError::InvalidTimestamp);
+        }
+        self.head = message.hash_id.hasher().chain_step(&self.head, &message.hash, element(self.salt));
+        self.message_count += 1;
+        self.last_timestamp = Some(message.timestamp);
+        Ok(())
+    }
+
+    /// Apply a proven batch ending at `message_count` and check the server's published head.
+    /// Messages the mirror already covers are skipped; a gap means broadcasts were missed.
+    pub fn apply_batch(&mut self, messages: &[Message], message_count: usize, final_hash: &Digest) -> Result<()> {
+        let first_position = message_count.checked_sub(messages.len())
+            .ok_or_else(|| inconsistency(format!("batch of {} messages cannot end at {}", messages.len(), message_count)))?;
+        if first_position > self.message_count {
+            return Err(inconsistency(format!(
+                "missed messages {}..{} before batch", self.message_count + 1, first_position + 1
+            )));
+        }
+        for message in &messages[self.message_count.saturating_sub(first_position).min(messages.len())..] {
+            self.apply(message)?;
+        }
+        if self.message_count == message_count && &self.head != final_hash {
+            return Err(inconsistency(format!(
+                "server head {} differs from mirrored head {} at {} messages",
+                final_hash, self.head, message_count
+            )));
+        }
+        Ok(())
+    }
+
+    /// Apply a batch covered by a server `ChainProof`. The proof must start at the mirrored head and
+    /// verify against the segment's public inputs as recomputed from the batch; otherwise nothing is applied.
+    pub fn apply_proven_batch(&mut self, messages: &[Message], proof: &CachedProof, min_profile: ProofProfile) -> Result<()> {
+        if proof.start_count != self.message_count || proof.initial_hash != self.head {
+            return Err(inconsistency(format!(
+                "proof starts at {} messages ({}), mirror holds {} ({})",
+                proof.start_count, proof.initial_hash, self.message_count, self.head
+            )));
+        }
+        let hash_id = match messages.first() {
+            Some(first) if proof.message_count == self.message_count + messages.len() => first.hash_id,
+            _ => return Err(inconsistency(format!(
+                "proof ends at {} messages but the batch holds {} after {}",
+                proof.message_count, messages.len(), self.message_count
+            ))),
+        };
+        // A mirror resumed from a `ChainState` has not seen the last timestamp; the proof asserts it
+        let initial_timestamp = match self.last_timestamp {
+            Some(timestamp) => timestamp,
+            None if self.message_count == 0 => 0,
+            None => proof.initial_timestamp,
+        };
+
+        let mut extended = self.clone();
+        extended.last_timestamp = Some(initial_timestamp).filter(|_| self.message_count > 0);
+        for message in messages {
+            if message.hash_id != hash_id {
+                return Err(ZkChatError::HashMismatch { expected: hash_id, found: message.hash_id });
+            }
+            extended.apply(message)?;
+        }
+        if extended.head != proof.final_hash {
+            return Err(inconsistency(format!(
+                "server head {} differs from mirrored head {} at {} messages",
+                proof.final_hash, extended.head, proof.message_count
+            )));
+        }
+
+        let leaves: Vec<Digest> = messages.iter().map(|m| m.hash).collect();
+        let pub_inputs = PublicInputs {
+            initial_hash: self.head,
+            initial_timestamp,
+            final_hash: extended.head,
+            final_timestamp: extended.last_timestamp.unwrap_or(initial_timestamp),
+            message_count: messages.len(),
+            message_root: MerkleTree::new(&leaves).root(),
+            hash_id,
+            salt: self.salt,
+            message_index: message_index(messages.iter().map(GlobalId::of)),
+        };
+        verify_proof(&decompress_proof(&proof.proof)?, pub_inputs, min_profile)?;
+        *self = extended;
+        Ok(())
+    }
+
+    /// Session salt the mirror chains under
+    pub fn salt(&self) -> u64 {
+        self.salt
+    }
+
+    /// Number of mirrored messages
+    pub fn message_count(&self) -> usize {
+        self.message_count
+    }
+
+    /// Current mirrored chain head
+    pub fn head(&self) -> Digest {
+        self.head
+    }
+}
+
+/// ECDSA P-256 key a client signs its observed chain heads with
+pub struct HeadSigner {
+    key: SigningKey,
+}
+
+impl HeadSigner {
+    /// Generate a fresh signing key
+    pub fn generate() -> Self {
+        Self { key: SigningKey::random(&mut OsRng) }
+    }
+
+    /// Verifying key as base64 SEC1 uncompressed point (WebCrypto "raw" format)
+    pub fn public_key(&self) -> String {
+        general_purpose::STANDARD.encode(self.key.verifying_key().to_encoded_point(false).as_bytes())
+    }
+
+    /// Sign the head `user_id` observed after `message_count` messages of the chain under `salt`
+    pub fn sign(&self, user_id: u64, salt: u64, message_count: usize, chain_hash: Digest) -> SignedHead {
+        let signature: Signature = self.key.sign(&head_signing_bytes(user_id, salt, message_count, &chain_hash));
+        SignedHead {
+            user_id,
+            salt,
+            message_count,
+            chain_hash,
+            public_key: self.public_key(),
+            signature: general_purpose::STANDARD.encode(signature.to_bytes()),
+        }
+    }
+}
+
+// Domain || user id || salt || message count || chain hash
+fn head_signing_bytes(user_id: u64, salt: u64, message_count: usize, chain_hash: &Digest) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEAD_SIGNATURE_DOMAIN.len() + 24 + DIGEST_BYTES);
+    bytes.extend_from_slice(HEAD_SIGNATURE_DOMAIN);
+    bytes.extend_from_slice(&user_id.to_le_bytes());
+    bytes.extend_from_slice(&salt.to_le_bytes());
+    bytes.extend_from_slice(&(message_count as u64).to_le_bytes());
+    bytes.extend_from_slice(&chain_hash.to_bytes());
+    bytes
+}
+
+/// Chain head a client observed, signed with its head signing key (ECDSA P-256 / SHA-256, r || s).
+/// The session salt names the epoch: heads of different epochs belong to different chains.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedHead {
+    pub user_id: u64,
+    #[serde(with = "crate::u64_string")]
+    pub salt: u64,
+    pub message_count: usize,
+    pub chain_hash: Digest,
+    pub public_key: String,
+    pub signature: String,
+}
+
+impl SignedHead {
+    /// Check the signature against the embedded public key
+    pub fn verify(&self) -> bool {
+        let key = general_purpose::STANDARD.decode(&self.public_key).ok()
+            .and_then(|bytes| VerifyingKey::from_sec1_bytes(&bytes).ok());
+        let signature = general_purpose::STANDARD.decode(&self.signature).ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok());
+        match (key, signature) {
+            (Some(key), Some(signature)) => key
+                .verify(&head_signing_bytes(self.user_id, self.salt, self.message_count, &self.chain_hash), &signature)
+                .is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// Two validly signed heads for the same message count that disagree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForkEvidence {
+    pub first: SignedHead,
+    pub second: SignedHead,
+}
+
+impl ForkEvidence {
+    /// Check that both heads are signed, cover the same count of the same epoch and differ
+    pub fn verify(&self) -> bool {
+        self.first.verify()
+            && self.second.verify()
+            && self.first.salt == self.second.salt
+            && self.first.message_count == self.second.message_count
+            && self.first.chain_hash != self.second.chain_hash
+    }
+}
+
+/// Collects signed heads gossiped between clients and detects divergent histories.
+/// Signing keys are pinned per user on first use; heads are compared only within one epoch (salt).
+#[derive(Debug, Clone, Default)]
+pub struct ForkMonitor {
+    pinned_keys: HashMap<u64, String>,
+    heads: BTreeMap<(u64, usize), SignedHead>,
+}
+
+impl ForkMonitor {
+    /// Create an empty monitor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a signed head; returns evidence if it conflicts with a head already seen for the same count
+    pub fn observe(&mut self, head: SignedHead) -> Result<Option<ForkEvidence>> {
+        if !head.verify() {
+            return Err(inconsistency(format!("invalid head signature from user {}", head.user_id)));
+        }
+        let pinned = self.pinned_keys.entry(head.user_id).or_insert_with(|| head.public_key.clone());
+        if *pinned != head.public_key {
+            return Err(inconsistency(format!("head signing key changed for user {}", head.user_id)));
+        }
+
+        let position = (head.salt, head.message_count);
+        if let Some(seen) = self.heads.get(&position) {
+            if seen.chain_hash != head.chain_hash {
+                return Ok(Some(ForkEvidence { first: seen.clone(), second: head }));
+            }
+            return Ok(None);
+        }
+        self.heads.insert(position, head);
+        while self.heads.len() > FORK_MONITOR_WINDOW {
+            self.heads.pop_first();
+        }
+        Ok(None)
+    }
+
+    /// Forget observed heads when the server starts a new epoch; pinned keys are kept
+    pub fn new_epoch(&mut self) {
+        self.heads.clear();
+    }
+
+    /// Head recorded for `message_count` under `salt`, if any
+    pub fn head_at(&self, salt: u64, message_count: usize) -> Option<&SignedHead> {
+        self.heads.get(&(salt, message_count))
+    }
+}
+
+/// Domain separator for server checkpoints
+const CHECKPOINT_SIGNATURE_DOMAIN: &[u8] = b"zk-chat checkpoint v2";
+
+/// Domain separator for signed epoch roots
+const EPOCH_SIGNATURE_DOMAIN: &[u8] = b"zk-chat epoch v1";
+
+// Check an ECDSA signature (base64 r || s) over `bytes` against a base64 SEC1 server public key
+fn verify_server_signature(server_public_key: &str, bytes: &[u8], signature: &str, what: String) -> Result<()> {
+    let key = general_purpose::STANDARD.decode(server_public_key).ok()
+        .and_then(|bytes| VerifyingKey::from_sec1_bytes(&bytes).ok())
+        .ok_or_else(|| inconsistency("invalid server public key".into()))?;
+    let signature = general_purpose::STANDARD.decode(signature).ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or_else(|| inconsistency(format!("malformed signature on {}", what)))?;
+    key.verify(bytes, &signature)
+        .map_err(|_| inconsistency(format!("{} not signed by pinned server key", what)))
+}
+
+/// Server's long-term ECDSA P-256 key for signing chain checkpoints
+pub struct ServerSigningKey {
+    key: SigningKey,
+}
+
+impl ServerSigningKey {
+    /// Generate a fresh signing key
+    pub fn generate() -> Self {
+        Self { key: SigningKey::random(&mut OsRng) }
+    }
+
+    /// Load the hex-encoded secret key at `path`, creating it on first start
+    pub fn load_or_create(path: &std::path::Path) -> Result<Self> {
+        if path.exists() {
+            let encoded = std::fs::read_to_string(path)?;
+            let bytes = hex::decode(encoded.trim())
+                .map_err(|_| inconsistency(format!("signing key {} is not hex", path.display())))?;
+            let key = SigningKey::from_slice(&bytes)
+                .map_err(|_| inconsistency(format!("signing key {} is invalid", path.display())))?;
+            return Ok(Self { key });
+        }
+        let signer = Self::generate();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, hex::encode(signer.key.to_bytes()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(signer)
+    }
+
+    /// Verifying key as base64 SEC1 uncompressed point; clients pin this
+    pub fn public_key(&self) -> String {
+        general_purpose::STANDARD.encode(self.key.verifying_key().to_encoded_point(false).as_bytes())
+    }
+
+    /// Sign the chain head and message root after `message_count` messages
+    pub fn sign(&self, message_count: usize, chain_hash: Digest, message_root: Digest, salt: u64, proof_ref: Option<usize>) -> SignedCheckpoint {
+        let mut checkpoint = SignedCheckpoint { message_count, chain_hash, message_root, salt, proof_ref, signature: String::new() };
+        let signature: Signature = self.key.sign(&checkpoint.signing_bytes());
+        checkpoint.signature = general_purpose::STANDARD.encode(signature.to_bytes());
+        checkpoint
+    }
+
+    /// Sign the Merkle root over an epoch's chain heads (see `zk::epoch`)
+    pub fn sign_epoch(&self, epoch: u64, epoch_root: Digest) -> SignedEpochRoot {
+        let mut signed = SignedEpochRoot { epoch, epoch_root, signature: String::new() };
+        let signature: Signature = self.key.sign(&signed.signing_bytes());
+        signed.signature = general_purpose::STANDARD.encode(signature.to_bytes());
+        signed
+    }
+}
+
+/// Server-signed statement of the chain head and message root at a message count
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedCheckpoint {
+    pub message_count: usize,
+    pub chain_hash: Digest,
+    /// Merkle root over the hashes of the first `message_count` messages
+    pub message_root: Digest,
+    #[serde(with = "crate::u64_string")]
+    pub salt: u64,
+    /// `message_count` of the latest `ChainProof` backing this head
+    pub proof_ref: Option<usize>,
+    pub signature: String,
+}
+
+impl SignedCheckpoint {
+    // Domain || message count || chain hash || message root || salt || proof ref (presence flag + count)
+    fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(CHECKPOINT_SIGNATURE_DOMAIN.len() + 25 + 2 * DIGEST_BYTES);
+        bytes.extend_from_slice(CHECKPOINT_SIGNATURE_DOMAIN);
+        bytes.extend_from_slice(&(self.message_count as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.chain_hash.to_bytes());
+        bytes.extend_from_slice(&self.message_root.to_bytes());
+        bytes.extend_from_slice(&self.salt.to_le_bytes());
+        bytes.push(self.proof_ref.is_some() as u8);
+        bytes.extend_from_slice(&(self.proof_ref.unwrap_or(0) as u64).to_le_bytes());
+        bytes
+    }
+
+    /// Check the signature against the pinned server public key
+    pub fn verify(&self, server_public_key: &str) -> Result<()> {
+        verify_server_signature(server_public_key, &self.signing_bytes(), &self.signature, format!("checkpoint at {} messages", self.message_count))
+    }
+}
+
+/// Server-signed Merkle root over the chain heads committed into an epoch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedEpochRoot {
+    pub epoch: u64,
+    pub epoch_root: Digest,
+    pub signature: String,
+}
+
+impl SignedEpochRoot {
+    // Domain || epoch || epoch root
+    fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(EPOCH_SIGNATURE_DOMAIN.len() + 8 + DIGEST_BYTES);
+        bytes.extend_from_slice(EPOCH_SIGNATURE_DOMAIN);
+        bytes.extend_from_slice(&self.epoch.to_le_bytes());
+        bytes.extend_from_slice(&self.epoch_root.to_bytes());
+        bytes
+    }
+
+    /// Check the signature against the pinned server public key
+    pub fn verify(&self, server_public_key: &str) -> Result<()> {
+        verify_server_signature(server_public_key, &self.signing_bytes(), &self.signature, format!("epoch {} root", self.epoch))
+    }
+}
+
+/// Verifies server checkpoints against a pinned key and catches the server contradicting itself
+#[derive(Debug, Clone)]
+pub struct CheckpointVerifier {
+    server_public_key: String,
+    salt: Option<u64>,
+    latest: Option<SignedCheckpoint>,
+    // Last checkpoint accepted under each earlier session salt
+    previous_epochs: Vec<SignedCheckpoint>,
+}
+
+impl CheckpointVerifier {
+    /// Pin the server's public key
+    pub fn new(server_public_key: impl Into<String>) -> Self {
+        Self { server_public_key: server_public_key.into(), salt: None, latest: None, previous_epochs: Vec::new() }
+    }
+
+    /// Verify a checkpoint: pinned signature, unchanged salt, and no rewinding or rewriting of the head.
+    /// The epoch switches only on a signed checkpoint of an empty chain under a salt not seen before.
+    pub fn check(&mut self, checkpoint: &SignedCheckpoint) -> Result<()> {
+        checkpoint.verify(&self.server_public_key)?;
+        if self.salt.is_some_and(|salt| salt != checkpoint.salt) {
+            let fresh_chain = checkpoint.message_count == 0 && checkpoint.chain_hash == Digest::ZERO;
+            let earlier_salt = self.previous_epochs.iter().any(|previous| previous.salt == checkpoint.salt);
+            if !fresh_chain || earlier_salt {
+                return Err(inconsistency("server changed its session salt".into()));
+            }
+            self.previous_epochs.extend(self.latest.take());
+        }
+        if let Some(latest) = &self.latest {
+            if checkpoint.message_count < latest.message_count {
+                return Err(inconsistency(format!(
+                    "server rewound from {} to {} messages", latest.message_count, checkpoint.message_count
+                )));
+            }
+            let same_count = checkpoint.message_count == latest.message_count;
+            if same_count && (checkpoint.chain_hash != latest.chain_hash || checkpoint.message_root != latest.message_root) {
+                return Err(inconsistency(format!("server signed two heads at {} messages", checkpoint.message_count)));
+            }
+        }
+        self.salt = Some(checkpoint.salt);
+        self.latest = Some(checkpoint.clone());
+        Ok(())
+    }
+
+    /// Latest accepted checkpoint
+    pub fn latest(&self) -> Option<&SignedCheckpoint> {
+        self.latest.as_ref()
+    }
+
+    /// Final checkpoints of earlier epochs, oldest first
+    pub fn previous_epochs(&self) -> &[SignedCheckpoint] {
+        &self.previous_epochs
+    }
+}
+
+/// Inclusion of one message hash under a server-signed head, disclosing neither the message nor the rest of the chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedInclusionProof {
+    pub leaf: Digest,
+    pub path: MerklePath,
+    pub message_root: Digest,
+    pub checkpoint: SignedCheckpoint,
+}
+
+impl SignedInclusionProof {
+    /// Check the checkpoint against the pinned server key, and the path from `leaf` to the root it signs
+    pub fn verify(&self, server_public_key: &str) -> Result<()> {
+        self.checkpoint.verify(server_public_key)?;
+        if self.checkpoint.message_root != self.message_root || !self.path.verify(&self.message_root, &self.leaf) {
+            return Err(inconsistency(format!("{} is not included under the signed root", self.leaf)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use super::field::{element_from_bytes, element_to_bytes, BaseElement, ELEMENT_BYTES};
+use crate::{Result, ZkChatError};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use winterfell::math::FieldElement;
+
+/// Bytes of a digest's canonical encoding: 64 over f128, 32 over f64
+pub const DIGEST_BYTES: usize = 4 * ELEMENT_BYTES;
+
+/// Hash output as its four field elements; serialized as the hex of its canonical encoding
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Digest([BaseElement; 4]);
+
+impl Digest {
+    /// The all-zero digest: head of the empty chain and Merkle padding leaf
+    pub const ZERO: Self = Self([BaseElement::ZERO; 4]);
+
+    /// Wrap a hash output
+    pub fn new(elements: [BaseElement; 4]) -> Self {
+        Self(elements)
+    }
+
+    /// The digest's field elements, as absorbed by further hashing and the trace
+    pub fn elements(&self) -> [BaseElement; 4] {
+        self.0
+    }
+
+    /// Canonical encoding: each element's value in `ELEMENT_BYTES` little-endian bytes
+    pub fn to_bytes(&self) -> [u8; DIGEST_BYTES] {
+        let mut bytes = [0u8; DIGEST_BYTES];
+        for (chunk, e) in bytes.chunks_mut(ELEMENT_BYTES).zip(self.0) {
+            chunk.copy_from_slice(&element_to_bytes(e));
+        }
+        bytes
+    }
+
+    /// Decode `to_bytes`, rejecting other lengths and values not below the modulus
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != DIGEST_BYTES {
+            return Err(ZkChatError::InvalidDigest(format!("expected {} bytes, got {}", DIGEST_BYTES, bytes.len())));
+        }
+        let mut elements = [BaseElement::ZERO; 4];
+        for (e, chunk) in elements.iter_mut().zip(bytes.chunks(ELEMENT_BYTES)) {
+            *e = element_from_bytes(chunk)
+                .ok_or_else(|| ZkChatError::InvalidDigest("element not below the field modulus".into()))?;
+        }
+        Ok(Self(elements))
+    }
+}
+
+impl Default for Digest {
+    fn default() -> Self {
+        Self::ZERO
+    }
+}
+
+impl std::hash::Hash for Digest {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.to_bytes().hash(state);
+    }
+}
+
+impl std::fmt::Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&hex::encode(self.to_bytes()))
+    }
+}
+
+impl std::fmt::Debug for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Digest({})", self)
+    }
+}
+
+impl std::str::FromStr for Digest {
+    type Err = ZkChatError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::from_bytes(&hex::decode(s).map_err(|e| ZkChatError::InvalidDigest(e.to_string()))?)
+    }
+}
+
+impl From<[BaseElement; 4]> for Digest {
+    fn from(elements: [BaseElement; 4]) -> Self {
+        Self(elements)
+    }
+}
+
+impl Serialize for Digest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Digest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
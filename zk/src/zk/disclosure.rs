@@ -0,0 +1,168 @@
+use super::{
+    air::{message_index, timestamp_follows, GlobalId, MessageRow, PublicInputs},
+    bytes_to_elements,
+    hasher::HashId,
+    merkle::MerkleTree,
+    prover::{check_hash_id, verify_proof, ProofProfile},
+    segment::{verify_segments, ChainBoundary, ComposedHistory, SegmentProof},
+    session_salt, session_salt_element,
+    field::{element, CONTENT_WORD_BYTES},
+    hash::pack_word,
+    zk_hash, Digest,
+};
+use crate::{Message, Result, ZkChatError};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// Fresh random salt for a content commitment
+pub fn random_salt() -> [u8; 32] {
+    let mut salt = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut salt);
+    salt
+}
+
+/// Salted commitment to the full message content: Poseidon(salt || length || content chunks)
+pub fn commit_content(content: &str, salt: &[u8; 32]) -> Digest {
+    let bytes = content.as_bytes();
+    let mut inputs = Vec::with_capacity(5 + bytes.len().div_ceil(CONTENT_WORD_BYTES));
+    inputs.extend_from_slice(&bytes_to_elements(salt));
+    inputs.push(element(bytes.len() as u64));
+    inputs.extend(bytes.chunks(CONTENT_WORD_BYTES).map(pack_word));
+    Digest::new(zk_hash(&inputs))
+}
+
+/// Message with its content replaced by a salted commitment.
+/// Sender and timestamp stay public; `hash` covers them together with the commitment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedactedMessage {
+    pub id: u64,
+    pub sender_id: u64,
+    pub timestamp: u64,
+    pub content_commitment: Digest,
+    pub hash: Digest,
+    #[serde(default)]
+    pub hash_id: HashId,
+}
+
+impl RedactedMessage {
+    /// Redact a message, committing to its content under `salt`
+    pub fn from_message(message: &Message, salt: &[u8; 32]) -> Self {
+        let mut redacted = Self {
+            id: message.id,
+            sender_id: message.sender_id,
+            timestamp: message.timestamp,
+            content_commitment: commit_content(&message.content, salt),
+            hash: Digest::ZERO,
+            hash_id: message.hash_id,
+        };
+        redacted.hash = redacted.compute_hash();
+        redacted
+    }
+
+    /// Global ID of the redacted message, as listed in the published index
+    pub fn global_id(&self) -> GlobalId {
+        GlobalId { sender_id: self.sender_id, id: self.id }
+    }
+
+    /// Trace row inputs: id, sender, timestamp and the content commitment
+    pub fn row(&self) -> MessageRow {
+        let commitment = self.content_commitment.elements();
+        [
+            element(self.id),
+            element(self.sender_id),
+            element(self.timestamp),
+            commitment[0],
+            commitment[1],
+            commitment[2],
+            commitment[3],
+        ]
+    }
+
+    /// Hash over the public metadata and the content commitment, with the message's hasher
+    pub fn compute_hash(&self) -> Digest {
+        self.hash_id.hasher().message_hash(&self.row())
+    }
+
+    /// Verify the hash of this redacted message
+    pub fn verify_hash(&self) -> bool {
+        self.compute_hash() == self.hash
+    }
+
+    /// Check that `content` and `salt` open this message's commitment
+    pub fn opens_to(&self, content: &str, salt: &[u8; 32]) -> bool {
+        commit_content(content, salt) == self.content_commitment
+    }
+}
+
+/// Redact a chain of messages, one salt per message
+pub fn redact_messages(messages: &[Message], salts: &[[u8; 32]]) -> Result<Vec<RedactedMessage>> {
+    if messages.len() != salts.len() {
+        return Err(ZkChatError::ProofGeneration("Need exactly one salt per redacted message".into()));
+    }
+    Ok(messages.iter().zip(salts).map(|(m, salt)| RedactedMessage::from_message(m, salt)).collect())
+}
+
+/// Public inputs of the redacted chain, recomputed from the redacted messages alone
+pub fn redacted_public_inputs(messages: &[RedactedMessage]) -> Result<PublicInputs> {
+    redacted_segment_public_inputs(&ChainBoundary::GENESIS, messages)
+}
+
+/// Public inputs of redacted messages continuing the chain from `start`
+pub fn redacted_segment_public_inputs(start: &ChainBoundary, messages: &[RedactedMessage]) -> Result<PublicInputs> {
+    let hash_id = messages.first().map(|m| m.hash_id).unwrap_or_default();
+    let mut chain_hash = start.hash;
+    for (i, message) in messages.iter().enumerate() {
+        if !message.verify_hash() {
+            return Err(ZkChatError::InvalidMessageHash);
+        }
+        if i > 0 && !timestamp_follows(messages[i - 1].timestamp, message.timestamp) {
+            return Err(ZkChatError::InvalidTimestamp);
+        }
+        check_hash_id(hash_id, message.hash_id)?;
+        chain_hash = hash_id.hasher().chain_step(&chain_hash, &message.hash, session_salt_element());
+    }
+    let leaves: Vec<Digest> = messages.iter().map(|m| m.hash).collect();
+    Ok(PublicInputs {
+        initial_hash: start.hash,
+        initial_timestamp: start.timestamp,
+        final_hash: chain_hash,
+        final_timestamp: messages.last().map_or(start.timestamp, |m| m.timestamp),
+        message_count: messages.len(),
+        message_root: MerkleTree::new(&leaves).root(),
+        hash_id,
+        salt: session_salt(),
+        message_index: message_index(messages.iter().map(RedactedMessage::global_id)),
+    })
+}
+
+/// Verify a proof from `MessageProver::prove_redacted` against the redacted transcript
+pub fn verify_redacted(proof: &[u8], messages: &[RedactedMessage], min_profile: ProofProfile) -> Result<PublicInputs> {
+    let pub_inputs = redacted_public_inputs(messages)?;
+    verify_proof(proof, pub_inputs.clone(), min_profile)?;
+    Ok(pub_inputs)
+}
+
+/// Verify redacted segment proofs that together cover `messages` from genesis, in order
+pub fn verify_redacted_segments(segments: &[SegmentProof], messages: &[RedactedMessage], min_profile: ProofProfile) -> Result<ComposedHistory> {
+    let mut rest = messages;
+    for (i, segment) in segments.iter().enumerate() {
+        let count = segment.public_inputs.message_count;
+        if count > rest.len() {
+            return Err(ZkChatError::ChainInconsistency(format!("segment {} covers more messages than were disclosed", i)));
+        }
+        let (covered, remaining) = rest.split_at(count);
+        let start = ChainBoundary::start_of(&segment.public_inputs);
+        if redacted_segment_public_inputs(&start, covered)? != segment.public_inputs {
+            return Err(ZkChatError::ChainInconsistency(format!("segment {} does not match the disclosed messages", i)));
+        }
+        rest = remaining;
+    }
+    if !rest.is_empty() {
+        return Err(ZkChatError::ChainInconsistency(format!("{} disclosed messages are not covered by a segment", rest.len())));
+    }
+    let history = verify_segments(segments, min_profile)?;
+    if history.start != ChainBoundary::GENESIS {
+        return Err(ZkChatError::ChainInconsistency("redacted history does not start at genesis".into()));
+    }
+    Ok(history)
+}
@@ -0,0 +1,138 @@
+use super::{
+    bytes_to_elements,
+    consistency::{ServerSigningKey, SignedEpochRoot},
+    field::{element, BaseElement},
+    hasher::HashId,
+    merkle::{MerklePath, MerkleTree},
+    prover::ProofProfile,
+    segment::{verify_segments, ChainBoundary, SegmentProof},
+    zk_hash, Digest,
+};
+use crate::{Result, ZkChatError};
+use serde::{Deserialize, Serialize};
+
+/// Head of one room's chain segment as committed into an epoch, with the boundary the segment starts from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainHead {
+    pub room_id: u64,
+    pub start: ChainBoundary,
+    pub message_count: usize,
+    pub chain_hash: Digest,
+    pub message_root: Digest,
+    #[serde(default)]
+    pub hash_id: HashId,
+}
+
+/// A room's chain segment submitted for aggregation
+#[derive(Debug, Clone)]
+pub struct ChainSegment {
+    pub room_id: u64,
+    pub segment: SegmentProof,
+}
+
+/// Epoch leaf: a chain head bound to the digest of the segment proof it was verified with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EpochEntry {
+    pub head: ChainHead,
+    #[serde(with = "crate::hex_serde")]
+    pub proof_digest: [u8; 32],
+}
+
+impl EpochEntry {
+    fn elements(&self) -> Vec<BaseElement> {
+        let mut elements = Vec::with_capacity(20);
+        elements.push(element(self.head.room_id));
+        elements.extend_from_slice(&self.head.start.hash.elements());
+        elements.push(element(self.head.start.timestamp));
+        elements.push(element(self.head.message_count as u64));
+        elements.extend_from_slice(&self.head.chain_hash.elements());
+        elements.extend_from_slice(&self.head.message_root.elements());
+        elements.extend_from_slice(&bytes_to_elements(&self.proof_digest));
+        elements.push(element(self.head.hash_id.code().into()));
+        elements
+    }
+
+    /// Poseidon leaf hash committed into the epoch Merkle tree
+    pub fn leaf_hash(&self) -> Digest {
+        Digest::new(zk_hash(&self.elements()))
+    }
+}
+
+/// Epoch commitment: a server-signed Merkle root over verified chain heads, shipped with the segments behind them.
+/// Nothing proves the heads together; each is only as good as its segment proof, which `verify_epoch` checks again.
+#[derive(Debug, Clone)]
+pub struct EpochAggregate {
+    pub signed_root: SignedEpochRoot,
+    pub entries: Vec<EpochEntry>,
+    pub segments: Vec<ChainSegment>,
+    tree: MerkleTree,
+}
+
+impl EpochAggregate {
+    /// Epoch root to publish or anchor externally
+    pub fn root(&self) -> Digest {
+        self.signed_root.epoch_root
+    }
+
+    /// Inclusion path for the entry at `index`
+    pub fn inclusion_path(&self, index: usize) -> Option<MerklePath> {
+        self.tree.path(index)
+    }
+}
+
+/// Digest identifying a segment proof inside an epoch entry
+pub fn proof_digest(proof: &[u8]) -> [u8; 32] {
+    *blake3::hash(proof).as_bytes()
+}
+
+/// Verify a segment proof and return the epoch entry it backs, starting where the segment starts
+fn segment_entry(segment: &ChainSegment, min_profile: ProofProfile) -> Result<EpochEntry> {
+    let history = verify_segments(std::slice::from_ref(&segment.segment), min_profile)?;
+    let head = ChainHead {
+        room_id: segment.room_id,
+        start: history.start,
+        message_count: history.message_count,
+        chain_hash: history.end.hash,
+        message_root: segment.segment.public_inputs.message_root,
+        hash_id: history.hash_id,
+    };
+    Ok(EpochEntry { head, proof_digest: proof_digest(&segment.segment.proof) })
+}
+
+fn entry_tree(entries: &[EpochEntry]) -> MerkleTree {
+    let leaves: Vec<Digest> = entries.iter().map(EpochEntry::leaf_hash).collect();
+    MerkleTree::new(&leaves)
+}
+
+/// Verify every segment proof and sign the Merkle root over the resulting heads
+pub fn aggregate_epoch(epoch: u64, segments: &[ChainSegment], signer: &ServerSigningKey, min_profile: ProofProfile) -> Result<EpochAggregate> {
+    if segments.is_empty() {
+        return Err(ZkChatError::ProofGeneration("Epoch has no chain segments".into()));
+    }
+
+    // Only heads backed by a verified chain segment enter the epoch
+    let entries = segments.iter().map(|segment| segment_entry(segment, min_profile)).collect::<Result<Vec<_>>>()?;
+    let tree = entry_tree(&entries);
+    let signed_root = signer.sign_epoch(epoch, tree.root());
+    Ok(EpochAggregate { signed_root, entries, segments: segments.to_vec(), tree })
+}
+
+/// Verify an epoch: the root must carry the pinned server signature and match the entries,
+/// and every entry must be backed by its segment's verified proof, one segment per entry, in order
+pub fn verify_epoch(signed_root: &SignedEpochRoot, entries: &[EpochEntry], segments: &[ChainSegment], server_public_key: &str, min_profile: ProofProfile) -> Result<()> {
+    signed_root.verify(server_public_key)?;
+    if entries.is_empty() || entries.len() != segments.len() || entry_tree(entries).root() != signed_root.epoch_root {
+        return Err(ZkChatError::ProofVerificationFailed);
+    }
+    for (entry, segment) in entries.iter().zip(segments) {
+        if segment_entry(segment, min_profile)? != *entry {
+            return Err(ZkChatError::ProofVerificationFailed);
+        }
+    }
+    Ok(())
+}
+
+/// Check that a chain head (with its proof digest) is included under an epoch root
+pub fn verify_head_inclusion(epoch_root: &Digest, entry: &EpochEntry, path: &MerklePath) -> bool {
+    path.verify(epoch_root, &entry.leaf_hash())
+}
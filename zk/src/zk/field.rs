@@ -0,0 +1,113 @@
+use winterfell::math::StarkField;
+
+#[cfg(not(feature = "f64"))]
+pub use winterfell::math::fields::f128::BaseElement;
+#[cfg(feature = "f64")]
+pub use winterfell::math::fields::f64::BaseElement;
+
+/// Merkle and Fiat-Shamir hash for proofs: Blake3 over f128
+#[cfg(not(feature = "f64"))]
+pub type HashFn = winterfell::crypto::hashers::Blake3_256<BaseElement>;
+/// Merkle and Fiat-Shamir hash for proofs: algebraic Rescue-Prime over f64
+#[cfg(feature = "f64")]
+pub type HashFn = winterfell::crypto::hashers::Rp64_256;
+
+/// Field extension used by every proof profile; 64-bit fields need the quadratic extension for soundness
+#[cfg(not(feature = "f64"))]
+pub const FIELD_EXTENSION: winterfell::FieldExtension = winterfell::FieldExtension::None;
+#[cfg(feature = "f64")]
+pub const FIELD_EXTENSION: winterfell::FieldExtension = winterfell::FieldExtension::Quadratic;
+
+/// Name of the selected backend, as reported by benchmarks
+#[cfg(not(feature = "f64"))]
+pub const NAME: &str = "f128-blake3";
+#[cfg(feature = "f64")]
+pub const NAME: &str = "f64-rp64_256";
+
+/// Field modulus, widened to `u128`
+#[cfg(not(feature = "f64"))]
+pub const MODULUS: u128 = BaseElement::MODULUS;
+#[cfg(feature = "f64")]
+pub const MODULUS: u128 = BaseElement::MODULUS as u128;
+
+/// Bits needed to write the modulus
+pub const MODULUS_BITS: u32 = u128::BITS - MODULUS.leading_zeros();
+
+/// Bytes of an element's canonical little-endian encoding
+#[cfg(not(feature = "f64"))]
+pub const ELEMENT_BYTES: usize = 16;
+#[cfg(feature = "f64")]
+pub const ELEMENT_BYTES: usize = 8;
+
+/// Content bytes packed per field element; 7 bytes always fit below the 64-bit modulus
+#[cfg(not(feature = "f64"))]
+pub const CONTENT_WORD_BYTES: usize = 8;
+#[cfg(feature = "f64")]
+pub const CONTENT_WORD_BYTES: usize = 7;
+
+/// A `u64` as a field element, reduced modulo the field's modulus
+pub fn element(value: u64) -> BaseElement {
+    #[cfg(not(feature = "f64"))]
+    {
+        BaseElement::from(value)
+    }
+    #[cfg(feature = "f64")]
+    {
+        BaseElement::new(value)
+    }
+}
+
+/// A `u128` below `MODULUS` as a field element
+pub fn element_from_u128(value: u128) -> BaseElement {
+    debug_assert!(value < MODULUS);
+    #[cfg(not(feature = "f64"))]
+    {
+        BaseElement::new(value)
+    }
+    #[cfg(feature = "f64")]
+    {
+        BaseElement::new(value as u64)
+    }
+}
+
+/// The canonical value of an element
+pub fn element_to_u128(element: BaseElement) -> u128 {
+    #[cfg(not(feature = "f64"))]
+    {
+        element.as_int()
+    }
+    #[cfg(feature = "f64")]
+    {
+        u128::from(element.as_int())
+    }
+}
+
+/// Low 64 bits of an element: the low half for f128, the whole (canonical) element for f64
+pub fn element_to_u64(element: BaseElement) -> u64 {
+    #[cfg(not(feature = "f64"))]
+    {
+        (element.as_int() % (1u128 << 64)) as u64
+    }
+    #[cfg(feature = "f64")]
+    {
+        element.as_int()
+    }
+}
+
+/// Canonical little-endian encoding of an element in `ELEMENT_BYTES` bytes
+pub fn element_to_bytes(element: BaseElement) -> [u8; ELEMENT_BYTES] {
+    let mut bytes = [0u8; ELEMENT_BYTES];
+    bytes.copy_from_slice(&element_to_u128(element).to_le_bytes()[..ELEMENT_BYTES]);
+    bytes
+}
+
+/// Decode `element_to_bytes`; `None` for other lengths and values not below the modulus
+pub fn element_from_bytes(bytes: &[u8]) -> Option<BaseElement> {
+    if bytes.len() != ELEMENT_BYTES {
+        return None;
+    }
+    let mut word = [0u8; 16];
+    word[..ELEMENT_BYTES].copy_from_slice(bytes);
+    let value = u128::from_le_bytes(word);
+    (value < MODULUS).then(|| element_from_u128(value))
+}
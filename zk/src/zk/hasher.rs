@@ -0,0 +1,196 @@
+use super::{
+    air::MessageRow,
+    bytes_to_elements,
+    digest::Digest,
+    field::{element_to_u128, BaseElement},
+    poseidon,
+};
+use crate::{Result, ZkChatError};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
+use winterfell::crypto::{hashers::Rp64_256, ElementHasher};
+
+static DEFAULT_HASH: AtomicU8 = AtomicU8::new(HashId::DEFAULT_CODE);
+
+/// Hash a message or chain was built with, recorded in messages and public inputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HashId {
+    /// Sponge over `zk::poseidon` without length separation; kept so existing chains still verify
+    Poseidon,
+    /// Rescue-Prime (`Rp64_256`)
+    RescuePrime,
+    /// Blake3 over the elements' canonical bytes
+    Blake3,
+    /// Sponge over `zk::poseidon` with the input length in its capacity; the default
+    #[default]
+    PoseidonSeparated,
+}
+
+impl HashId {
+    const DEFAULT_CODE: u8 = 4;
+
+    /// Code bound into the proof's public inputs
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Poseidon => 1,
+            Self::RescuePrime => 2,
+            Self::Blake3 => 3,
+            Self::PoseidonSeparated => 4,
+        }
+    }
+
+    /// Hash with the given code
+    pub fn from_code(code: u8) -> Option<Self> {
+        [Self::Poseidon, Self::RescuePrime, Self::Blake3, Self::PoseidonSeparated].into_iter().find(|id| id.code() == code)
+    }
+
+    /// Whether the message AIR evaluates this hash in-circuit, so chains built with it can be proven;
+    /// only the Poseidon sponges can be, the others remain available for hashing alone
+    pub fn provable(self) -> bool {
+        matches!(self, Self::Poseidon | Self::PoseidonSeparated)
+    }
+
+    /// The implementation of this hash
+    pub fn hasher(self) -> &'static dyn ChainHasher {
+        match self {
+            Self::Poseidon => &PoseidonHasher,
+            Self::RescuePrime => &RescuePrimeHasher,
+            Self::Blake3 => &Blake3Hasher,
+            Self::PoseidonSeparated => &PoseidonSeparatedHasher,
+        }
+    }
+}
+
+impl std::fmt::Display for HashId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Poseidon => "poseidon",
+            Self::RescuePrime => "rescue-prime",
+            Self::Blake3 => "blake3",
+            Self::PoseidonSeparated => "poseidon-separated",
+        })
+    }
+}
+
+impl std::str::FromStr for HashId {
+    type Err = ZkChatError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "poseidon" => Ok(Self::Poseidon),
+            "rescue-prime" | "rescue" | "rp64-256" => Ok(Self::RescuePrime),
+            "blake3" => Ok(Self::Blake3),
+            "poseidon-separated" => Ok(Self::PoseidonSeparated),
+            _ => Err(ZkChatError::Config(format!("unknown hash {:?} (expected poseidon-separated, poseidon, rescue-prime or blake3)", s))),
+        }
+    }
+}
+
+/// Hash used for new messages and chains in this process
+pub fn default_hash() -> HashId {
+    HashId::from_code(DEFAULT_HASH.load(Ordering::SeqCst)).unwrap_or_default()
+}
+
+/// Pick the hash for messages and chains created from now on; existing ones keep theirs
+pub fn set_default_hash(id: HashId) {
+    DEFAULT_HASH.store(id.code(), Ordering::SeqCst);
+}
+
+/// Hash behind message hashes and chain steps
+pub trait ChainHasher: Send + Sync {
+    /// Identifier recorded next to everything hashed with this
+    fn id(&self) -> HashId;
+
+    /// Hash field elements to a 4-element digest
+    fn hash_elements(&self, inputs: &[BaseElement]) -> [BaseElement; 4];
+
+    /// Hash of one message's trace row (id, sender, timestamp, content)
+    fn message_hash(&self, row: &MessageRow) -> Digest {
+        Digest::new(self.hash_elements(row))
+    }
+
+    /// Extend a chain hash by one message hash: H(prev || message_hash || session salt)
+    fn chain_step(&self, prev_chain_hash: &Digest, message_hash: &Digest, salt: BaseElement) -> Digest {
+        let mut inputs = Vec::with_capacity(9);
+        inputs.extend_from_slice(&prev_chain_hash.elements());
+        inputs.extend_from_slice(&message_hash.elements());
+        inputs.push(salt);
+        Digest::new(self.hash_elements(&inputs))
+    }
+}
+
+/// Poseidon sponge without length separation (see `zk::poseidon::poseidon_hash_unseparated`)
+#[derive(Debug, Clone, Copy)]
+pub struct PoseidonHasher;
+
+impl ChainHasher for PoseidonHasher {
+    fn id(&self) -> HashId {
+        HashId::Poseidon
+    }
+
+    fn hash_elements(&self, inputs: &[BaseElement]) -> [BaseElement; 4] {
+        poseidon::poseidon_hash_unseparated(inputs)
+    }
+}
+
+/// Length-separated Poseidon sponge (see `zk::poseidon::poseidon_hash`)
+#[derive(Debug, Clone, Copy)]
+pub struct PoseidonSeparatedHasher;
+
+impl ChainHasher for PoseidonSeparatedHasher {
+    fn id(&self) -> HashId {
+        HashId::PoseidonSeparated
+    }
+
+    fn hash_elements(&self, inputs: &[BaseElement]) -> [BaseElement; 4] {
+        poseidon::poseidon_hash(inputs)
+    }
+}
+
+/// Rescue-Prime over the 64-bit field; f128 elements are absorbed as four 32-bit limbs
+#[derive(Debug, Clone, Copy)]
+pub struct RescuePrimeHasher;
+
+impl ChainHasher for RescuePrimeHasher {
+    fn id(&self) -> HashId {
+        HashId::RescuePrime
+    }
+
+    #[cfg(feature = "f64")]
+    fn hash_elements(&self, inputs: &[BaseElement]) -> [BaseElement; 4] {
+        Rp64_256::hash_elements(inputs).into()
+    }
+
+    #[cfg(not(feature = "f64"))]
+    fn hash_elements(&self, inputs: &[BaseElement]) -> [BaseElement; 4] {
+        use winterfell::math::fields::f64;
+        let limbs: Vec<f64::BaseElement> = inputs
+            .iter()
+            .flat_map(|e| {
+                let value = element_to_u128(*e);
+                (0..4).map(move |i| f64::BaseElement::from((value >> (32 * i)) as u32))
+            })
+            .collect();
+        let digest: [f64::BaseElement; 4] = Rp64_256::hash_elements(&limbs).into();
+        digest.map(|d| super::field::element(d.as_int()))
+    }
+}
+
+/// Blake3 over each element's canonical value as 16 little-endian bytes
+#[derive(Debug, Clone, Copy)]
+pub struct Blake3Hasher;
+
+impl ChainHasher for Blake3Hasher {
+    fn id(&self) -> HashId {
+        HashId::Blake3
+    }
+
+    fn hash_elements(&self, inputs: &[BaseElement]) -> [BaseElement; 4] {
+        let mut hasher = blake3::Hasher::new();
+        for e in inputs {
+            hasher.update(&element_to_u128(*e).to_le_bytes());
+        }
+        bytes_to_elements(hasher.finalize().as_bytes())
+    }
+}
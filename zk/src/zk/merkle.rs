@@ -0,0 +1,145 @@
+use super::{zk_hash, Digest};
+use crate::Message;
+use serde::{Deserialize, Serialize};
+
+/// Hash two child nodes into their parent using Poseidon
+pub fn node_hash(left: &Digest, right: &Digest) -> Digest {
+    let mut inputs = Vec::with_capacity(8);
+    inputs.extend_from_slice(&left.elements());
+    inputs.extend_from_slice(&right.elements());
+    Digest::new(zk_hash(&inputs))
+}
+
+/// Binary Poseidon Merkle tree over digest leaves.
+/// Leaves are padded with zero leaves up to the next power of two; the empty tree has a zero root.
+#[derive(Debug, Clone, Default)]
+pub struct MerkleTree {
+    // levels[0] = padded leaves, last level = [root]
+    levels: Vec<Vec<Digest>>,
+    leaf_count: usize,
+}
+
+impl MerkleTree {
+    /// Build a tree over the given leaves
+    pub fn new(leaves: &[Digest]) -> Self {
+        if leaves.is_empty() {
+            return Self::default();
+        }
+        let mut level = leaves.to_vec();
+        level.resize(leaves.len().next_power_of_two(), Digest::ZERO);
+        let mut levels = vec![level];
+        while levels.last().unwrap().len() > 1 {
+            let parents = levels.last().unwrap().chunks(2).map(|pair| node_hash(&pair[0], &pair[1])).collect();
+            levels.push(parents);
+        }
+        Self { levels, leaf_count: leaves.len() }
+    }
+
+    /// Append a leaf, rehashing only the path to the root (the tree doubles when full)
+    pub fn push(&mut self, leaf: Digest) {
+        let index = self.leaf_count;
+        if self.levels.is_empty() {
+            self.levels.push(vec![leaf]);
+            self.leaf_count = 1;
+            return;
+        }
+
+        if index == self.levels[0].len() {
+            // Full: the old tree becomes the left subtree, padded on the right with empty subtrees
+            let mut empty = Digest::ZERO;
+            for level in self.levels.iter_mut() {
+                let width = level.len();
+                level.resize(width * 2, empty);
+                empty = node_hash(&empty, &empty);
+            }
+            // The right half is empty, so every new node already equals the empty-subtree hash
+            let top = self.levels.last().unwrap();
+            let root = node_hash(&top[0], &top[1]);
+            self.levels.push(vec![root]);
+        }
+
+        self.levels[0][index] = leaf;
+        self.leaf_count += 1;
+        let mut position = index;
+        for depth in 1..self.levels.len() {
+            position /= 2;
+            let children = &self.levels[depth - 1];
+            let parent = node_hash(&children[2 * position], &children[2 * position + 1]);
+            self.levels[depth][position] = parent;
+        }
+    }
+
+    /// Root of the tree
+    pub fn root(&self) -> Digest {
+        self.levels.last().map(|level| level[0]).unwrap_or(Digest::ZERO)
+    }
+
+    /// Number of (unpadded) leaves
+    pub fn len(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Check if the tree has no leaves
+    pub fn is_empty(&self) -> bool {
+        self.leaf_count == 0
+    }
+
+    /// Inclusion path for the leaf at `index`
+    pub fn path(&self, index: usize) -> Option<MerklePath> {
+        if index >= self.leaf_count {
+            return None;
+        }
+        let mut siblings = Vec::with_capacity(self.levels.len() - 1);
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            siblings.push(level[position ^ 1]);
+            position /= 2;
+        }
+        Some(MerklePath { index, siblings })
+    }
+}
+
+/// Sibling hashes from a leaf up to the root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerklePath {
+    pub index: usize,
+    pub siblings: Vec<Digest>,
+}
+
+impl MerklePath {
+    /// Recompute the root implied by `leaf` and this path
+    pub fn compute_root(&self, leaf: &Digest) -> Digest {
+        let mut node = *leaf;
+        let mut position = self.index;
+        for sibling in &self.siblings {
+            node = if position & 1 == 0 { node_hash(&node, sibling) } else { node_hash(sibling, &node) };
+            position /= 2;
+        }
+        node
+    }
+
+    /// Check that `leaf` sits at `self.index` in the tree with the given root
+    pub fn verify(&self, root: &Digest, leaf: &Digest) -> bool {
+        self.index >> self.siblings.len() == 0 && &self.compute_root(leaf) == root
+    }
+}
+
+/// Merkle root over the hashes of `messages`, in chain order
+pub fn message_root(messages: &[Message]) -> Digest {
+    let leaves: Vec<Digest> = messages.iter().map(|m| m.hash).collect();
+    MerkleTree::new(&leaves).root()
+}
+
+/// Proof that a single message is part of a chain, revealing only that message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageInclusionProof {
+    pub message: Message,
+    pub path: MerklePath,
+}
+
+impl MessageInclusionProof {
+    /// Check the message hash and its path against the chain's message root
+    pub fn verify(&self, message_root: &Digest) -> bool {
+        self.message.verify_hash() && self.path.verify(message_root, &self.message.hash)
+    }
+}
@@ -1,128 +1,99 @@
 pub mod air;
 pub mod prover;
 pub mod hash;
+pub mod compression;
+pub mod batch;
+pub mod merkle;
+pub mod epoch;
+pub mod disclosure;
+pub mod consistency;
+pub mod storage;
+pub mod field;
+pub mod poseidon;
+pub mod hasher;
+pub mod digest;
+pub mod segment;
 
 use crate::{Message, ZkChatError, Result};
-use winterfell::math::{fields::f128::BaseElement, FieldElement, StarkField};
+pub use digest::Digest;
+pub use poseidon::poseidon_permutation;
+use field::{element, BaseElement};
 use once_cell::sync::Lazy;
 use rand::{RngCore, SeedableRng};
 use rand::rngs::StdRng;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 // Session salt (epoch root) for replay resistance; bound into chain hash inputs.
-pub static SESSION_SALT: Lazy<BaseElement> = Lazy::new(|| {
+// Rotating it starts a new epoch: chains built under the old salt no longer extend.
+static SESSION_SALT: Lazy<AtomicU64> = Lazy::new(|| AtomicU64::new(random_u64()));
+
+fn random_u64() -> u64 {
     let mut rng = StdRng::from_entropy();
     let mut bytes = [0u8; 8];
     rng.fill_bytes(&mut bytes);
-    BaseElement::from(u64::from_le_bytes(bytes))
-});
+    u64::from_le_bytes(bytes)
+}
 
-/// Production-grade ZK-friendly hash function using 60-round Poseidon
+/// ZK-friendly sponge hash (rate 3, capacity 1) over the Grain-generated Poseidon permutation
+#[cfg(not(feature = "f64"))]
 pub fn zk_hash(inputs: &[BaseElement]) -> [BaseElement; 4] {
-    let mut state = [BaseElement::ZERO; 4];
-    
-    // Absorption phase: process all inputs in chunks of 3 (rate = 3, capacity = 1)
-    let mut input_idx = 0;
-    while input_idx < inputs.len() {
-        for i in 0..3 {
-            if input_idx + i < inputs.len() {
-                state[i] = state[i] + inputs[input_idx + i];
-            }
-        }
-        state = poseidon_permutation(state);
-        input_idx += 3;
-    }
-    
-    // Final permutation for security
-    poseidon_permutation(state)
+    poseidon::poseidon_hash(inputs)
 }
 
-/// Enhanced production-grade Poseidon permutation (60-round structure)
-pub fn poseidon_permutation(mut state: [BaseElement; 4]) -> [BaseElement; 4] {
-    const ROUND_CONSTANTS_CYCLE: [[u64; 4]; 20] = [
-        [0x6861759ea556a233, 0x4ef8de4df501ae40, 0x296d6b8ca6ce42c1, 0x2ef38af5a47bd0f4],
-        [0x101071f0032379b6, 0x6625a4a3d5b4a4b6, 0x2d5b2e8f5a4c8b6a, 0x4a8f2d6b9e3c7f1a],
-        [0x3b7f8e2a9d6c4f1e, 0x7e4a1f8d5c2b9a6e, 0x9c6e3f7a1d4c9e6b, 0x1d4c9e6b2f8a5d1c],
-        [0x5c2b9a6e3f1d7c4b, 0x8a5d1c7e4a1f8d5c, 0x6b2f8a5d1c7e4a1f, 0x3d7e6f2c1b4a8d5e],
-        [0xf1d7c4b6a8e2f1c5, 0x1c7e4a1f8d5c2b9a, 0xc1b4a8d5e9c6b2f8, 0x6a8e2f1c5b9a3d7e],
-        [0xf8d5c2b9a6e3f1d7, 0x5e9c6b2f8a5d1c7e, 0xc5b9a3d7e6f2c1b4, 0x9a6e3f1d7c4b6a8e],
-        [0xf8a5d1c7e4a1f8d5, 0x7e6f2c1b4a8d5e9c, 0xd7c4b6a8e2f1c5b9, 0x7e4a1f8d5c2b9a6e],
-        [0xb4a8d5e9c6b2f8a5, 0x8e2f1c5b9a3d7e6f, 0x8d5c2b9a6e3f1d7c, 0xe9c6b2f8a5d1c7e4],
-        [0x5b9a3d7e6f2c1b4a, 0xa6e3f1d7c4b6a8e2, 0x8a5d1c7e4a1f8d5c, 0xe6f2c1b4a8d5e9c6],
-        [0x7c4b6a8e2f1c5b9a, 0xe4a1f8d5c2b9a6e3, 0x4a8d5e9c6b2f8a5d, 0xe2f1c5b9a3d7e6f2],
-        [0x5c2b9a6e3f1d7c4b, 0xc6b2f8a5d1c7e4a1, 0x9a3d7e6f2c1b4a8d, 0xe3f1d7c4b6a8e2f1],
-        [0x5d1c7e4a1f8d5c2b, 0xf2c1b4a8d5e9c6b2, 0x4b6a8e2f1c5b9a3d, 0xa1f8d5c2b9a6e3f1],
-        [0x8c3e5f9b2a6d4e7f, 0x3f7a1d5c8e2b9f6a, 0x9e6b3f8a2d5c1e7b, 0x2d5f8a3e6b9c1f4d],
-        [0x7b4e8a5d2f6c9e3b, 0x6c9e3f7a4d8b5f2c, 0x5f2a8d6e3b9c7f1e, 0x4e7b1f5a8d3c6b9e],
-        [0x1f5d8b3e6c9a7f4b, 0x8e3b6f9c2d5a7e1f, 0x3c7f1e5b8a4d6c2f, 0x6f9d2e5b8c3a7f1d],
-        [0x9c4e7f2a5d8b6f3c, 0x7e1f4b8d5c2a6f9e, 0x2a6f9e3c7b1d5f8a, 0x5d8f3e6b9c4a7f2d],
-        [0x4b7f1e5c8a2d6f9b, 0x1e5f8b3c6a9d4f7e, 0x8f3a6d9e2c5b7f1a, 0x3e6b9f4d7a1c5f8e],
-        [0x6d9f2e5a8c3b7f4d, 0x9e4f7a1d5c8b2f6a, 0x7a1f4e8d6c3b9f5a, 0x2f6a9d3e7b4c1f8d],
-        [0xf4a7e1d5c8b3f6a9, 0x3b7f4e1a5d8c6f2b, 0x1d5f8e3a6c9b4f7d, 0x8c2f6a9e3d7b1f5c],
-        [0xe3d7b1f4a8c5e6b9, 0x5f8a2e6d9c3b7f1e, 0x4a7f1d5e8b2c6f9a, 0x7e1f5c8a4d6b3f7e],
-    ];
-    
-    const NUM_FULL_ROUNDS_START: usize = 6;
-    const NUM_PARTIAL_ROUNDS: usize = 48;
-    const NUM_FULL_ROUNDS_END: usize = 6;
-    
-    let get_rc = |round: usize, element_idx: usize| {
-        BaseElement::from(ROUND_CONSTANTS_CYCLE[round % 20][element_idx])
-    };
-    
-    let apply_sbox = |x: BaseElement| x * x * x;
-    
-    let apply_mds = |state: [BaseElement; 4]| -> [BaseElement; 4] {
-        [
-            state[0] * BaseElement::from(5u64) + state[1] * BaseElement::from(7u64) + state[2] + state[3] * BaseElement::from(3u64),
-            state[0] * BaseElement::from(4u64) + state[1] * BaseElement::from(6u64) + state[2] + state[3],
-            state[0] + state[1] * BaseElement::from(3u64) + state[2] * BaseElement::from(5u64) + state[3] * BaseElement::from(7u64),
-            state[0] + state[1] + state[2] * BaseElement::from(4u64) + state[3] * BaseElement::from(6u64),
-        ]
-    };
-    
-    // First 6 full rounds
-    for round in 0..NUM_FULL_ROUNDS_START {
-        for i in 0..4 { state[i] = state[i] + get_rc(round, i); }
-        for i in 0..4 { state[i] = apply_sbox(state[i]); }
-        state = apply_mds(state);
-    }
-    
-    // 48 partial rounds
-    for round in NUM_FULL_ROUNDS_START..(NUM_FULL_ROUNDS_START + NUM_PARTIAL_ROUNDS) {
-        for i in 0..4 { state[i] = state[i] + get_rc(round, i); }
-        state[0] = apply_sbox(state[0]);
-        state = apply_mds(state);
-    }
-    
-    // Final 6 full rounds
-    let final_start = NUM_FULL_ROUNDS_START + NUM_PARTIAL_ROUNDS;
-    for round in final_start..(final_start + NUM_FULL_ROUNDS_END) {
-        for i in 0..4 { state[i] = state[i] + get_rc(round, i); }
-        for i in 0..4 { state[i] = apply_sbox(state[i]); }
-        state = apply_mds(state);
-    }
-    
-    state
+/// ZK-friendly hash function using Rescue-Prime (Rp64_256), the same hash that commits the proof's traces
+#[cfg(feature = "f64")]
+pub fn zk_hash(inputs: &[BaseElement]) -> [BaseElement; 4] {
+    use winterfell::crypto::{hashers::Rp64_256, ElementHasher};
+    Rp64_256::hash_elements(inputs).into()
 }
 
 /// Represents a sequence of messages with ZK proofs
 #[derive(Debug, Clone)]
 pub struct MessageChain {
     pub messages: Vec<Message>,
-    pub chain_hash: [u8; 32],
+    pub chain_hash: Digest,
+    /// Hash of the chain steps; every message must be hashed with it too
+    pub hash_id: hasher::HashId,
+    /// Boundary the chain continues from; `ChainBoundary::GENESIS` unless it was resumed
+    pub start: segment::ChainBoundary,
+    // Merkle accumulator over message hashes, maintained alongside the linear hash chain
+    message_tree: merkle::MerkleTree,
 }
 
 impl MessageChain {
-    /// Create a new empty message chain
+    /// Create a new empty message chain using the process default hash
     pub fn new() -> Self {
+        Self::with_hash(hasher::default_hash())
+    }
+
+    /// Create a new empty message chain using a specific hash
+    pub fn with_hash(hash_id: hasher::HashId) -> Self {
         Self {
             messages: Vec::new(),
-            chain_hash: [0u8; 32],
+            chain_hash: Digest::ZERO,
+            hash_id,
+            start: segment::ChainBoundary::GENESIS,
+            message_tree: merkle::MerkleTree::default(),
         }
     }
 
+    /// Start a chain segment continuing from `start`, the end of an earlier segment
+    pub fn resume(start: segment::ChainBoundary, hash_id: hasher::HashId) -> Self {
+        Self { chain_hash: start.hash, start, ..Self::with_hash(hash_id) }
+    }
+
+    /// Boundary the next segment continues from: the chain hash and the last timestamp
+    pub fn end(&self) -> segment::ChainBoundary {
+        let timestamp = self.messages.last().map_or(self.start.timestamp, |m| m.timestamp);
+        segment::ChainBoundary { hash: self.chain_hash, timestamp }
+    }
+
     /// Add a message to the chain using production ZK verification
     pub fn add_message(&mut self, message: Message) -> Result<()> {
+        if message.hash_id != self.hash_id {
+            return Err(ZkChatError::HashMismatch { expected: self.hash_id, found: message.hash_id });
+        }
+
         // Verify message hash using production ZK-friendly computation
         if !message.verify_hash() {
             return Err(ZkChatError::InvalidMessageHash);
@@ -133,39 +104,56 @@ impl MessageChain {
             return Err(ZkChatError::DuplicateMessageId);
         }
 
-
         // Verify timestamp monotonicity (required by ZK constraints)
-        if let Some(last_msg) = self.messages.last() {
-            if message.timestamp <= last_msg.timestamp {
-                return Err(ZkChatError::InvalidTimestamp);
+        if !self.end().admits(message.timestamp) {
+            return Err(ZkChatError::InvalidTimestamp);
+        }
+
+        // Each sender continues its own sequence (its first message may start anywhere)
+        if let Some(previous) = self.messages.iter().rev().find(|m| m.sender_id == message.sender_id) {
+            let expected = previous.id.saturating_add(1);
+            if message.id != expected {
+                return Err(ZkChatError::SequenceGap { sender_id: message.sender_id, expected, found: message.id });
             }
         }
 
-        // Update chain hash using production ZK-friendly Poseidon
+        // Update chain hash using the chain's ZK-friendly hash
         self.chain_hash = self.compute_chain_hash(&message);
+        self.message_tree.push(message.hash);
         self.messages.push(message);
 
         Ok(())
     }
 
-    /// Compute the chain hash using production ZK-friendly Poseidon hash
-    fn compute_chain_hash(&self, new_message: &Message) -> [u8; 32] {
-        // Convert current chain hash to field elements
-        let prev_chain_elements = hash_to_elements(&self.chain_hash);
-        
-        // Convert new message hash to field elements
-        let new_msg_elements = hash_to_elements(&new_message.hash);
-        
-        // Combine and hash using ZK-friendly Poseidon function (including session salt)
-        let mut chain_inputs = Vec::with_capacity(9);
-        chain_inputs.extend_from_slice(&prev_chain_elements);
-        chain_inputs.extend_from_slice(&new_msg_elements);
-        chain_inputs.push(*SESSION_SALT);
-        
-        let new_chain_hash_elements = zk_hash(&chain_inputs);
-        
-        // Convert back to bytes
-        elements_to_hash(&new_chain_hash_elements)
+    /// Compute the chain hash using the chain's ZK-friendly hash
+    fn compute_chain_hash(&self, new_message: &Message) -> Digest {
+        self.hash_id.hasher().chain_step(&self.chain_hash, &new_message.hash, session_salt_element())
+    }
+
+    /// Merkle root over all message hashes in the chain
+    pub fn message_root(&self) -> Digest {
+        self.message_tree.root()
+    }
+
+    /// Inclusion proof for the message at `index`, disclosing only that message
+    pub fn inclusion_proof(&self, index: usize) -> Option<merkle::MessageInclusionProof> {
+        let path = self.message_tree.path(index)?;
+        Some(merkle::MessageInclusionProof { message: self.messages[index].clone(), path })
+    }
+
+    /// Public inputs describing the whole chain, as proven by `MessageProver::prove(&self.messages)`
+    pub fn public_inputs(&self) -> air::PublicInputs {
+        air::PublicInputs {
+            initial_hash: self.start.hash,
+            initial_timestamp: self.start.timestamp,
+            final_hash: self.chain_hash,
+            final_timestamp: self.end().timestamp,
+            message_count: self.len(),
+            message_root: self.message_root(),
+            hash_id: self.hash_id,
+            salt: session_salt(),
+            message_index: air::message_index(self.messages.iter().map(air::GlobalId::of)),
+        }
     }
 
     /// Get the length of the message chain
@@ -179,27 +167,46 @@ impl MessageChain {
     }
 }
 
-/// Convert a hash to field elements for ZK proofs
-pub fn hash_to_elements(hash: &[u8; 32]) -> [BaseElement; 4] {
-    let mut elements = [BaseElement::ZERO; 4];
-    for (i, chunk) in hash.chunks(8).enumerate() {
-        if i < 4 {
-            let mut bytes = [0u8; 8];
-            bytes[..chunk.len()].copy_from_slice(chunk);
-            elements[i] = BaseElement::from(u64::from_le_bytes(bytes));
-        }
+impl Default for MessageChain {
+    fn default() -> Self {
+        Self::new()
     }
-    elements
 }
 
-/// Convert field elements back to hash
-pub fn elements_to_hash(elements: &[BaseElement; 4]) -> [u8; 32] {
-    let mut hash = [0u8; 32];
-    for (i, element) in elements.iter().enumerate() {
-        // Convert to u64 (fits in 8 bytes) instead of u128
-        let value = (element.as_int() % (1u128 << 64)) as u64;
-        let bytes = value.to_le_bytes();
-        hash[i * 8..(i + 1) * 8].copy_from_slice(&bytes);
-    }
-    hash
-}
\ No newline at end of file
+/// Extend a chain hash by one message hash with the default hash: H(prev || message_hash || session salt)
+pub fn chain_step(prev_chain_hash: &Digest, message_hash: &Digest) -> Digest {
+    chain_step_with_salt(prev_chain_hash, message_hash, session_salt_element())
+}
+
+/// Extend a chain hash under another session's salt (e.g. a client mirroring the server's chain)
+pub fn chain_step_with_salt(prev_chain_hash: &Digest, message_hash: &Digest, salt: BaseElement) -> Digest {
+    hasher::default_hash().hasher().chain_step(prev_chain_hash, message_hash, salt)
+}
+
+/// This process's session salt, as published to clients mirroring the chain
+pub fn session_salt() -> u64 {
+    SESSION_SALT.load(Ordering::SeqCst)
+}
+
+/// Replace the session salt with a fresh random one, returning it
+pub fn rotate_session_salt() -> u64 {
+    let salt = random_u64();
+    SESSION_SALT.store(salt, Ordering::SeqCst);
+    salt
+}
+
+/// Adopt the salt of a stored chain so it can be extended after a restart
+pub fn restore_session_salt(salt: u64) {
+    SESSION_SALT.store(salt, Ordering::SeqCst);
+}
+
+/// The session salt as a field element, as bound into chain steps
+pub fn session_salt_element() -> BaseElement {
+    element(session_salt())
+}
+
+/// 32 raw bytes (salts, Blake3 outputs, proof digests) as four little-endian 8-byte words.
+/// Over f64 words at or above the modulus wrap; field digests use `Digest` instead.
+pub fn bytes_to_elements(bytes: &[u8; 32]) -> [BaseElement; 4] {
+    std::array::from_fn(|i| element(u64::from_le_bytes(bytes[i * 8..(i + 1) * 8].try_into().unwrap())))
+}
@@ -0,0 +1,227 @@
+use super::field::{element, element_from_u128, BaseElement, MODULUS, MODULUS_BITS};
+use once_cell::sync::Lazy;
+use winterfell::math::FieldElement;
+
+/// State width of the instance: rate 3, capacity 1
+pub const WIDTH: usize = 4;
+
+/// S-box exponent, the smallest alpha coprime to p - 1
+#[cfg(not(feature = "f64"))]
+pub const ALPHA: u32 = 3;
+#[cfg(feature = "f64")]
+pub const ALPHA: u32 = 7;
+
+/// Full rounds, half before and half after the partial rounds
+pub const FULL_ROUNDS: usize = 8;
+
+/// Partial rounds for 128-bit security, from the reference round-number formulas including their 7.5% margin
+#[cfg(not(feature = "f64"))]
+pub const PARTIAL_ROUNDS: usize = 84;
+#[cfg(feature = "f64")]
+pub const PARTIAL_ROUNDS: usize = 21;
+
+static PARAMS: Lazy<PoseidonParams> = Lazy::new(PoseidonParams::generate);
+
+/// Grain LFSR of the Poseidon reference parameter script (`generate_parameters_grain.sage`),
+/// seeded with a prime field, an x^alpha S-box and the given instance sizes
+#[derive(Debug, Clone)]
+pub struct GrainLfsr {
+    bits: [bool; 80],
+    head: usize,
+}
+
+impl GrainLfsr {
+    /// Seed the LFSR and discard its first 160 bits
+    pub fn new(field_bits: u32, width: usize, full_rounds: usize, partial_rounds: usize) -> Self {
+        let mut bits = [true; 80];
+        let fields = [(1, 2), (0, 4), (field_bits as u64, 12), (width as u64, 12), (full_rounds as u64, 10), (partial_rounds as u64, 10)];
+        let mut i = 0;
+        for (value, len) in fields {
+            for shift in (0..len).rev() {
+                bits[i] = (value >> shift) & 1 == 1;
+                i += 1;
+            }
+        }
+        let mut lfsr = Self { bits, head: 0 };
+        for _ in 0..160 {
+            lfsr.clock();
+        }
+        lfsr
+    }
+
+    /// Next `bits` output bits as a big-endian integer, most significant bit drawn first
+    pub fn next_bits(&mut self, bits: u32) -> Vec<u8> {
+        let len = bits.div_ceil(8) as usize;
+        let mut out = vec![0u8; len];
+        for pos in (0..bits as usize).rev() {
+            if self.next_bit() {
+                out[len - 1 - pos / 8] |= 1 << (pos % 8);
+            }
+        }
+        out
+    }
+
+    /// Next `bits`-bit integer below `modulus`, rejecting larger draws.
+    /// `modulus` is big-endian and as long as `next_bits(bits)`.
+    pub fn next_below(&mut self, bits: u32, modulus: &[u8]) -> Vec<u8> {
+        loop {
+            let value = self.next_bits(bits);
+            if value.as_slice() < modulus {
+                return value;
+            }
+        }
+    }
+
+    // Output bits come in pairs: the second is kept only when the first is set
+    fn next_bit(&mut self) -> bool {
+        loop {
+            let keep = self.clock();
+            let bit = self.clock();
+            if keep {
+                return bit;
+            }
+        }
+    }
+
+    // b[i + 80] = b[i + 62] ^ b[i + 51] ^ b[i + 38] ^ b[i + 23] ^ b[i + 13] ^ b[i]
+    fn clock(&mut self) -> bool {
+        let tap = |offset: usize| self.bits[(self.head + offset) % 80];
+        let bit = tap(62) ^ tap(51) ^ tap(38) ^ tap(23) ^ tap(13) ^ tap(0);
+        self.bits[self.head] = bit;
+        self.head = (self.head + 1) % 80;
+        bit
+    }
+}
+
+/// Round constants and MDS matrix of the instance over the selected field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoseidonParams {
+    pub round_constants: Vec<[BaseElement; WIDTH]>,
+    pub mds: [[BaseElement; WIDTH]; WIDTH],
+}
+
+impl PoseidonParams {
+    /// Generate the parameters as the reference script does: round constants rejection-sampled below
+    /// the modulus, then the Cauchy matrix 1 / (x_i + y_j) from the next 2 * WIDTH draws reduced mod p.
+    /// The script's extra subspace-trail checks on the matrix are not repeated here.
+    pub fn generate() -> Self {
+        let mut grain = GrainLfsr::new(MODULUS_BITS, WIDTH, FULL_ROUNDS, PARTIAL_ROUNDS);
+        let modulus = &MODULUS.to_be_bytes()[16 - MODULUS_BITS.div_ceil(8) as usize..];
+
+        let round_constants = (0..FULL_ROUNDS + PARTIAL_ROUNDS)
+            .map(|_| std::array::from_fn(|_| element_from_u128(to_u128(&grain.next_below(MODULUS_BITS, modulus)))))
+            .collect();
+
+        let mds = loop {
+            let draws: [BaseElement; 2 * WIDTH] =
+                std::array::from_fn(|_| element_from_u128(to_u128(&grain.next_bits(MODULUS_BITS)) % MODULUS));
+            let (xs, ys) = draws.split_at(WIDTH);
+            let distinct = (0..draws.len()).all(|i| !draws[i + 1..].contains(&draws[i]));
+            if distinct && xs.iter().all(|x| ys.iter().all(|y| *x + *y != BaseElement::ZERO)) {
+                break std::array::from_fn(|i| std::array::from_fn(|j| (xs[i] + ys[j]).inv()));
+            }
+        };
+
+        Self { round_constants, mds }
+    }
+
+    fn mds_mul<E: FieldElement + From<BaseElement>>(&self, state: [E; WIDTH]) -> [E; WIDTH] {
+        std::array::from_fn(|i| self.mds[i].iter().zip(&state).fold(E::ZERO, |acc, (m, s)| acc + E::from(*m) * *s))
+    }
+}
+
+/// The instance's parameters, generated on first use
+pub fn params() -> &'static PoseidonParams {
+    &PARAMS
+}
+
+/// Poseidon permutation: FULL_ROUNDS / 2 full rounds, PARTIAL_ROUNDS partial rounds (S-box on the
+/// first element only), then FULL_ROUNDS / 2 full rounds; each round adds constants, applies the S-box and the MDS matrix
+pub fn poseidon_permutation(state: [BaseElement; WIDTH]) -> [BaseElement; WIDTH] {
+    rounds(state, sbox)
+}
+
+/// S-box applications per permutation: one per element in full rounds, one in partial rounds
+pub const SBOX_OUTPUTS: usize = FULL_ROUNDS * WIDTH + PARTIAL_ROUNDS;
+
+/// Every S-box output of the permutation on `state`, in the order the rounds apply them
+pub fn sbox_outputs(state: [BaseElement; WIDTH]) -> Vec<BaseElement> {
+    let mut outputs = Vec::with_capacity(SBOX_OUTPUTS);
+    rounds(state, |input| {
+        let output = sbox(input);
+        outputs.push(output);
+        output
+    });
+    outputs
+}
+
+/// The permutation with its S-box outputs taken from `outputs` (ordered as by `sbox_outputs`) instead of computed.
+/// Writes each claimed output minus the S-box of its input to `residues` and returns the resulting state,
+/// so an AIR can check the permutation at the S-box degree.
+pub fn evaluate_rounds<E: FieldElement + From<BaseElement>>(state: [E; WIDTH], outputs: &[E], residues: &mut [E]) -> [E; WIDTH] {
+    let mut applied = 0;
+    rounds(state, |input| {
+        let output = outputs[applied];
+        residues[applied] = output - sbox(input);
+        applied += 1;
+        output
+    })
+}
+
+fn rounds<E: FieldElement + From<BaseElement>>(mut state: [E; WIDTH], mut apply_sbox: impl FnMut(E) -> E) -> [E; WIDTH] {
+    let params = params();
+    let half = FULL_ROUNDS / 2;
+    for (round, constants) in params.round_constants.iter().enumerate() {
+        for (s, c) in state.iter_mut().zip(constants) {
+            *s += E::from(*c);
+        }
+        if round < half || round >= half + PARTIAL_ROUNDS {
+            for s in state.iter_mut() {
+                *s = apply_sbox(*s);
+            }
+        } else {
+            state[0] = apply_sbox(state[0]);
+        }
+        state = params.mds_mul(state);
+    }
+    state
+}
+
+/// Sponge over the permutation: rate 3, capacity 1, one extra permutation after absorbing.
+/// The capacity starts at the input length, so inputs differing only by trailing zeros hash differently.
+pub fn poseidon_hash(inputs: &[BaseElement]) -> [BaseElement; WIDTH] {
+    let mut state = [BaseElement::ZERO; WIDTH];
+    state[WIDTH - 1] = element(inputs.len() as u64);
+    sponge(state, inputs)
+}
+
+/// The same sponge with a zero capacity, as `HashId::Poseidon` hashes; `[a]` and `[a, 0, 0]` collide
+pub fn poseidon_hash_unseparated(inputs: &[BaseElement]) -> [BaseElement; WIDTH] {
+    sponge([BaseElement::ZERO; WIDTH], inputs)
+}
+
+fn sponge(mut state: [BaseElement; WIDTH], inputs: &[BaseElement]) -> [BaseElement; WIDTH] {
+    for chunk in inputs.chunks(WIDTH - 1) {
+        for (s, input) in state.iter_mut().zip(chunk) {
+            *s += *input;
+        }
+        state = poseidon_permutation(state);
+    }
+    poseidon_permutation(state)
+}
+
+fn sbox<E: FieldElement>(x: E) -> E {
+    #[cfg(not(feature = "f64"))]
+    {
+        x.square() * x
+    }
+    #[cfg(feature = "f64")]
+    {
+        let x2 = x.square();
+        x2.square() * x2 * x
+    }
+}
+
+fn to_u128(bytes: &[u8]) -> u128 {
+    bytes.iter().fold(0, |acc, &b| (acc << 8) | u128::from(b))
+}
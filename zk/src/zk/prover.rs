@@ -1,17 +1,65 @@
-use super::air::{PublicInputs, build_trace, MessageAir};
+use super::air::{self, PublicInputs, build_trace, build_segment_trace, message_row, global_id_term, timestamp_follows, GlobalId, MessageAir, MessageRow, AUX_RAND_ELEMENTS, AUX_TRACE_WIDTH, BLOCK_LENGTH, CHAIN_HASH, INPUTS, SORTED, TRACE_WIDTH};
+use super::{disclosure::RedactedMessage, field::{BaseElement, HashFn, FIELD_EXTENSION}, hasher::HashId, merkle::MerkleTree, segment::ChainBoundary, session_salt, Digest};
 use crate::{Message, Result, ZkChatError};
 use winterfell::{
-    math::{fields::f128::BaseElement, FieldElement},
-    ProofOptions, TraceTable, Prover,
-    crypto::{DefaultRandomCoin, hashers::Blake3_256},
+    math::FieldElement,
+    ProofOptions, Prover, Trace,
+    crypto::DefaultRandomCoin,
     matrix::ColMatrix, AuxRandElements, ConstraintCompositionCoefficients,
-    DefaultConstraintEvaluator, DefaultTraceLde,
+    DefaultConstraintEvaluator, DefaultTraceLde, EvaluationFrame,
     StarkDomain, TraceInfo, TracePolyTable,
 };
 use std::marker::PhantomData;
 
-/// Hash function for Merkle trees - Blake3 is production-grade
-type HashFn = Blake3_256<BaseElement>;
+/// Named proof parameter presets, trading proving time for soundness
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProofProfile {
+    /// Quick proofs for development (~96-bit conjectured security)
+    Fast,
+    /// The default parameters of `MessageProver::new`
+    #[default]
+    Standard,
+    /// More queries and grinding for long-lived proofs
+    Secure,
+}
+
+impl ProofProfile {
+    /// Winterfell proof options for this profile
+    pub fn options(self) -> ProofOptions {
+        match self {
+            Self::Fast => ProofOptions::new(32, 8, 0, FIELD_EXTENSION, 8, 31),
+            Self::Standard => ProofOptions::new(
+                54, // num_queries - increased spot checks for stronger soundness
+                16, // blowup_factor - larger domain for lower interpolation risks
+                16, // grinding_factor - introduce computational work against DoS
+                FIELD_EXTENSION,
+                4,  // fri_folding_factor - more FRI rounds, higher confidence
+                31, // fri_max_remainder_size
+            ),
+            Self::Secure => ProofOptions::new(80, 16, 20, FIELD_EXTENSION, 4, 31),
+        }
+    }
+
+    /// Options of this profile and every stronger one, the proofs a verifier requiring it accepts
+    pub fn acceptable(self) -> winterfell::AcceptableOptions {
+        let profiles = [Self::Fast, Self::Standard, Self::Secure];
+        winterfell::AcceptableOptions::OptionSet(profiles.into_iter().filter(|p| *p >= self).map(Self::options).collect())
+    }
+}
+
+impl std::str::FromStr for ProofProfile {
+    type Err = ZkChatError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "fast" => Ok(Self::Fast),
+            "standard" => Ok(Self::Standard),
+            "secure" => Ok(Self::Secure),
+            _ => Err(ZkChatError::Config(format!("unknown proof profile {:?} (expected fast, standard or secure)", s))),
+        }
+    }
+}
 
 /// REAL Winterfell ZK-STARK Prover for message chain proofs
 /// This implements the full Prover trait for industry-standard cryptographic proofs
@@ -19,49 +67,105 @@ type HashFn = Blake3_256<BaseElement>;
 pub struct MessageProver {
     options: ProofOptions,
     message_count: usize, // Store actual message count for public inputs
+    message_root: Digest, // Merkle root over the proven messages' hashes
+    message_index: Vec<GlobalId>, // Published index of the proven messages
+    hash_id: HashId, // Hash the proven chain was built with
+    start: ChainBoundary, // Boundary the proven segment continues from
+    salt: u64, // Session salt the proven chain steps were taken under
     _hasher: PhantomData<HashFn>,
 }
 
 impl MessageProver {
     /// Create a new message prover with default security parameters
     pub fn new() -> Self {
-        Self {
-            options: ProofOptions::new(
-                54, // num_queries - increased spot checks for stronger soundness
-                16, // blowup_factor - larger domain for lower interpolation risks
-                16, // grinding_factor - introduce computational work against DoS
-                winterfell::FieldExtension::None,
-                4,  // fri_folding_factor - more FRI rounds, higher confidence
-                31, // fri_max_remainder_size
-            ),
-            message_count: 0, // Will be set during prove()
-            _hasher: PhantomData,
-        }
+        Self::with_profile(ProofProfile::Standard)
+    }
+
+    /// Create a new message prover using a named parameter preset
+    pub fn with_profile(profile: ProofProfile) -> Self {
+        Self::with_options(profile.options())
     }
 
     /// Create a new message prover with custom options
     pub fn with_options(options: ProofOptions) -> Self {
-        Self { options, message_count: 0, _hasher: PhantomData }
+        Self { options, message_count: 0, message_root: Digest::ZERO, message_index: Vec::new(), hash_id: HashId::default(), start: ChainBoundary::GENESIS, salt: 0, _hasher: PhantomData }
     }
 
     /// Generate REAL ZK-STARK proof using Winterfell's prove() function
     /// This is 100% industry-standard cryptographic proof generation - NO MOCKS
     pub fn prove(&mut self, messages: &[Message]) -> Result<Vec<u8>> {
+        self.prove_segment(&ChainBoundary::GENESIS, messages)
+    }
+
+    /// Prove a segment of messages continuing the chain from `start` (see `zk::segment`)
+    pub fn prove_segment(&mut self, start: &ChainBoundary, messages: &[Message]) -> Result<Vec<u8>> {
         if messages.is_empty() {
             return Err(ZkChatError::InvalidMessageHash);
         }
 
-        // Store actual message count for public inputs
-        self.message_count = messages.len();
-
-        // Verify all messages have valid hashes and timestamps
+        // Verify all messages have valid hashes and timestamps, the first one after the boundary
         self.validate_message_chain(messages)?;
+        if !start.admits(messages[0].timestamp) {
+            return Err(ZkChatError::InvalidTimestamp);
+        }
 
         // Build execution trace - this is the computation being proven
-        let trace = self.build_trace(messages);
-        
+        let rows: Vec<MessageRow> = messages.iter().map(message_row).collect();
+        let index = air::message_index(messages.iter().map(GlobalId::of));
+        check_sequences(&index)?;
+        self.prove_rows(&rows, super::merkle::message_root(messages), index, messages[0].hash_id, *start)
+    }
+
+    /// Prove a chain of redacted messages: contents stay hidden behind their salted commitments
+    pub fn prove_redacted(&mut self, messages: &[RedactedMessage]) -> Result<Vec<u8>> {
+        self.prove_redacted_segment(&ChainBoundary::GENESIS, messages)
+    }
+
+    /// Prove redacted messages continuing the chain from `start`, as `prove_segment` does for full ones
+    pub fn prove_redacted_segment(&mut self, start: &ChainBoundary, messages: &[RedactedMessage]) -> Result<Vec<u8>> {
+        if messages.is_empty() {
+            return Err(ZkChatError::InvalidMessageHash);
+        }
+        for (i, message) in messages.iter().enumerate() {
+            if !message.verify_hash() {
+                return Err(ZkChatError::InvalidMessageHash);
+            }
+            if i > 0 && !timestamp_follows(messages[i - 1].timestamp, message.timestamp) {
+                return Err(ZkChatError::InvalidTimestamp);
+            }
+            check_hash_id(messages[0].hash_id, message.hash_id)?;
+        }
+        if !start.admits(messages[0].timestamp) {
+            return Err(ZkChatError::InvalidTimestamp);
+        }
+
+        let rows: Vec<MessageRow> = messages.iter().map(RedactedMessage::row).collect();
+        let leaves: Vec<Digest> = messages.iter().map(|m| m.hash).collect();
+        let index = air::message_index(messages.iter().map(RedactedMessage::global_id));
+        check_sequences(&index)?;
+        self.prove_rows(&rows, MerkleTree::new(&leaves).root(), index, messages[0].hash_id, *start)
+    }
+
+    fn prove_rows(&mut self, rows: &[MessageRow], message_root: Digest, message_index: Vec<GlobalId>, hash_id: HashId, start: ChainBoundary) -> Result<Vec<u8>> {
+        // Only hashes the AIR evaluates in-circuit can be proven
+        if !hash_id.provable() {
+            return Err(ZkChatError::ProofGeneration(format!("{} chains cannot be proven; the AIR only evaluates Poseidon", hash_id)));
+        }
+
+        // Build execution trace - this is the computation being proven
+        self.salt = session_salt();
+        let trace = MessageTrace::new(build_segment_trace(rows, hash_id, &start, self.salt));
+
+        // Store actual message count, message root, index, hash and starting boundary for public inputs
+        self.message_count = rows.len();
+        self.start = start;
+        self.message_root = message_root;
+        self.message_index = message_index;
+        self.hash_id = hash_id;
+
         // Call Winterfell's REAL prove() function through the Prover trait
         // This generates a complete cryptographic ZK-STARK proof
+        let _timer = crate::metrics::PROVE_DURATION.start_timer();
         let proof = Prover::prove(self, trace)
             .map_err(|e| ZkChatError::ProofGeneration(format!("{:?}", e)))?;
         
@@ -70,9 +174,9 @@ impl MessageProver {
     }
 
     /// Build execution trace for messages
-    pub fn build_trace(&self, messages: &[Message]) -> TraceTable<BaseElement> {
+    pub fn build_trace(&self, messages: &[Message]) -> MessageTrace {
         let trace_data = build_trace(messages);
-        TraceTable::init(trace_data)
+        MessageTrace::new(trace_data)
     }
 
     /// Validate that the message chain is well-formed
@@ -83,11 +187,14 @@ impl MessageProver {
                 return Err(ZkChatError::InvalidMessageHash);
             }
 
-            // Verify timestamp monotonicity
-            if i > 0 && message.timestamp <= messages[i - 1].timestamp {
+            // Verify timestamp monotonicity, in steps the AIR can prove
+            if i > 0 && !timestamp_follows(messages[i - 1].timestamp, message.timestamp) {
                 return Err(ZkChatError::InvalidTimestamp);
             }
 
+            // All messages must share the chain's hash
+            check_hash_id(messages[0].hash_id, message.hash_id)?;
+
             // Allow any sender_id (removed restriction)
         }
 
@@ -102,6 +209,69 @@ impl MessageProver {
     }
 }
 
+/// Reject a message hashed differently from the rest of its chain
+pub(crate) fn check_hash_id(expected: HashId, found: HashId) -> Result<()> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(ZkChatError::HashMismatch { expected, found })
+    }
+}
+
+/// Reject a sorted index in which a sender's IDs repeat or skip a number; the AIR only accepts gap-free sequences
+pub(crate) fn check_sequences(index: &[GlobalId]) -> Result<()> {
+    for pair in index.windows(2) {
+        let (previous, next) = (pair[0], pair[1]);
+        if previous.sender_id != next.sender_id || previous.id.checked_add(1) == Some(next.id) {
+            continue;
+        }
+        if previous.id == next.id {
+            return Err(ZkChatError::DuplicateMessageId);
+        }
+        return Err(ZkChatError::SequenceGap { sender_id: next.sender_id, expected: previous.id.saturating_add(1), found: next.id });
+    }
+    Ok(())
+}
+
+impl Default for MessageProver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Main trace segment of a message proof; the index arguments are built on top of it as the auxiliary segment
+#[derive(Debug, Clone)]
+pub struct MessageTrace {
+    info: TraceInfo,
+    main: ColMatrix<BaseElement>,
+}
+
+impl MessageTrace {
+    /// Wrap trace columns as produced by `air::build_trace`
+    pub fn new(columns: Vec<Vec<BaseElement>>) -> Self {
+        let info = air::trace_info(columns[0].len());
+        Self { info, main: ColMatrix::new(columns) }
+    }
+}
+
+impl Trace for MessageTrace {
+    type BaseField = BaseElement;
+
+    fn info(&self) -> &TraceInfo {
+        &self.info
+    }
+
+    fn main_segment(&self) -> &ColMatrix<BaseElement> {
+        &self.main
+    }
+
+    fn read_main_frame(&self, row_idx: usize, frame: &mut EvaluationFrame<BaseElement>) {
+        let next_row_idx = (row_idx + 1) % self.info.length();
+        self.main.read_row_into(row_idx, frame.current_mut());
+        self.main.read_row_into(next_row_idx, frame.next_mut());
+    }
+}
+
 // ================================================================================================
 // REAL WINTERFELL PROVER TRAIT IMPLEMENTATION
 // This is the industry-standard ZK-STARK prover - 100% cryptographic, NO MOCKS
@@ -110,7 +280,7 @@ impl MessageProver {
 impl Prover for MessageProver {
     type BaseField = BaseElement;
     type Air = MessageAir;
-    type Trace = TraceTable<BaseElement>;
+    type Trace = MessageTrace;
     type HashFn = HashFn;
     type RandomCoin = DefaultRandomCoin<Self::HashFn>;
     type TraceLde<E: FieldElement<BaseField = Self::BaseField>> =
@@ -121,22 +291,28 @@ impl Prover for MessageProver {
     /// Extract public inputs from the execution trace
     fn get_pub_inputs(&self, trace: &Self::Trace) -> PublicInputs {
         
-        // Initial hash is all zeros (start of chain)
-        let initial_hash = [0u8; 32];
+        // Initial hash and timestamp are the boundary the segment continues from (zero for a fresh chain)
+        let ChainBoundary { hash: initial_hash, timestamp: initial_timestamp } = self.start;
         
-        // Final hash is from the last REAL message step (not padded trace length)
-        // Get it from columns 4-7 at step (message_count - 1)
-        let last_message_step = self.message_count - 1;
+        // Final hash and timestamp are from the last REAL message block (not padded trace length)
+        let last_block = (self.message_count - 1) * BLOCK_LENGTH;
         let mut final_hash_elements = [BaseElement::ZERO; 4];
-        for i in 0..4 {
-            final_hash_elements[i] = trace.get(4 + i, last_message_step);
+        for (i, element) in final_hash_elements.iter_mut().enumerate() {
+            *element = trace.main_segment().get(CHAIN_HASH + i, last_block + BLOCK_LENGTH - 1);
         }
-        let final_hash = crate::zk::elements_to_hash(&final_hash_elements);
+        let final_hash = Digest::new(final_hash_elements);
+        let final_timestamp = super::field::element_to_u64(trace.main_segment().get(INPUTS + 2, last_block));
         
         PublicInputs {
             initial_hash,
+            initial_timestamp,
             final_hash,
+            final_timestamp,
             message_count: self.message_count, // Use actual message count, not trace length
+            message_root: self.message_root,
+            hash_id: self.hash_id,
+            salt: self.salt,
+            message_index: self.message_index.clone(),
         }
     }
 
@@ -144,6 +320,35 @@ impl Prover for MessageProver {
         &self.options
     }
 
+    /// Build the auxiliary columns from the verifier's challenges, absorbing on the first step of each block
+    /// and holding in between:
+    /// z[0] = 1, z[step + 1] = z[step] * (gamma - (id[step] + delta * sender[step]))
+    /// idx[0] = 0, idx[step + 1] = idx[step] * epsilon + (sorted_id[step] + delta * sorted_sender[step]),
+    /// over the sorted global IDs; it ends equal to the same fold over `message_index`
+    fn build_aux_trace<E: FieldElement<BaseField = Self::BaseField>>(
+        &self,
+        main_trace: &Self::Trace,
+        aux_rand_elements: &AuxRandElements<E>,
+    ) -> ColMatrix<E> {
+        let &[gamma, delta, epsilon] = aux_rand_elements.rand_elements() else {
+            unreachable!("the message AIR draws {} auxiliary challenges", air::AUX_RAND_ELEMENTS)
+        };
+        let main = main_trace.main_segment();
+        let mut product = vec![E::ONE; main_trace.length()];
+        let mut sorted = vec![E::ZERO; main_trace.length()];
+        for step in 0..main_trace.length() - 1 {
+            (product[step + 1], sorted[step + 1]) = if step % BLOCK_LENGTH == 0 {
+                (
+                    product[step] * (gamma - global_id_term(main.get(INPUTS, step), main.get(INPUTS + 1, step), delta)),
+                    sorted[step] * epsilon + global_id_term(main.get(SORTED + 1, step), main.get(SORTED, step), delta),
+                )
+            } else {
+                (product[step], sorted[step])
+            };
+        }
+        ColMatrix::new(vec![product, sorted])
+    }
+
     /// Create the trace low-degree extension (LDE) for FRI protocol
     fn new_trace_lde<E: FieldElement<BaseField = Self::BaseField>>(
         &self,
@@ -171,19 +376,38 @@ impl Prover for MessageProver {
 // ================================================================================================
 
 /// Verify a REAL ZK-STARK proof using Winterfell's industry-standard verification
+/// Proofs made with options weaker than `min_profile` are rejected
 pub fn verify_proof(
     proof_data: &[u8],
     pub_inputs: PublicInputs,
+    min_profile: ProofProfile,
 ) -> Result<()> {
     // Deserialize the REAL Winterfell proof from bytes
     let proof = winterfell::Proof::from_bytes(proof_data)
         .map_err(|e| ZkChatError::ProofGeneration(format!("Proof deserialization failed: {:?}", e)))?;
+
+    // Reject layouts the AIR cannot describe (e.g. proofs from before the in-circuit hash), hashes it cannot
+    // evaluate, and public inputs whose index or count do not fit the trace, before winterfell builds the AIR;
+    // the published index must be sorted without repeats
+    let trace_info = proof.trace_info();
+    if trace_info.main_trace_width() != TRACE_WIDTH
+        || trace_info.aux_segment_width() != AUX_TRACE_WIDTH
+        || trace_info.get_num_aux_segment_rand_elements() != AUX_RAND_ELEMENTS
+        || !pub_inputs.hash_id.provable()
+        || pub_inputs.message_count == 0
+        || pub_inputs.message_count >= trace_info.length() / BLOCK_LENGTH
+        || pub_inputs.message_index.len() != pub_inputs.message_count
+        || pub_inputs.message_index.windows(2).any(|pair| pair[0] >= pair[1])
+    {
+        return Err(ZkChatError::ProofGeneration("Verification failed: proof does not match the message AIR".into()));
+    }
     
-    // Define acceptable proof options for verification
-    let acceptable_options = winterfell::AcceptableOptions::OptionSet(vec![proof.options().clone()]);
+    // Only the options of `min_profile` or a stronger profile are accepted, whatever the proof claims
+    let acceptable_options = min_profile.acceptable();
     
     // Call Winterfell's REAL verify() function - this performs complete cryptographic verification
     // This checks: FRI commitments, Merkle proofs, constraint satisfaction, and random coin challenges
+    let _timer = crate::metrics::VERIFY_DURATION.start_timer();
     winterfell::verify::<MessageAir, HashFn, DefaultRandomCoin<HashFn>>(
         proof,
         pub_inputs,
@@ -0,0 +1,93 @@
+use super::{
+    air::{timestamp_follows, PublicInputs},
+    hasher::HashId,
+    prover::{check_hash_id, verify_proof, ProofProfile},
+    Digest,
+};
+use crate::{Result, ZkChatError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Chain state between two segments: the chain hash and the timestamp of the last message before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainBoundary {
+    pub hash: Digest,
+    pub timestamp: u64,
+}
+
+impl ChainBoundary {
+    /// Start of a fresh chain
+    pub const GENESIS: Self = Self { hash: Digest::ZERO, timestamp: 0 };
+
+    /// Whether a message stamped `timestamp` may follow this boundary; after genesis any timestamp from 1 to
+    /// `MAX_TIMESTAMP_STEP` may
+    pub fn admits(&self, timestamp: u64) -> bool {
+        timestamp_follows(self.timestamp, timestamp)
+    }
+
+    /// Boundary a segment starts from, as stated in its public inputs
+    pub fn start_of(pub_inputs: &PublicInputs) -> Self {
+        Self { hash: pub_inputs.initial_hash, timestamp: pub_inputs.initial_timestamp }
+    }
+
+    /// Boundary a segment ends at: its final hash and the timestamp of its last message
+    pub fn end_of(pub_inputs: &PublicInputs) -> Self {
+        Self { hash: pub_inputs.final_hash, timestamp: pub_inputs.final_timestamp }
+    }
+}
+
+/// One segment of a chain's history: its public inputs and the proof over them
+#[derive(Debug, Clone)]
+pub struct SegmentProof {
+    pub public_inputs: PublicInputs,
+    pub proof: Vec<u8>,
+}
+
+/// What a verified run of segment proofs attests: an unbroken history between two boundaries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComposedHistory {
+    pub start: ChainBoundary,
+    pub end: ChainBoundary,
+    pub message_count: usize,
+    pub hash_id: HashId,
+}
+
+/// Verify segment proofs in order, checking that each one starts where the previous one ended
+pub fn verify_segments(segments: &[SegmentProof], min_profile: ProofProfile) -> Result<ComposedHistory> {
+    let first = segments.first().ok_or_else(|| ZkChatError::ChainInconsistency("no segments to compose".into()))?;
+    let start = ChainBoundary::start_of(&first.public_inputs);
+    let hash_id = first.public_inputs.hash_id;
+    let mut end = start;
+    let mut message_count = 0;
+    // Last ID seen for each sender in earlier segments
+    let mut last_ids: HashMap<u64, u64> = HashMap::new();
+
+    for (i, segment) in segments.iter().enumerate() {
+        let pub_inputs = &segment.public_inputs;
+        check_hash_id(hash_id, pub_inputs.hash_id)?;
+        if ChainBoundary::start_of(pub_inputs) != end {
+            return Err(ZkChatError::ChainInconsistency(format!("segment {} does not start where the previous segment ends", i)));
+        }
+        // The proof also makes timestamps increase from the segment's initial timestamp on
+        verify_proof(&segment.proof, pub_inputs.clone(), min_profile)?;
+
+        // The proof makes each sender's IDs gap-free within the segment; across segments they must continue
+        for run in pub_inputs.message_index.chunk_by(|a, b| a.sender_id == b.sender_id) {
+            let (first_id, last_id) = (run[0], run[run.len() - 1]);
+            if let Some(&previous) = last_ids.get(&first_id.sender_id) {
+                if first_id.id <= previous {
+                    return Err(ZkChatError::DuplicateMessageId);
+                }
+                if first_id.id != previous + 1 {
+                    return Err(ZkChatError::SequenceGap { sender_id: first_id.sender_id, expected: previous + 1, found: first_id.id });
+                }
+            }
+            last_ids.insert(last_id.sender_id, last_id.id);
+        }
+
+        message_count += pub_inputs.message_count;
+        end = ChainBoundary::end_of(pub_inputs);
+    }
+
+    Ok(ComposedHistory { start, end, message_count, hash_id })
+}
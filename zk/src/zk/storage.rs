@@ -0,0 +1,101 @@
+use crate::{Message, Result, ZkChatError, zk::{air::GlobalId, hasher::HashId, session_salt, Digest, MessageChain}};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// On-disk format version written by `StoredChain::save` and the only one `StoredChain::load` reads
+pub const CHAIN_FORMAT_VERSION: u32 = 1;
+
+/// Disclosure salt committing to one message's content (see `zk::disclosure`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredContentSalt {
+    pub sender_id: u64,
+    pub id: u64,
+    #[serde(with = "crate::hex_serde")]
+    pub salt: [u8; 32],
+}
+
+impl StoredContentSalt {
+    /// Global id of the message this salt belongs to
+    pub fn global_id(&self) -> GlobalId {
+        GlobalId { sender_id: self.sender_id, id: self.id }
+    }
+}
+
+/// A chain epoch persisted across restarts: the messages, the salt they were chained under and the head they reach
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredChain {
+    pub version: u32,
+    #[serde(with = "crate::u64_string")]
+    pub salt: u64,
+    pub epoch: u64,
+    pub chain_hash: Digest,
+    pub messages: Vec<Message>,
+    /// Hash the chain was built with; restored chains keep it even if the configured hash changed
+    pub hash_id: HashId,
+    #[serde(default)]
+    pub content_salts: Vec<StoredContentSalt>,
+}
+
+impl StoredChain {
+    /// Snapshot a chain built under the current session salt
+    pub fn new(chain: &MessageChain, epoch: u64) -> Self {
+        Self {
+            version: CHAIN_FORMAT_VERSION,
+            salt: session_salt(),
+            epoch,
+            chain_hash: chain.chain_hash,
+            messages: chain.messages.clone(),
+            hash_id: chain.hash_id,
+            content_salts: Vec::new(),
+        }
+    }
+
+    /// Attach the per-message disclosure salts, ordered by global id
+    pub fn with_content_salts(mut self, salts: impl IntoIterator<Item = (GlobalId, [u8; 32])>) -> Self {
+        self.content_salts = salts.into_iter().map(|(global_id, salt)| StoredContentSalt { sender_id: global_id.sender_id, id: global_id.id, salt }).collect();
+        self.content_salts.sort_by_key(|s| s.global_id());
+        self
+    }
+
+    /// Read a stored chain; `None` if the file does not exist
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let value: serde_json::Value = serde_json::from_slice(&std::fs::read(path)?)?;
+        if value["version"].as_u64() != Some(u64::from(CHAIN_FORMAT_VERSION)) {
+            return Err(ZkChatError::ChainInconsistency(format!(
+                "{}: unsupported chain format version {}", path.display(), value["version"]
+            )));
+        }
+        Ok(Some(serde_json::from_value(value)?))
+    }
+
+    /// Write to a temporary file and rename it over `path`, so a crash never leaves a half-written chain
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Rebuild the chain, re-checking every message and the stored head.
+    /// The session salt must already be the stored one (see `zk::restore_session_salt`).
+    pub fn restore(&self) -> Result<MessageChain> {
+        if session_salt() != self.salt {
+            return Err(ZkChatError::ChainInconsistency("stored chain was built under another session salt".into()));
+        }
+        let mut chain = MessageChain::with_hash(self.hash_id);
+        for message in &self.messages {
+            chain.add_message(message.clone())?;
+        }
+        if chain.chain_hash != self.chain_hash {
+            return Err(ZkChatError::ChainInconsistency("stored chain hash does not match its messages".into()));
+        }
+        Ok(chain)
+    }
+}
+
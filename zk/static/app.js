@@ -1,37 +1,391 @@
+// End-to-end encryption: P-256 identity keys, pairwise-sealed AES-256-GCM room keys.
+// Mirrors zk_chat::e2e so browser and Rust clients interoperate; the server only sees ciphertext.
+const E2E_CONTENT_PREFIX = 'e2e1:';
+const ROOM_KEY_INFO = new TextEncoder().encode('zk-chat room key v1');
+
+function toBase64(bytes) {
+    return btoa(String.fromCharCode(...bytes));
+}
+
+function fromBase64(text) {
+    return Uint8Array.from(atob(text), c => c.charCodeAt(0));
+}
+
+// Little-endian u64 values, used as AES-GCM associated data
+function u64le(...values) {
+    const bytes = new Uint8Array(8 * values.length);
+    const view = new DataView(bytes.buffer);
+    values.forEach((value, i) => view.setBigUint64(i * 8, BigInt(value), true));
+    return bytes;
+}
+
+async function aesSeal(key, data, aad) {
+    const nonce = crypto.getRandomValues(new Uint8Array(12));
+    const ciphertext = new Uint8Array(await crypto.subtle.encrypt({ name: 'AES-GCM', iv: nonce, additionalData: aad }, key, data));
+    const sealed = new Uint8Array(nonce.length + ciphertext.length);
+    sealed.set(nonce);
+    sealed.set(ciphertext, nonce.length);
+    return sealed;
+}
+
+async function aesOpen(key, sealed, aad) {
+    const nonce = sealed.slice(0, 12);
+    return new Uint8Array(await crypto.subtle.decrypt({ name: 'AES-GCM', iv: nonce, additionalData: aad }, key, sealed.slice(12)));
+}
+
+// Identity key fingerprint for out-of-band comparison (mirrors zk_chat::e2e::fingerprint)
+async function fingerprint(publicKey) {
+    const digest = new Uint8Array(await crypto.subtle.digest('SHA-256', fromBase64(publicKey)));
+    const hex = Array.from(digest.slice(0, 16), b => b.toString(16).padStart(2, '0')).join('');
+    return hex.match(/.{4}/g).join(' ');
+}
+
+class E2ESession {
+    // `storageKey` prefixes the saved identity key and the peer keys pinned on first use
+    constructor(userId, storageKey) {
+        this.userId = userId;
+        this.storageKey = storageKey;
+        this.identity = null;
+        this.publicKey = null;
+        this.pinned = new Map(JSON.parse(localStorage.getItem(`${storageKey}.pinnedKeys`) ?? '[]'));  // user id -> base64 public key
+        this.members = new Set();   // peers in the room, who receive room keys
+        this.roomKeys = new Map();  // key id -> { raw, key }
+        this.currentKeyId = null;
+    }
+
+    // Load (or create and save) our identity key and build the KeyAnnounce message
+    async announce() {
+        const algorithm = { name: 'ECDH', namedCurve: 'P-256' };
+        let jwk = JSON.parse(localStorage.getItem(`${this.storageKey}.identityKey`) ?? 'null');
+        if (!jwk) {
+            jwk = await crypto.subtle.exportKey('jwk', (await crypto.subtle.generateKey(algorithm, true, ['deriveBits'])).privateKey);
+            localStorage.setItem(`${this.storageKey}.identityKey`, JSON.stringify(jwk));
+        }
+        const { d: _, ...publicJwk } = jwk;
+        this.identity = {
+            privateKey: await crypto.subtle.importKey('jwk', jwk, algorithm, false, ['deriveBits']),
+            publicKey: await crypto.subtle.importKey('jwk', { ...publicJwk, key_ops: [] }, algorithm, true, []),
+        };
+        this.publicKey = toBase64(new Uint8Array(await crypto.subtle.exportKey('raw', this.identity.publicKey)));
+        return { KeyAnnounce: { user_id: this.userId, public_key: this.publicKey } };
+    }
+
+    // Pin on first use and add the peer to the room; a changed key is refused until trusted
+    async admit(userId, publicKey) {
+        const pinned = this.pinned.get(userId);
+        if (pinned && pinned !== publicKey) {
+            throw new Error(`identity key of user ${userId} changed (pinned ${await fingerprint(pinned)}, announced ${await fingerprint(publicKey)})`);
+        }
+        if (!pinned) this.trust(userId, publicKey);
+        this.members.add(userId);
+    }
+
+    // Accept a new identity key for a user after checking its fingerprint out of band
+    trust(userId, publicKey) {
+        this.pinned.set(userId, publicKey);
+        localStorage.setItem(`${this.storageKey}.pinnedKeys`, JSON.stringify([...this.pinned]));
+    }
+
+    async handleDirectory(keys) {
+        const refused = [];
+        for (const [userId, publicKey] of keys) {
+            if (userId !== this.userId) await this.admit(userId, publicKey).catch(error => refused.push(error));
+        }
+        if (refused.length) throw refused[0];
+    }
+
+    // Record a new peer and share the current room key with it
+    async handleAnnounce(userId, publicKey) {
+        if (userId === this.userId) return [];
+        await this.admit(userId, publicKey);
+        const current = this.roomKeys.get(this.currentKeyId);
+        return current ? [await this.shareWith(userId, current)] : [];
+    }
+
+    // A room key shared by a member becomes the key used for sending
+    async handleShare(from, to, sealedKey) {
+        if (to !== this.userId) return;
+        if (!this.members.has(from)) throw new Error('room key from unknown peer');
+        const raw = await aesOpen(await this.wrappingKey(this.pinned.get(from)), fromBase64(sealedKey), u64le(from, to));
+        this.currentKeyId = await this.addRoomKey(raw);
+    }
+
+    // When members left, the remaining member with the lowest id rotates the room key
+    async handleMembers(online) {
+        const before = this.members.size;
+        this.members = new Set([...this.members].filter(member => online.includes(member)));
+        const rotator = Math.min(this.userId, ...this.members);
+        return this.members.size < before && this.currentKeyId && rotator === this.userId ? this.rotate() : [];
+    }
+
+    // ECDH with the peer, then HKDF-SHA256 into an AES-256-GCM wrapping key
+    async wrappingKey(peerPublicKey) {
+        const peer = await crypto.subtle.importKey('raw', fromBase64(peerPublicKey), { name: 'ECDH', namedCurve: 'P-256' }, false, []);
+        const shared = await crypto.subtle.deriveBits({ name: 'ECDH', public: peer }, this.identity.privateKey, 256);
+        const ikm = await crypto.subtle.importKey('raw', shared, 'HKDF', false, ['deriveKey']);
+        return crypto.subtle.deriveKey(
+            { name: 'HKDF', hash: 'SHA-256', salt: new Uint8Array(), info: ROOM_KEY_INFO },
+            ikm, { name: 'AES-GCM', length: 256 }, false, ['encrypt', 'decrypt']);
+    }
+
+    async addRoomKey(raw) {
+        const digest = new Uint8Array(await crypto.subtle.digest('SHA-256', raw));
+        const keyId = Array.from(digest.slice(0, 4), b => b.toString(16).padStart(2, '0')).join('');
+        const key = await crypto.subtle.importKey('raw', raw, 'AES-GCM', false, ['encrypt', 'decrypt']);
+        this.roomKeys.set(keyId, { raw, key });
+        return keyId;
+    }
+
+    // Generate a new room key and seal it to every member in the room
+    async rotate() {
+        const keyId = await this.addRoomKey(crypto.getRandomValues(new Uint8Array(32)));
+        this.currentKeyId = keyId;
+        const roomKey = this.roomKeys.get(keyId);
+        return Promise.all([...this.members].map(peer => this.shareWith(peer, roomKey)));
+    }
+
+    async shareWith(peer, roomKey) {
+        const sealed = await aesSeal(await this.wrappingKey(this.pinned.get(peer)), roomKey.raw, u64le(this.userId, peer));
+        return { RoomKeyShare: { from: this.userId, to: peer, sealed_key: toBase64(sealed) } };
+    }
+
+    // Encrypt for the room; returns the ciphertext content and any key shares to send first
+    async encrypt(plaintext) {
+        const shares = this.currentKeyId ? [] : await this.rotate();
+        const { key } = this.roomKeys.get(this.currentKeyId);
+        const sealed = await aesSeal(key, new TextEncoder().encode(plaintext), u64le(this.userId));
+        return { ciphertext: `${E2E_CONTENT_PREFIX}${this.currentKeyId}:${toBase64(sealed)}`, shares };
+    }
+
+    // Decrypt content from a sender; plaintext content is returned unchanged
+    async decrypt(senderId, content) {
+        if (!content.startsWith(E2E_CONTENT_PREFIX)) return content;
+        const [keyId, payload] = content.slice(E2E_CONTENT_PREFIX.length).split(':');
+        const roomKey = this.roomKeys.get(keyId);
+        if (!roomKey) throw new Error('unknown room key');
+        return new TextDecoder().decode(await aesOpen(roomKey.key, fromBase64(payload), u64le(senderId)));
+    }
+}
+
+// Chain head gossip: sign the heads the server shows us and compare them with other clients'.
+// Mirrors zk::consistency (ECDSA P-256 / SHA-256, r || s signatures); signing keys are pinned per user.
+const HEAD_SIGNATURE_DOMAIN = new TextEncoder().encode('zk-chat head v2');
+
+function headSigningBytes(head) {
+    const hash = Uint8Array.from(head.chain_hash.match(/../g), byte => parseInt(byte, 16));
+    const bytes = new Uint8Array(HEAD_SIGNATURE_DOMAIN.length + 24 + hash.length);
+    bytes.set(HEAD_SIGNATURE_DOMAIN);
+    bytes.set(u64le(head.user_id, head.salt, head.message_count), HEAD_SIGNATURE_DOMAIN.length);
+    bytes.set(hash, HEAD_SIGNATURE_DOMAIN.length + 24);
+    return bytes;
+}
+
+class ForkMonitor {
+    constructor(userId) {
+        this.userId = userId;
+        this.signer = null;
+        this.publicKey = null;
+        this.pinnedKeys = new Map();  // user id -> base64 public key
+        this.heads = new Map();       // "salt:message count" -> signed head
+    }
+
+    // The server started a new epoch: heads from the old chain no longer compare
+    newEpoch() {
+        this.heads.clear();
+    }
+
+    async init() {
+        this.signer = await crypto.subtle.generateKey({ name: 'ECDSA', namedCurve: 'P-256' }, false, ['sign', 'verify']);
+        this.publicKey = toBase64(new Uint8Array(await crypto.subtle.exportKey('raw', this.signer.publicKey)));
+    }
+
+    async sign(salt, messageCount, chainHash) {
+        const head = { user_id: this.userId, salt: String(salt), message_count: messageCount, chain_hash: chainHash, public_key: this.publicKey };
+        const signature = await crypto.subtle.sign({ name: 'ECDSA', hash: 'SHA-256' }, this.signer.privateKey, headSigningBytes(head));
+        return { ...head, signature: toBase64(new Uint8Array(signature)) };
+    }
+
+    static async verify(head) {
+        try {
+            const key = await crypto.subtle.importKey('raw', fromBase64(head.public_key), { name: 'ECDSA', namedCurve: 'P-256' }, false, ['verify']);
+            return await crypto.subtle.verify({ name: 'ECDSA', hash: 'SHA-256' }, key, fromBase64(head.signature), headSigningBytes(head));
+        } catch {
+            return false;
+        }
+    }
+
+    static async verifyEvidence(evidence) {
+        const { first, second } = evidence;
+        return String(first.salt) === String(second.salt) && first.message_count === second.message_count
+            && first.chain_hash !== second.chain_hash
+            && await ForkMonitor.verify(first) && await ForkMonitor.verify(second);
+    }
+
+    // Record a signed head; returns fork evidence if it conflicts with one already seen for the same count
+    async observe(head) {
+        if (!await ForkMonitor.verify(head)) throw new Error(`invalid head signature from user ${head.user_id}`);
+        const pinned = this.pinnedKeys.get(head.user_id) ?? head.public_key;
+        if (pinned !== head.public_key) throw new Error(`head signing key changed for user ${head.user_id}`);
+        this.pinnedKeys.set(head.user_id, pinned);
+
+        // Heads are compared only within one epoch
+        const position = `${head.salt}:${head.message_count}`;
+        const seen = this.heads.get(position);
+        if (seen) return seen.chain_hash !== head.chain_hash ? { first: seen, second: head } : null;
+        this.heads.set(position, head);
+        return null;
+    }
+}
+
+// Account key registered with the server; join challenges are signed with it (mirrors zk_chat::registry::AccountKey).
+// Kept per host and username so the registered id can be rejoined after a reload.
+const JOIN_SIGNATURE_DOMAIN = new TextEncoder().encode('zk-chat join v1');
+
+class AccountKey {
+    constructor(privateKey, publicKey) {
+        this.privateKey = privateKey;
+        this.publicKey = publicKey;  // base64 SEC1 point, as registered
+    }
+
+    static async load(storageKey) {
+        const algorithm = { name: 'ECDSA', namedCurve: 'P-256' };
+        let jwk = JSON.parse(localStorage.getItem(storageKey) ?? 'null');
+        if (!jwk) {
+            const pair = await crypto.subtle.generateKey(algorithm, true, ['sign', 'verify']);
+            jwk = await crypto.subtle.exportKey('jwk', pair.privateKey);
+            localStorage.setItem(storageKey, JSON.stringify(jwk));
+        }
+        const privateKey = await crypto.subtle.importKey('jwk', jwk, algorithm, false, ['sign']);
+        const { d: _, ...publicJwk } = jwk;
+        const publicKey = await crypto.subtle.importKey('jwk', { ...publicJwk, key_ops: ['verify'] }, algorithm, true, ['verify']);
+        return new AccountKey(privateKey, toBase64(new Uint8Array(await crypto.subtle.exportKey('raw', publicKey))));
+    }
+
+    // Domain || user id || nonce
+    async signJoin(userId, nonce) {
+        const nonceBytes = new TextEncoder().encode(nonce);
+        const bytes = new Uint8Array(JOIN_SIGNATURE_DOMAIN.length + 8 + nonceBytes.length);
+        bytes.set(JOIN_SIGNATURE_DOMAIN);
+        bytes.set(u64le(userId), JOIN_SIGNATURE_DOMAIN.length);
+        bytes.set(nonceBytes, JOIN_SIGNATURE_DOMAIN.length + 8);
+        const signature = await crypto.subtle.sign({ name: 'ECDSA', hash: 'SHA-256' }, this.privateKey, bytes);
+        return toBase64(new Uint8Array(signature));
+    }
+}
+
+// Server checkpoints: chain heads signed with the server's long-term key (mirrors zk::consistency::SignedCheckpoint).
+// The key is pinned per host on first use; a changed key or self-contradicting checkpoints raise an alert.
+const CHECKPOINT_SIGNATURE_DOMAIN = new TextEncoder().encode('zk-chat checkpoint v2');
+
+function checkpointSigningBytes(cp) {
+    const hash = Uint8Array.from(cp.chain_hash.match(/../g), byte => parseInt(byte, 16));
+    const root = Uint8Array.from(cp.message_root.match(/../g), byte => parseInt(byte, 16));
+    const domain = CHECKPOINT_SIGNATURE_DOMAIN.length;
+    // Chain hashes and roots are 64 bytes over f128 and 32 over f64 (zk::Digest)
+    const tail = domain + 8 + hash.length + root.length;
+    const bytes = new Uint8Array(tail + 17);
+    bytes.set(CHECKPOINT_SIGNATURE_DOMAIN);
+    bytes.set(u64le(cp.message_count), domain);
+    bytes.set(hash, domain + 8);
+    bytes.set(root, domain + 8 + hash.length);
+    bytes.set(u64le(cp.salt), tail);
+    bytes[tail + 8] = cp.proof_ref == null ? 0 : 1;
+    bytes.set(u64le(cp.proof_ref ?? 0), tail + 9);
+    return bytes;
+}
+
+class CheckpointVerifier {
+    constructor() {
+        this.key = null;
+        this.salt = null;
+        this.latest = null;
+        this.previousSalts = new Set();
+    }
+
+
+    // Fetch the server key and pin it for this host on first use
+    async pin() {
+        const { public_key } = await (await fetch('/api/server-key')).json();
+        const storageKey = `zkchat.serverKey.${window.location.host}`;
+        const pinned = localStorage.getItem(storageKey);
+        if (pinned && pinned !== public_key) {
+            throw new Error('server checkpoint key changed since it was pinned');
+        }
+        localStorage.setItem(storageKey, public_key);
+        this.key = await crypto.subtle.importKey('raw', fromBase64(public_key), { name: 'ECDSA', namedCurve: 'P-256' }, false, ['verify']);
+    }
+
+    async check(cp) {
+        if (!this.key) throw new Error('no pinned server key');
+        const valid = await crypto.subtle.verify({ name: 'ECDSA', hash: 'SHA-256' }, this.key, fromBase64(cp.signature), checkpointSigningBytes(cp));
+        if (!valid) throw new Error(`checkpoint at ${cp.message_count} messages not signed by pinned server key`);
+        // The epoch switches only on a signed checkpoint of an empty chain under a salt not seen before
+        if (this.salt !== null && String(cp.salt) !== this.salt) {
+            const freshChain = cp.message_count === 0 && /^0+$/.test(cp.chain_hash);
+            if (!freshChain || this.previousSalts.has(String(cp.salt))) throw new Error('server changed its session salt');
+            this.previousSalts.add(this.salt);
+            this.latest = null;
+        }
+        const latest = this.latest;
+        if (latest) {
+            if (cp.message_count < latest.message_count) throw new Error(`server rewound from ${latest.message_count} to ${cp.message_count} messages`);
+            if (cp.message_count === latest.message_count && (cp.chain_hash !== latest.chain_hash || cp.message_root !== latest.message_root)) {
+                throw new Error(`server signed two heads at ${cp.message_count} messages`);
+            }
+        }
+        this.salt = String(cp.salt);
+        this.latest = cp;
+    }
+}
+
 class ZKChat {
     constructor() {
         this.ws = null;
         this.userId = null;
         this.username = null;
+        this.joined = false;
+        this.chainSalt = null;
+        this.serverShutdown = false;
         this.messageCounter = 0;
         this.isConnected = false;
+        this.latestChainProof = null;
+        this.accountKey = null;
+        this.e2e = null;
+        this.forks = null;
+        this.forkAlerts = new Set();
+        this.checkpoints = new CheckpointVerifier();
+        this.inbox = Promise.resolve();
     }
 
-    connect(userId, username) {
-        this.userId = parseInt(userId);
+    connect(username) {
         this.username = username;
 
         // Connect to WebSocket server
-        const wsUrl = `ws://${window.location.host}/ws`;
+        const scheme = window.location.protocol === 'https:' ? 'wss' : 'ws';
+        const wsUrl = `${scheme}://${window.location.host}/ws`;
         this.ws = new WebSocket(wsUrl);
 
-        this.ws.onopen = () => {
+        this.ws.onopen = async () => {
             console.log('Connected to ZK Chat server');
             this.updateConnectionStatus(true);
-            
-            // Send join message
-            this.sendProtocolMessage({
-                Join: {
-                    user_id: this.userId,
-                    username: this.username
-                }
-            });
+            this.accountKey = await AccountKey.load(`${this.userIdKey()}.accountKey`);
+
+            // Ids are allocated by the server; reuse the one registered for this username
+            const storedId = localStorage.getItem(this.userIdKey());
+            if (storedId) {
+                this.join(parseInt(storedId));
+            } else {
+                this.register();
+            }
         };
 
         this.ws.onmessage = (event) => {
             try {
                 const message = JSON.parse(event.data);
-                this.handleServerMessage(message);
+                // Handle messages one at a time so decryption keeps broadcast order
+                this.inbox = this.inbox
+                    .then(() => this.handleServerMessage(message))
+                    .catch(error => console.error('Failed to handle server message:', error));
             } catch (error) {
                 console.error('Failed to parse server message:', error);
                 this.showError('Invalid message from server');
@@ -41,7 +395,9 @@ class ZKChat {
         this.ws.onclose = () => {
             console.log('Disconnected from server');
             this.updateConnectionStatus(false);
-            this.showError('Connection lost. Please refresh to reconnect.');
+            this.showError(this.serverShutdown
+                ? 'Server stopped. Refresh once it is back to reconnect.'
+                : 'Connection lost. Please refresh to reconnect.');
         };
 
         this.ws.onerror = (error) => {
@@ -50,6 +406,28 @@ class ZKChat {
         };
     }
 
+    userIdKey() {
+        return `zkchat.userId.${window.location.host}.${this.username.toLowerCase()}`;
+    }
+
+    register() {
+        this.sendProtocolMessage({ Register: { username: this.username, public_key: this.accountKey.publicKey } });
+    }
+
+    join(userId) {
+        this.userId = userId;
+        this.sendProtocolMessage({
+            Join: {
+                user_id: this.userId,
+                username: this.username
+            }
+        });
+        this.e2e = new E2ESession(this.userId, this.userIdKey());
+        this.forks = new ForkMonitor(this.userId);
+        this.forks.init();
+        this.checkpoints.pin().catch(error => this.showError(`⚠ ${error.message}`));
+    }
+
     async sendMessage(content) {
         if (!this.isConnected || !content.trim()) {
             return;
@@ -57,17 +435,22 @@ class ZKChat {
 
         this.messageCounter++;
         const timestamp = Math.floor(Date.now() / 1000);
-        
-        // Backend computes real ZK hash and generates proof
-        const messageRequest = {
-            id: this.messageCounter,
-            sender_id: this.userId,
-            content: content.trim(),
-            timestamp: timestamp
-        };
+        const plaintext = content.trim();
 
-        // Generate REAL ZK proof via backend (which computes correct Poseidon hash)
         try {
+            // Encrypt for the room; the server hashes and proves the ciphertext only
+            const { ciphertext, shares } = await this.e2e.encrypt(plaintext);
+            shares.forEach(share => this.sendProtocolMessage(share));
+
+            // Backend computes real ZK hash and generates proof
+            const messageRequest = {
+                id: this.messageCounter,
+                sender_id: this.userId,
+                content: ciphertext,
+                timestamp: timestamp
+            };
+
+            // Generate REAL ZK proof via backend (which computes correct Poseidon hash)
             const res = await fetch('/api/prove', {
                 method: 'POST',
                 headers: { 'Content-Type': 'application/json' },
@@ -92,7 +475,7 @@ class ZKChat {
             });
 
             // Show message as sending (will be updated when broadcast received)
-            this.displayMessage(verifiedMessage, false, true, proofBase64);
+            this.displayMessage({ ...verifiedMessage, plaintext }, false, true, proofBase64);
         } catch (error) {
             console.error('Failed to generate ZK proof:', error);
             this.showError('Failed to generate ZK proof for message');
@@ -105,20 +488,106 @@ class ZKChat {
         }
     }
 
-    handleServerMessage(message) {
+    async handleServerMessage(message) {
         console.log('Received message:', message);
 
-        if (message.MessageBroadcast) {
-            const { message: msg, verified, local_id } = message.MessageBroadcast;
+        if (message.Registered) {
+            const user = message.Registered.user;
+            if (this.userId === null && user.active) {
+                localStorage.setItem(this.userIdKey(), user.id);
+                this.username = user.username;
+                this.join(user.id);
+            }
+            return;
+        }
+        if (message.Error && this.userId !== null && !this.joined && message.Error.code === 1009) {
+            // Stored id is unknown to this server (or deactivated): register afresh
+            localStorage.removeItem(this.userIdKey());
+            this.userId = null;
+            this.register();
+            return;
+        }
+        if (this.userId === null && !message.Error) {
+            // Broadcasts before joining are of no use to us
+            return;
+        }
+
+        if (message.JoinChallenge) {
+            // Prove we hold the registered account key, then publish our E2E identity key
+            // so members can share the room key with us
+            const signature = await this.accountKey.signJoin(this.userId, message.JoinChallenge.nonce);
+            this.sendProtocolMessage({ JoinProof: { signature } });
+            this.sendProtocolMessage(await this.e2e.announce());
+            console.log(`Identity key fingerprint: ${await fingerprint(this.e2e.publicKey)}`);
+        } else if (message.MessageBroadcast) {
+            const { message: msg, verified, local_id, proof_ref } = message.MessageBroadcast;
             if (msg.sender_id === this.userId) {
                 // Update our own message with server-computed hash and verification status
-                this.updateOwnMessage(msg, verified, local_id);
+                this.updateOwnMessage(msg, verified, local_id, proof_ref);
             } else {
-                // Display other users' messages
-                this.displayMessage(msg, verified, false, null, local_id);
+                // Display other users' messages, decrypted with the room key
+                const plaintext = await this.e2e.decrypt(msg.sender_id, msg.content)
+                    .catch(error => `🔒 Unable to decrypt (${error.message})`);
+                this.displayMessage({ ...msg, plaintext }, verified, false, null, local_id, proof_ref);
+            }
+        } else if (message.ChainState) {
+            // Reply to our Join (the server accepted our registered id), or a new epoch after a salt rotation
+            const salt = String(message.ChainState.salt);
+            if (this.joined && this.chainSalt !== salt) {
+                this.forks.newEpoch();
+                console.log(`Server started a new chain epoch (salt ${salt})`);
             }
+            this.chainSalt = salt;
+            this.joined = true;
+        } else if (message.KeyDirectory) {
+            await this.e2e.handleDirectory(message.KeyDirectory.keys).catch(error => this.showError(`⚠ ${error.message}`));
+        } else if (message.KeyAnnounce) {
+            const { user_id, public_key } = message.KeyAnnounce;
+            const shares = await this.e2e.handleAnnounce(user_id, public_key)
+                .catch(error => { this.showError(`⚠ ${error.message}`); return []; });
+            shares.forEach(share => this.sendProtocolMessage(share));
+        } else if (message.RoomKeyShare) {
+            const { from, to, sealed_key } = message.RoomKeyShare;
+            await this.e2e.handleShare(from, to, sealed_key);
+        } else if (message.ChainProof) {
+            // Compressed chain proof, published once per batch of messages
+            const { message_count, final_hash, message_root, proof } = message.ChainProof;
+            this.latestChainProof = { messageCount: message_count, finalHash: final_hash, messageRoot: message_root, size: proof.length };
+            console.log(`Chain proof received for ${message_count} messages (${proof.length} bytes)`);
+
+            // Gossip the head the server showed us so other clients can compare
+            const head = await this.forks.sign(this.chainSalt, message_count, final_hash);
+            const evidence = await this.forks.observe(head);
+            if (evidence) this.reportFork(evidence);
+            this.sendProtocolMessage({ HeadGossip: { head } });
+        } else if (message.Checkpoint) {
+            const cp = message.Checkpoint;
+            try {
+                await this.checkpoints.check(cp);
+                const proof = this.latestChainProof;
+                if (proof && proof.messageCount === cp.message_count && proof.finalHash !== cp.chain_hash) {
+                    this.showError(`⚠ Server signed a different head than its chain proof at ${cp.message_count} messages`);
+                }
+            } catch (error) {
+                this.showError(`⚠ Server checkpoint rejected: ${error.message}`);
+            }
+        } else if (message.HeadGossip) {
+            const evidence = await this.forks.observe(message.HeadGossip.head)
+                .catch(error => { console.warn('Rejected head gossip:', error); return null; });
+            if (evidence) this.reportFork(evidence);
+        } else if (message.ForkAlert) {
+            if (await ForkMonitor.verifyEvidence(message.ForkAlert.evidence)) {
+                this.showForkAlert(message.ForkAlert.evidence);
+            }
+        } else if (message.ServerShutdown) {
+            this.serverShutdown = true;
+            this.showError(`Server shutting down: ${message.ServerShutdown.reason}`);
         } else if (message.UserListUpdate) {
             this.updateUsersList(message.UserListUpdate.users);
+            if (this.e2e) {
+                const shares = await this.e2e.handleMembers(message.UserListUpdate.users.map(([id]) => id));
+                shares.forEach(share => this.sendProtocolMessage(share));
+            }
         } else if (message.Error) {
             this.showError(`Server Error ${message.Error.code}: ${message.Error.message}`);
         } else if (message.Pong) {
@@ -126,7 +595,20 @@ class ZKChat {
         }
     }
 
-    updateOwnMessage(serverMessage, verified, localId) {
+    // Tell everyone about divergent heads we detected ourselves
+    reportFork(evidence) {
+        this.showForkAlert(evidence);
+        this.sendProtocolMessage({ ForkAlert: { evidence } });
+    }
+
+    showForkAlert(evidence) {
+        const { first, second } = evidence;
+        if (this.forkAlerts.has(first.message_count)) return;
+        this.forkAlerts.add(first.message_count);
+        this.showError(`⚠ Fork detected at ${first.message_count} messages: user ${first.user_id} and user ${second.user_id} were shown different chain histories`);
+    }
+
+    updateOwnMessage(serverMessage, verified, localId, proofRef = null) {
         // Find and update our own message with server-computed data
         const messagesDiv = document.getElementById('messages');
         const messageElements = messagesDiv.querySelectorAll('.message-self');
@@ -145,7 +627,8 @@ class ZKChat {
                     ...messageData,
                     ...serverMessage,
                     verified: verified,
-                    local_id: localId
+                    local_id: localId,
+                    proof_ref: proofRef
                 };
                 messageElement.dataset.messageData = JSON.stringify(updatedData);
                 
@@ -165,7 +648,7 @@ class ZKChat {
         }
     }
 
-    displayMessage(message, verified, isSelf, proof = null, localId = null) {
+    displayMessage(message, verified, isSelf, proof = null, localId = null, proofRef = null) {
         const messagesDiv = document.getElementById('messages');
         const messageDiv = document.createElement('div');
         
@@ -187,13 +670,14 @@ class ZKChat {
                 <span>${timestamp}</span>
                 <button class="inspect-btn" onclick="chat.showMessageDetails('${message.id}')" title="Inspect ZK Proof & Metadata">🔍</button>
             </div>
-            <div class="message-content">${this.escapeHtml(message.content)}</div>
+            <div class="message-content">${this.escapeHtml(message.plaintext ?? message.content)}</div>
         `;
 
         // Store message data for inspection
         messageDiv.dataset.messageData = JSON.stringify({
             ...message,
             local_id: localId,
+            proof_ref: proofRef,
             proof: proof,
             verified: verified,
             zkProofSize: proof ? new Blob([proof]).size : 0
@@ -224,7 +708,8 @@ class ZKChat {
                         <table>
                             <tr><td><strong>ID:</strong></td><td>${messageData.id}</td></tr>
                             <tr><td><strong>Sender ID:</strong></td><td>${messageData.sender_id}</td></tr>
-                            <tr><td><strong>Content:</strong></td><td>${this.escapeHtml(messageData.content)}</td></tr>
+                            <tr><td><strong>Content:</strong></td><td>${this.escapeHtml(messageData.plaintext ?? messageData.content)}</td></tr>
+                            ${messageData.plaintext !== undefined && messageData.plaintext !== messageData.content ? `<tr><td><strong>Ciphertext:</strong></td><td class="hash-display">${this.escapeHtml(messageData.content)}</td></tr>` : ''}
                             <tr><td><strong>Timestamp:</strong></td><td>${new Date(messageData.timestamp * 1000).toLocaleString()}</td></tr>
                         </table>
                     </div>
@@ -248,6 +733,7 @@ class ZKChat {
                                 </details>
                             </div>
                         ` : '<p>No proof data available</p>'}
+                        ${messageData.proof_ref ? `<p><strong>Batch Proof:</strong> chain proof covering ${messageData.proof_ref} messages</p>` : ''}
                     </div>
                     
                     <div class="detail-section">
@@ -417,16 +903,10 @@ const chat = new ZKChat();
 
 // UI Functions
 function connect() {
-    const userId = document.getElementById('userIdInput').value;
-    const username = document.getElementById('usernameInput').value;
+    const username = document.getElementById('usernameInput').value.trim();
 
-    if (!userId || !username) {
-        alert('Please enter both User ID and Username');
-        return;
-    }
-
-    if (userId < 1 || userId > 9999) {
-        alert('User ID must be between 1 and 9999');
+    if (!/^[A-Za-z0-9_.-]{3,32}$/.test(username)) {
+        alert('Username must be 3-32 letters, digits, "_", "-" or "."');
         return;
     }
 
@@ -435,7 +915,7 @@ function connect() {
     document.getElementById('chatArea').classList.remove('hidden');
 
     // Connect to server
-    chat.connect(userId, username);
+    chat.connect(username);
 }
 
 async function sendMessage() {
@@ -451,7 +931,6 @@ async function sendMessage() {
 // Enter key to send message
 document.addEventListener('DOMContentLoaded', () => {
     const messageInput = document.getElementById('messageInput');
-    const userIdInput = document.getElementById('userIdInput');
     const usernameInput = document.getElementById('usernameInput');
 
     messageInput.addEventListener('keypress', (e) => {
@@ -466,14 +945,6 @@ document.addEventListener('DOMContentLoaded', () => {
         }
     });
 
-    userIdInput.addEventListener('keypress', (e) => {
-        if (e.key === 'Enter') {
-            connect();
-        }
-    });
-
-    // Generate random user ID
-    userIdInput.value = Math.floor(Math.random() * 9000) + 1000;
 
     // Wire up debug panel buttons after DOM loads
     const btnBuildTrace = document.getElementById('btnBuildTrace');
@@ -0,0 +1,62 @@
+pub mod common;
+
+use common::head;
+use zk_chat::{Message, ZkChatError, registry::UserRegistry, websocket::{ProtocolMessage, error_codes}};
+use zk_chat::zk::{Digest, MessageChain, chain_step, rotate_session_salt, session_salt, batch::{BatchConfig, BatchProver}, consistency::{CheckpointVerifier, ServerSigningKey}};
+
+#[test]
+fn banned_users_cannot_join_until_unbanned() {
+    let mut registry = UserRegistry::in_memory();
+    let mallory = registry.register("mallory", None).unwrap();
+    registry.set_banned(mallory.id, true).unwrap();
+
+    let err = registry.active_user(mallory.id).unwrap_err();
+    assert!(matches!(err, ZkChatError::UserBanned(_)));
+    assert!(matches!(ProtocolMessage::from(err), ProtocolMessage::Error { code: error_codes::UNAUTHORIZED, .. }));
+
+    registry.set_banned(mallory.id, false).unwrap();
+    assert!(registry.active_user(mallory.id).is_ok());
+}
+
+#[test]
+fn salt_rotation_starts_a_new_chain() {
+    let message = Message::new(1, 1, "hello".into(), 100);
+    let mut before = MessageChain::new();
+    before.add_message(message.clone()).unwrap();
+
+    let old_salt = session_salt();
+    let new_salt = rotate_session_salt();
+    assert_ne!(old_salt, new_salt);
+    assert_eq!(session_salt(), new_salt);
+
+    // The same message heads a different chain under the new salt
+    let mut after = MessageChain::new();
+    after.add_message(message.clone()).unwrap();
+    assert_ne!(before.chain_hash, after.chain_hash);
+    assert_eq!(after.chain_hash, chain_step(&Digest::ZERO, &message.hash));
+}
+
+#[test]
+fn checkpoints_follow_epoch_rotation() {
+    let server = ServerSigningKey::generate();
+    let mut verifier = CheckpointVerifier::new(server.public_key());
+    verifier.check(&server.sign(7, head(1), Digest::ZERO, 11, Some(7))).unwrap();
+
+    // A new salt is only taken from the signed head of its empty chain; then counts restart and the old salt is refused
+    assert!(verifier.check(&server.sign(1, head(2), Digest::ZERO, 12, Some(1))).is_err());
+    verifier.check(&server.sign(0, Digest::ZERO, Digest::ZERO, 12, None)).unwrap();
+    verifier.check(&server.sign(1, head(2), Digest::ZERO, 12, Some(1))).unwrap();
+    assert!(verifier.check(&server.sign(8, head(3), Digest::ZERO, 11, Some(8))).is_err());
+    assert!(verifier.check(&server.sign(0, Digest::ZERO, Digest::ZERO, 11, None)).is_err(), "earlier epochs stay closed");
+    assert_eq!(verifier.previous_epochs().len(), 1);
+    assert_eq!(verifier.previous_epochs()[0].message_count, 7);
+}
+
+#[test]
+fn requeued_batch_is_proven_first() {
+    let mut queue = BatchProver::new(BatchConfig::default());
+    queue.enqueue(Message::new(3, 1, "c".into(), 3));
+    queue.requeue(vec![Message::new(1, 1, "a".into(), 1), Message::new(2, 1, "b".into(), 2)]);
+    let ids: Vec<u64> = queue.take_batch().iter().map(|m| m.id).collect();
+    assert_eq!(ids, [1, 2, 3]);
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+use zk_chat::{Message, zk::{MessageChain, batch::{prove_batch, BatchConfig, BatchProver}, prover::{verify_proof, MessageProver, ProofProfile}, segment::{verify_segments, ChainBoundary, SegmentProof}}, ZkChatError};
+
+#[test]
+fn batch_ready_on_count_or_window() {
+    let mut queue = BatchProver::new(BatchConfig { max_batch_size: 2, max_wait: Duration::from_secs(60) });
+    assert_eq!(queue.time_until_ready(), None);
+    assert!(!queue.enqueue(Message::new(1, 1, "a".into(), 1000)));
+    assert!(!queue.is_ready());
+    assert!(queue.enqueue(Message::new(2, 1, "b".into(), 1001)));
+    assert!(queue.is_ready());
+    assert_eq!(queue.take_batch().len(), 2);
+    assert_eq!(queue.pending_len(), 0);
+
+    let mut timed = BatchProver::new(BatchConfig { max_batch_size: 100, max_wait: Duration::ZERO });
+    timed.enqueue(Message::new(1, 1, "a".into(), 1000));
+    assert!(timed.is_ready(), "window elapsed");
+}
+
+#[test]
+fn one_proof_covers_whole_batch() {
+    let mut chain = MessageChain::new();
+    chain.add_message(Message::new(1, 1, "before".into(), 1000)).unwrap();
+
+    let batch = vec![
+        Message::new(2, 2, "x".into(), 1001),
+        Message::new(3, 3, "y".into(), 1002),
+        Message::new(4, 2, "z".into(), 1002), // non-monotonic timestamp
+    ];
+    let outcome = prove_batch(&chain, batch);
+    assert!(outcome.verified);
+    assert_eq!(outcome.accepted.len(), 2);
+    assert_eq!(outcome.rejected.len(), 1);
+    assert!(matches!(outcome.rejected[0].1, ZkChatError::InvalidTimestamp));
+    assert_eq!(outcome.proof_ref(), Some(3));
+
+    // The proof covers only the batch, continuing from the chain's previous head
+    let segment = outcome.proof.clone().unwrap();
+    assert_eq!(segment.public_inputs.message_count, 2);
+    assert_eq!(ChainBoundary::start_of(&segment.public_inputs), chain.end());
+    verify_proof(&segment.proof, segment.public_inputs.clone(), ProofProfile::Standard).unwrap();
+
+    // Together with a proof of the earlier chain it composes into the whole history
+    let earlier = SegmentProof { public_inputs: chain.public_inputs(), proof: MessageProver::new().prove(&chain.messages).unwrap() };
+    let history = verify_segments(&[earlier, segment], ProofProfile::Standard).unwrap();
+    assert_eq!((history.message_count, history.end), (3, outcome.chain.end()));
+}
+
+#[test]
+fn rejected_message_does_not_leave_a_gap() {
+    let chain = MessageChain::new();
+    let batch = vec![
+        Message::new(1, 5, "first".into(), 1000),
+        Message::new(2, 5, "stale".into(), 999), // non-monotonic timestamp
+        Message::new(3, 5, "third".into(), 1001),
+        Message::new(1, 6, "other sender".into(), 1002),
+    ];
+    let outcome = prove_batch(&chain, batch);
+    assert!(outcome.verified);
+    let ids: Vec<(u64, u64)> = outcome.accepted.iter().map(|m| (m.sender_id, m.id)).collect();
+    assert_eq!(ids, vec![(5, 1), (5, 2), (6, 1)]);
+    assert!(outcome.accepted.iter().all(Message::verify_hash));
+    assert_eq!(outcome.dropped(), [(5, 1)].into_iter().collect());
+
+    // Messages already queued behind the batch move down too
+    let mut queue = BatchProver::new(BatchConfig::default());
+    queue.enqueue(Message::new(4, 5, "queued".into(), 1003));
+    queue.enqueue(Message::new(2, 6, "queued".into(), 1004));
+    queue.close_gaps(&outcome.dropped());
+    let mut next = outcome.chain.clone();
+    for message in queue.take_batch() {
+        next.add_message(message).unwrap();
+    }
+    assert_eq!(next.messages[3].id, 3);
+}
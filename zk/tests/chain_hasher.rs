@@ -0,0 +1,91 @@
+pub mod common;
+
+use common::TestChain;
+use zk_chat::{
+    config::ServerConfig,
+    zk::{
+        air::PublicInputs,
+        hasher::HashId,
+        prover::{verify_proof, MessageProver, ProofProfile},
+        MessageChain,
+    },
+    Message, ZkChatError,
+};
+
+#[test]
+fn poseidon_hashes_prove_and_bind_their_id() {
+    let mut heads = Vec::new();
+    for hash_id in [HashId::Poseidon, HashId::PoseidonSeparated] {
+        let chain = TestChain::new("hash").hashed_with(hash_id).chain(3);
+        assert!(chain.messages.iter().all(|m| m.hash_id == hash_id && m.verify_hash()));
+        let proof = MessageProver::with_profile(ProofProfile::Fast).prove(&chain.messages).unwrap();
+        let pub_inputs = chain.public_inputs();
+        assert_eq!(pub_inputs.hash_id, hash_id);
+        verify_proof(&proof, pub_inputs.clone(), ProofProfile::Fast).unwrap();
+
+        // Claiming another hash for the same transcript fails
+        for other in [HashId::Poseidon, HashId::RescuePrime, HashId::Blake3, HashId::PoseidonSeparated] {
+            if other != hash_id {
+                let claimed = PublicInputs { hash_id: other, ..pub_inputs.clone() };
+                assert!(verify_proof(&proof, claimed, ProofProfile::Fast).is_err());
+            }
+        }
+        heads.push(chain.chain_hash);
+    }
+    assert_ne!(heads[0], heads[1]);
+}
+
+#[test]
+fn hashes_outside_the_air_are_not_provable() {
+    let mut heads = vec![TestChain::new("hash").hashed_with(HashId::Poseidon).chain(3).chain_hash];
+    for hash_id in [HashId::RescuePrime, HashId::Blake3] {
+        let chain = TestChain::new("hash").hashed_with(hash_id).chain(3);
+        assert!(chain.messages.iter().all(|m| m.verify_hash()));
+        assert!(matches!(
+            MessageProver::with_profile(ProofProfile::Fast).prove(&chain.messages),
+            Err(ZkChatError::ProofGeneration(_))
+        ));
+        heads.push(chain.chain_hash);
+
+        let mut config = ServerConfig::default();
+        config.set("hash", &hash_id.to_string()).unwrap();
+        assert!(matches!(config.validate(), Err(ZkChatError::Config(_))));
+    }
+    assert!(heads.iter().enumerate().all(|(i, head)| !heads[..i].contains(head)));
+}
+
+#[test]
+fn chains_reject_messages_hashed_differently() {
+    let mut chain = MessageChain::with_hash(HashId::Blake3);
+    let message = Message::hashed_with(HashId::Poseidon, 1, 1, "mixed".into(), 10);
+    assert!(matches!(
+        chain.add_message(message),
+        Err(ZkChatError::HashMismatch { expected: HashId::Blake3, found: HashId::Poseidon })
+    ));
+
+    let mut messages = TestChain::new("hash").hashed_with(HashId::Blake3).chain(3).messages;
+    messages.push(Message::hashed_with(HashId::RescuePrime, 4, 1, "late".into(), 1003));
+    assert!(matches!(
+        MessageProver::with_profile(ProofProfile::Fast).prove(&messages),
+        Err(ZkChatError::HashMismatch { .. })
+    ));
+}
+
+#[test]
+fn transcripts_without_hash_id_use_the_default() {
+    let chain = TestChain::new("hash").chain(3);
+    let proof = MessageProver::with_profile(ProofProfile::Fast).prove(&chain.messages).unwrap();
+
+    let mut message = serde_json::to_value(&chain.messages[0]).unwrap();
+    message.as_object_mut().unwrap().remove("hash_id");
+    let message: Message = serde_json::from_value(message).unwrap();
+    assert_eq!(message.hash_id, HashId::default());
+    assert!(message.verify_hash());
+
+    let mut pub_inputs = serde_json::to_value(chain.public_inputs()).unwrap();
+    pub_inputs.as_object_mut().unwrap().remove("hash_id");
+    verify_proof(&proof, serde_json::from_value(pub_inputs).unwrap(), ProofProfile::Fast).unwrap();
+
+    assert_eq!("rescue_prime".parse::<HashId>().unwrap(), HashId::RescuePrime);
+    assert!("sha1".parse::<HashId>().is_err());
+}
@@ -0,0 +1,92 @@
+//! Helpers shared by the integration tests; test crates declare `pub mod common` so unused helpers stay exported
+
+use std::ops::Range;
+use winterfell::{math::FieldElement, Air, EvaluationFrame};
+use zk_chat::{
+    zk::{
+        air::{self, MessageAir, PublicInputs, TRACE_WIDTH},
+        field::{self, BaseElement},
+        hasher::HashId,
+        prover::{MessageProver, ProofProfile},
+        Digest, MessageChain,
+    },
+    Message,
+};
+
+/// Test chain: message k (from 0) reads `"{label} {k}"` at time `1000 + k`.
+/// Senders `first_sender..first_sender + senders` take turns, each numbering its own messages from 1.
+#[derive(Debug, Clone)]
+pub struct TestChain {
+    label: String,
+    first_sender: u64,
+    senders: u64,
+    hash_id: HashId,
+}
+
+impl TestChain {
+    /// One sender (id 1) hashing with the default hash
+    pub fn new(label: impl Into<String>) -> Self {
+        Self { label: label.into(), first_sender: 1, senders: 1, hash_id: HashId::default() }
+    }
+
+    /// Let `senders` senders, from `first_sender` on, take turns
+    pub fn senders(self, first_sender: u64, senders: u64) -> Self {
+        Self { first_sender, senders, ..self }
+    }
+
+    /// Hash every message with `hash_id`
+    pub fn hashed_with(self, hash_id: HashId) -> Self {
+        Self { hash_id, ..self }
+    }
+
+    /// Messages at chain positions `positions`
+    pub fn messages(&self, positions: Range<u64>) -> Vec<Message> {
+        positions
+            .map(|k| {
+                let (sender_id, id) = (self.first_sender + k % self.senders, k / self.senders + 1);
+                Message::hashed_with(self.hash_id, id, sender_id, format!("{} {}", self.label, k), 1000 + k)
+            })
+            .collect()
+    }
+
+    /// The first `count` messages as a chain
+    pub fn chain(&self, count: u64) -> MessageChain {
+        let mut chain = MessageChain::with_hash(self.hash_id);
+        for message in self.messages(0..count) {
+            chain.add_message(message).unwrap();
+        }
+        chain
+    }
+
+    /// The first `count` messages as a chain, with a proof of it under `profile`
+    pub fn proven(&self, count: u64, profile: ProofProfile) -> (MessageChain, Vec<u8>) {
+        let chain = self.chain(count);
+        let proof = MessageProver::with_profile(profile).prove(&chain.messages).unwrap();
+        (chain, proof)
+    }
+}
+
+/// Distinct placeholder chain head
+pub fn head(n: u64) -> Digest {
+    Digest::new([field::element(n); 4])
+}
+
+/// AIR for a trace built by hand, with the fast proof options
+pub fn message_air(trace: &[Vec<BaseElement>], pub_inputs: PublicInputs) -> MessageAir {
+    MessageAir::new(air::trace_info(trace[0].len()), pub_inputs, ProofProfile::Fast.options())
+}
+
+/// Steps whose transition constraints do not all evaluate to zero
+pub fn violated_steps(air: &MessageAir, trace: &[Vec<BaseElement>]) -> Vec<usize> {
+    let mut result = vec![BaseElement::ZERO; air.context().num_main_transition_constraints()];
+    let periodic = air.get_periodic_column_values();
+    (0..trace[0].len() - 1)
+        .filter(|&step| {
+            let row = |s: usize| (0..TRACE_WIDTH).map(|c| trace[c][s]).collect();
+            let frame = EvaluationFrame::from_rows(row(step), row(step + 1));
+            let values: Vec<_> = periodic.iter().map(|column| column[step % column.len()]).collect();
+            air.evaluate_transition(&frame, &values, &mut result);
+            result.iter().any(|&value| value != BaseElement::ZERO)
+        })
+        .collect()
+}
@@ -0,0 +1,54 @@
+use serde_json::json;
+use zk_chat::{
+    zk::{
+        digest::DIGEST_BYTES,
+        field::{self, ELEMENT_BYTES, MODULUS},
+        hasher::HashId,
+        storage::{StoredChain, CHAIN_FORMAT_VERSION},
+        Digest, MessageChain,
+    },
+    Message, ZkChatError,
+};
+
+#[test]
+fn encoding_is_canonical() {
+    let message = Message::new(1, 2, "digest".into(), 30);
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["hash"].as_str().unwrap().len(), 2 * DIGEST_BYTES);
+    assert_eq!(serde_json::from_value::<Message>(json).unwrap(), message);
+
+    // Short encodings and values at the modulus are rejected, so every digest has exactly one encoding
+    assert!(matches!(Digest::from_bytes(&[0u8; 31]), Err(ZkChatError::InvalidDigest(_))));
+    let mut bytes = message.hash.to_bytes();
+    bytes[..ELEMENT_BYTES].copy_from_slice(&MODULUS.to_le_bytes()[..ELEMENT_BYTES]);
+    assert!(matches!(Digest::from_bytes(&bytes), Err(ZkChatError::InvalidDigest(_))));
+    assert!(serde_json::from_value::<Digest>(json!(hex::encode([0xffu8; DIGEST_BYTES]))).is_err());
+}
+
+#[test]
+fn digests_keep_every_element_bit() {
+    let low = Digest::new([field::element(1); 4]);
+    #[cfg(not(feature = "f64"))]
+    {
+        let high = Digest::new([field::element_from_u128(1 + (1 << 64)); 4]);
+        assert_ne!(low, high);
+        assert_ne!(low.to_bytes(), high.to_bytes());
+    }
+    assert_eq!(low.to_string().parse::<Digest>().unwrap(), low);
+}
+
+#[test]
+fn other_chain_format_versions_are_refused() {
+    let mut chain = MessageChain::with_hash(HashId::default());
+    chain.add_message(Message::new(1, 4, "stored".into(), 700)).unwrap();
+    let path = std::env::temp_dir().join(format!("zk_chat_digest_version_{}.json", std::process::id()));
+    StoredChain::new(&chain, 5).save(&path).unwrap();
+    let stored = StoredChain::load(&path).unwrap().expect("chain was written");
+    assert_eq!((stored.version, stored.epoch), (CHAIN_FORMAT_VERSION, 5));
+
+    let mut other = serde_json::to_value(&stored).unwrap();
+    other["version"] = json!(CHAIN_FORMAT_VERSION + 1);
+    std::fs::write(&path, serde_json::to_vec(&other).unwrap()).unwrap();
+    assert!(matches!(StoredChain::load(&path), Err(ZkChatError::ChainInconsistency(_))));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,130 @@
+use zk_chat::{Message, e2e::{fingerprint, is_encrypted, E2eSession, IdentityKey}, websocket::ProtocolMessage, zk::{MessageChain, prover::{MessageProver, ProofProfile, verify_proof}}};
+
+fn public_key(announce: &ProtocolMessage) -> String {
+    match announce {
+        ProtocolMessage::KeyAnnounce { public_key, .. } => public_key.clone(),
+        other => panic!("expected KeyAnnounce, got {:?}", other),
+    }
+}
+
+fn deliver(shares: Vec<ProtocolMessage>, recipient: &mut E2eSession) {
+    for share in shares {
+        if let ProtocolMessage::RoomKeyShare { from, to, sealed_key } = share {
+            recipient.handle_share(from, to, &sealed_key).unwrap();
+        }
+    }
+}
+
+#[test]
+fn room_key_shared_between_clients() {
+    let mut alice = E2eSession::new(1);
+    let mut bob = E2eSession::new(2);
+    bob.handle_directory(&[(1, public_key(&alice.announce()))]).unwrap();
+    assert!(alice.handle_announce(2, public_key(&bob.announce())).unwrap().is_empty());
+
+    // Alice's first send creates the room key and seals it to Bob
+    let (ciphertext, shares) = alice.encrypt("meet at noon").unwrap();
+    assert!(is_encrypted(&ciphertext) && !ciphertext.contains("noon"));
+    deliver(shares, &mut bob);
+    assert_eq!(bob.decrypt(1, &ciphertext).unwrap(), "meet at noon");
+
+    // Bob reuses the shared key; the sender id is bound as associated data
+    let (reply, shares) = bob.encrypt("ok").unwrap();
+    assert!(shares.is_empty());
+    assert_eq!(alice.decrypt(2, &reply).unwrap(), "ok");
+    assert!(alice.decrypt(3, &reply).is_err());
+
+    // A late joiner gets the current key from a member
+    let mut carol = E2eSession::new(3);
+    carol.handle_directory(&[(1, public_key(&alice.announce())), (2, public_key(&bob.announce()))]).unwrap();
+    deliver(alice.handle_announce(3, public_key(&carol.announce())).unwrap(), &mut carol);
+    assert_eq!(carol.decrypt(2, &reply).unwrap(), "ok");
+}
+
+#[test]
+fn share_for_another_client_cannot_be_opened() {
+    let mut alice = E2eSession::new(1);
+    let mut bob = E2eSession::new(2);
+    let mut eve = E2eSession::new(9);
+    alice.handle_announce(2, public_key(&bob.announce())).unwrap();
+    bob.handle_directory(&[(1, public_key(&alice.announce()))]).unwrap();
+    eve.handle_directory(&[(1, public_key(&alice.announce()))]).unwrap();
+
+    let (ciphertext, shares) = alice.encrypt("private").unwrap();
+    for share in shares {
+        if let ProtocolMessage::RoomKeyShare { from, sealed_key, .. } = share {
+            // Re-addressing the share to Eve fails authentication
+            assert!(eve.handle_share(from, 9, &sealed_key).is_err());
+        }
+    }
+    assert!(eve.decrypt(1, &ciphertext).is_err());
+}
+
+#[test]
+fn changed_identity_keys_are_refused_until_trusted() {
+    let mut alice = E2eSession::new(1);
+    let bob_key = IdentityKey::generate();
+    let bob = E2eSession::with_identity(2, IdentityKey::from_bytes(&bob_key.to_bytes()).unwrap());
+    alice.handle_announce(2, public_key(&bob.announce())).unwrap();
+    assert_eq!(alice.peer_fingerprint(2), Some(bob.fingerprint()));
+    assert_eq!(bob.fingerprint(), fingerprint(&bob_key.public_key()).unwrap());
+
+    // The same identity reconnecting is accepted; an impostor's key is not, in announcements or the directory
+    let reconnected = E2eSession::with_identity(2, bob_key);
+    alice.handle_announce(2, public_key(&reconnected.announce())).unwrap();
+    let impostor = public_key(&E2eSession::new(2).announce());
+    let err = alice.handle_announce(2, impostor.clone()).unwrap_err();
+    assert!(err.to_string().contains(&bob.fingerprint()), "{}", err);
+    assert!(alice.handle_directory(&[(2, impostor.clone())]).is_err());
+    assert_eq!(alice.peer_fingerprint(2), Some(bob.fingerprint()));
+
+    // After comparing fingerprints out of band the user can accept the new key
+    alice.trust(2, impostor.clone());
+    alice.handle_announce(2, impostor).unwrap();
+}
+
+#[test]
+fn room_key_rotates_when_a_member_leaves() {
+    let mut members: Vec<E2eSession> = (1..=3).map(E2eSession::new).collect();
+    let keys: Vec<(u64, String)> = members.iter().enumerate().map(|(i, m)| (i as u64 + 1, public_key(&m.announce()))).collect();
+    for member in &mut members {
+        member.handle_directory(&keys).unwrap();
+    }
+    let (_, shares) = members[1].encrypt("before").unwrap();
+    for share in shares {
+        if let ProtocolMessage::RoomKeyShare { from, to, sealed_key } = share {
+            members[to as usize - 1].handle_share(from, to, &sealed_key).unwrap();
+        }
+    }
+    let old_key = members[0].current_room_key().unwrap().key_id();
+
+    // User 3 leaves: only user 1, the lowest remaining id, rotates, and shares with user 2 alone
+    assert!(members[1].handle_members(&[1, 2]).unwrap().is_empty());
+    let shares = members[0].handle_members(&[1, 2]).unwrap();
+    assert_eq!(shares.len(), 1);
+    deliver(shares, &mut members[1]);
+    let (after, _) = members[1].encrypt("after").unwrap();
+    assert_ne!(members[1].current_room_key().unwrap().key_id(), old_key);
+    assert_eq!(members[0].decrypt(2, &after).unwrap(), "after");
+    assert!(members[2].decrypt(2, &after).is_err(), "the departed member lacks the new key");
+}
+
+#[test]
+fn chain_proof_over_ciphertext() {
+    let mut alice = E2eSession::new(1);
+    let mut chain = MessageChain::new();
+    for i in 0..3u64 {
+        let (ciphertext, _) = alice.encrypt(&format!("secret {i}")).unwrap();
+        chain.add_message(Message::new(i + 1, 1, ciphertext, 100 + i)).unwrap();
+    }
+    let proof = MessageProver::new().prove(&chain.messages).unwrap();
+    verify_proof(&proof, chain.public_inputs(), ProofProfile::Standard).unwrap();
+
+    // The Poseidon hash covers the whole ciphertext, not only its first 32 bytes
+    let original = &chain.messages[0];
+    let mut tail_tampered = original.content.clone();
+    let last = tail_tampered.pop().unwrap();
+    tail_tampered.push(if last == 'A' { 'B' } else { 'A' });
+    let tampered = Message::with_hash(original.id, original.sender_id, tail_tampered, original.timestamp, original.hash);
+    assert!(!tampered.verify_hash());
+}
@@ -1,7 +1,9 @@
-use zk_chat::{Message, zk::{MessageChain, air::{PublicInputs, build_trace}, prover::{MessageProver, verify_proof}, elements_to_hash}};
-use winterfell::math::{fields::f128::BaseElement, FieldElement};
+use zk_chat::{Message, zk::{MessageChain, air::{PublicInputs, build_trace, GlobalId, BLOCK_LENGTH, CHAIN_HASH}, prover::{MessageProver, ProofProfile, verify_proof}, session_salt, Digest}};
+use winterfell::math::FieldElement;
+use zk_chat::zk::field::BaseElement;
 
 #[test]
+#[allow(clippy::unnecessary_cast, clippy::cloned_ref_to_slice_refs)]
 fn e2e_three_message_chain() {
     let mut chain = MessageChain::new();
     for i in 0..3 {
@@ -13,10 +15,10 @@ fn e2e_three_message_chain() {
     let last = chain.messages.last().unwrap().clone();
     let trace = build_trace(&[last.clone()]);
     let mut final_hash_elements = [BaseElement::ZERO; 4];
-    for i in 0..4 { final_hash_elements[i] = trace[4 + i][0]; }
-    let final_hash = elements_to_hash(&final_hash_elements);
-    let pub_inputs = PublicInputs { initial_hash: [0u8;32], final_hash, message_count: 1 };
+    for i in 0..4 { final_hash_elements[i] = trace[CHAIN_HASH + i][BLOCK_LENGTH - 1]; }
+    let final_hash = Digest::new(final_hash_elements);
+    let pub_inputs = PublicInputs { initial_hash: Digest::ZERO, initial_timestamp: 0, final_hash, message_count: 1, message_root: last.hash, hash_id: last.hash_id, final_timestamp: last.timestamp, salt: session_salt(), message_index: vec![GlobalId::of(&last)] };
     let mut prover = MessageProver::new();
     let proof = prover.prove(&[last.clone()]).unwrap();
-    verify_proof(&proof, pub_inputs).unwrap();
+    verify_proof(&proof, pub_inputs, ProofProfile::Standard).unwrap();
 }
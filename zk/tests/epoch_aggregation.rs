@@ -0,0 +1,93 @@
+pub mod common;
+
+use common::TestChain;
+use zk_chat::zk::{
+    consistency::ServerSigningKey,
+    epoch::{aggregate_epoch, verify_epoch, verify_head_inclusion, ChainSegment},
+    field::element,
+    merkle::MerkleTree,
+    prover::{MessageProver, ProofProfile},
+    segment::{ChainBoundary, SegmentProof},
+    Digest, MessageChain,
+};
+
+fn room_segment(room_id: u64, count: u64) -> ChainSegment {
+    let (chain, proof) = TestChain::new(format!("room{room_id}")).senders(room_id * 10, 1).proven(count, ProofProfile::Standard);
+    ChainSegment { room_id, segment: SegmentProof { public_inputs: chain.public_inputs(), proof } }
+}
+
+#[test]
+fn epoch_commits_verified_heads_under_a_signed_root() {
+    let server = ServerSigningKey::generate();
+    let key = server.public_key();
+    let segments = vec![room_segment(1, 2), room_segment(2, 1), room_segment(3, 3)];
+    let aggregate = aggregate_epoch(7, &segments, &server, ProofProfile::Standard).unwrap();
+    verify_epoch(&aggregate.signed_root, &aggregate.entries, &aggregate.segments, &key, ProofProfile::Standard).unwrap();
+
+    for (index, entry) in aggregate.entries.iter().enumerate() {
+        let path = aggregate.inclusion_path(index).unwrap();
+        assert!(verify_head_inclusion(&aggregate.root(), entry, &path));
+    }
+
+    // Altering a committed head breaks the root; another key or epoch breaks the signature
+    let mut tampered = aggregate.entries.clone();
+    tampered[1].head.message_count += 1;
+    assert!(verify_epoch(&aggregate.signed_root, &tampered, &aggregate.segments, &key, ProofProfile::Standard).is_err());
+    let other_key = ServerSigningKey::generate().public_key();
+    assert!(verify_epoch(&aggregate.signed_root, &aggregate.entries, &aggregate.segments, &other_key, ProofProfile::Standard).is_err());
+    let mut renumbered = aggregate.signed_root.clone();
+    renumbered.epoch += 1;
+    assert!(verify_epoch(&renumbered, &aggregate.entries, &aggregate.segments, &key, ProofProfile::Standard).is_err());
+}
+
+#[test]
+fn epoch_heads_start_where_their_segments_start() {
+    let server = ServerSigningKey::generate();
+    let earlier = TestChain::new("later").chain(5);
+    let start = earlier.end();
+    let mut chain = MessageChain::resume(start, earlier.hash_id);
+    for message in TestChain::new("later").messages(5..7) {
+        chain.add_message(message).unwrap();
+    }
+    let proof = MessageProver::with_profile(ProofProfile::Standard).prove_segment(&start, &chain.messages).unwrap();
+    let segment = ChainSegment { room_id: 4, segment: SegmentProof { public_inputs: chain.public_inputs(), proof } };
+
+    let aggregate = aggregate_epoch(3, &[segment], &server, ProofProfile::Standard).unwrap();
+    assert_eq!(aggregate.entries[0].head.start, start);
+    assert_eq!(aggregate.entries[0].head.message_count, 2);
+
+    // The same head claimed from genesis is not what the proof shows
+    let mut from_genesis = aggregate.entries.clone();
+    from_genesis[0].head.start = ChainBoundary::GENESIS;
+    let resigned = server.sign_epoch(3, MerkleTree::new(&[from_genesis[0].leaf_hash()]).root());
+    assert!(verify_epoch(&resigned, &from_genesis, &aggregate.segments, &server.public_key(), ProofProfile::Standard).is_err());
+}
+
+#[test]
+fn epoch_rejects_unverified_segment() {
+    let server = ServerSigningKey::generate();
+    let key = server.public_key();
+    let mut segment = room_segment(1, 1);
+    segment.segment.public_inputs.message_count = 2; // head not backed by the proof
+    assert!(aggregate_epoch(1, &[segment], &server, ProofProfile::Standard).is_err());
+
+    // Verification re-checks the segment proofs: missing, swapped or forged ones are rejected
+    let aggregate = aggregate_epoch(2, &[room_segment(1, 1), room_segment(2, 2)], &server, ProofProfile::Standard).unwrap();
+    let (root, entries) = (&aggregate.signed_root, &aggregate.entries);
+    assert!(verify_epoch(root, entries, &aggregate.segments[..1], &key, ProofProfile::Standard).is_err());
+    let swapped = [aggregate.segments[1].clone(), aggregate.segments[0].clone()];
+    assert!(verify_epoch(root, entries, &swapped, &key, ProofProfile::Standard).is_err());
+    let mut forged = aggregate.segments.clone();
+    forged[1].segment.proof = forged[0].segment.proof.clone();
+    assert!(verify_epoch(root, entries, &forged, &key, ProofProfile::Standard).is_err());
+}
+
+#[test]
+fn merkle_paths_verify_only_their_leaf() {
+    let leaves: Vec<Digest> = (0u64..5).map(|i| Digest::new([element(i); 4])).collect();
+    let tree = MerkleTree::new(&leaves);
+    let path = tree.path(3).unwrap();
+    assert!(path.verify(&tree.root(), &leaves[3]));
+    assert!(!path.verify(&tree.root(), &leaves[2]));
+    assert!(tree.path(5).is_none());
+}
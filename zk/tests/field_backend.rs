@@ -0,0 +1,46 @@
+use zk_chat::{
+    zk::{
+        field, hash::pack_content,
+        prover::{verify_proof, MessageProver, ProofProfile}, zk_hash, Digest, MessageChain,
+    },
+    Message,
+};
+
+#[test]
+fn digest_bytes_round_trip() {
+    let inputs: Vec<field::BaseElement> = (1..=9u64).map(field::element).collect();
+    let digest = Digest::new(zk_hash(&inputs));
+    let bytes = digest.to_bytes();
+    assert_eq!(bytes.len(), 4 * field::ELEMENT_BYTES);
+    assert_eq!(Digest::from_bytes(&bytes).unwrap(), digest);
+    assert_eq!(digest.elements(), zk_hash(&inputs));
+}
+
+#[test]
+fn content_packing_is_injective_at_word_boundaries() {
+    let limit = 4 * field::CONTENT_WORD_BYTES;
+    let direct = "\u{7f}".repeat(limit);
+    let mut edited = direct.clone();
+    edited.replace_range(limit - 1.., "~");
+    assert_ne!(pack_content(&direct), pack_content(&edited));
+    // One byte more is hashed rather than packed, and still differs
+    assert_ne!(pack_content(&direct), pack_content(&format!("{}\u{7f}", direct)));
+    // High bytes never wrap around the modulus
+    assert_ne!(pack_content(&"\u{ff}".repeat(14)), pack_content(&"\u{ff}".repeat(13)));
+}
+
+#[test]
+fn proofs_use_the_backend_extension() {
+    let mut chain = MessageChain::new();
+    for i in 1..=4 {
+        chain.add_message(Message::new(i, 3, format!("backend {}", i), 500 + i)).unwrap();
+    }
+    let proof = MessageProver::with_profile(ProofProfile::Fast).prove(&chain.messages).unwrap();
+    let parsed = winterfell::Proof::from_bytes(&proof).unwrap();
+    assert_eq!(parsed.options().field_extension(), field::FIELD_EXTENSION);
+    verify_proof(&proof, chain.public_inputs(), ProofProfile::Fast).unwrap();
+
+    // A verifier requiring a stronger profile rejects the proof whatever its header claims
+    assert!(verify_proof(&proof, chain.public_inputs(), ProofProfile::Standard).is_err());
+    assert!(verify_proof(&proof, chain.public_inputs(), ProofProfile::Secure).is_err());
+}
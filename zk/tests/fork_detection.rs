@@ -0,0 +1,101 @@
+pub mod common;
+
+use common::{head, TestChain};
+use zk_chat::{Message, zk::{field, Digest, MessageChain, session_salt, compression::ProofCache, consistency::{ChainMirror, ForkEvidence, ForkMonitor, HeadSigner}, prover::{MessageProver, ProofProfile}}};
+
+#[test]
+fn mirror_tracks_server_chain() {
+    let mut chain = MessageChain::new();
+    let mut mirror = ChainMirror::new(session_salt());
+
+    let first = TestChain::new("m").senders(1, 2).messages(0..3);
+    for message in &first {
+        chain.add_message(message.clone()).unwrap();
+    }
+    mirror.apply_batch(&first, chain.len(), &chain.chain_hash).unwrap();
+    assert_eq!(mirror.head(), chain.chain_hash);
+
+    // A client joining mid-session resumes from the published head
+    let mut late = ChainMirror::resume(session_salt(), chain.len(), chain.chain_hash);
+    let second = TestChain::new("m").senders(1, 2).messages(3..5);
+    for message in &second {
+        chain.add_message(message.clone()).unwrap();
+    }
+    mirror.apply_batch(&second, chain.len(), &chain.chain_hash).unwrap();
+    late.apply_batch(&second, chain.len(), &chain.chain_hash).unwrap();
+    assert_eq!(late.head(), mirror.head());
+}
+
+#[test]
+fn mirror_rejects_forged_head_and_gaps() {
+    let batch = TestChain::new("m").senders(1, 2).messages(0..3);
+    let mut chain = MessageChain::new();
+    for message in &batch {
+        chain.add_message(message.clone()).unwrap();
+    }
+
+    let forged = Digest::new(chain.chain_hash.elements().map(|e| e + field::element(1)));
+    assert!(ChainMirror::new(session_salt()).apply_batch(&batch, 3, &forged).is_err());
+
+    // Batch positions 4..=5 after a mirror holding nothing: broadcasts were missed
+    assert!(ChainMirror::new(session_salt()).apply_batch(&batch[1..], 5, &chain.chain_hash).is_err());
+}
+
+#[test]
+fn mirror_verifies_chain_proofs() {
+    let mut chain = MessageChain::new();
+    let batch = TestChain::new("m").senders(1, 2).messages(0..3);
+    for message in &batch {
+        chain.add_message(message.clone()).unwrap();
+    }
+    let proof = MessageProver::with_profile(ProofProfile::Fast).prove_segment(&chain.start, &chain.messages).unwrap();
+    let mut cache = ProofCache::default();
+    cache.update(0, &chain.public_inputs(), &proof).unwrap();
+    let cached = cache.flush().unwrap();
+
+    let mut mirror = ChainMirror::new(session_salt());
+    assert!(mirror.apply_proven_batch(&batch, &cached, ProofProfile::Standard).is_err(), "weaker proof than required");
+    let mut tampered = batch.clone();
+    tampered[1] = Message::new(tampered[1].id, tampered[1].sender_id, "forged".into(), tampered[1].timestamp);
+    assert!(mirror.apply_proven_batch(&tampered, &cached, ProofProfile::Fast).is_err());
+    let mut corrupted = cached.clone();
+    corrupted.proof.truncate(corrupted.proof.len() / 2);
+    assert!(mirror.apply_proven_batch(&batch, &corrupted, ProofProfile::Fast).is_err());
+    assert_eq!(mirror.message_count(), 0, "nothing is applied from a rejected proof");
+
+    mirror.apply_proven_batch(&batch, &cached, ProofProfile::Fast).unwrap();
+    assert_eq!(mirror.head(), chain.chain_hash);
+    // The same proof no longer starts at the mirrored head
+    assert!(mirror.apply_proven_batch(&batch, &cached, ProofProfile::Fast).is_err());
+}
+
+#[test]
+fn divergent_signed_heads_are_reported() {
+    let alice = HeadSigner::generate();
+    let bob = HeadSigner::generate();
+    let mut monitor = ForkMonitor::new();
+
+    assert!(monitor.observe(alice.sign(1, 8, 4, head(1))).unwrap().is_none());
+    assert!(monitor.observe(bob.sign(2, 8, 4, head(1))).unwrap().is_none(), "same head is consistent");
+
+    let evidence = monitor.observe(bob.sign(2, 8, 5, head(2))).unwrap();
+    assert!(evidence.is_none(), "first head for a new count");
+    let evidence = monitor.observe(alice.sign(1, 8, 5, head(3))).unwrap().expect("fork at 5 messages");
+    assert!(evidence.verify());
+    assert_eq!((evidence.first.user_id, evidence.second.user_id), (2, 1));
+
+    // Tampered gossip and swapped signing keys are rejected
+    let mut tampered = alice.sign(1, 8, 6, head(4));
+    tampered.chain_hash = head(40);
+    assert!(monitor.observe(tampered).is_err());
+    assert!(monitor.observe(HeadSigner::generate().sign(1, 8, 7, head(5))).is_err());
+
+    // Heads from another epoch name another chain; neither the monitor nor evidence mixes them
+    assert!(monitor.observe(bob.sign(2, 9, 5, head(6))).unwrap().is_none());
+    assert_eq!(monitor.head_at(9, 5).map(|h| h.chain_hash), Some(head(6)));
+    let mixed = ForkEvidence { first: alice.sign(1, 8, 5, head(3)), second: bob.sign(2, 9, 5, head(6)) };
+    assert!(!mixed.verify());
+    let mut resalted = alice.sign(1, 8, 6, head(4));
+    resalted.salt = 9;
+    assert!(!resalted.verify(), "the salt is signed");
+}
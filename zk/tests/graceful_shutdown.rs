@@ -0,0 +1,82 @@
+use futures_util::{SinkExt, StreamExt};
+use std::path::PathBuf;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use zk_chat::{
+    websocket::{server::ChatServer, ProtocolMessage},
+    zk::{air::GlobalId, field, restore_session_salt, session_salt, storage::StoredChain, Digest, MessageChain},
+    Message, ZkChatError,
+};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("zk_chat_shutdown_{}_{}.json", name, std::process::id()))
+}
+
+#[test]
+fn stored_chain_round_trip_and_tamper_detection() {
+    let mut chain = MessageChain::new();
+    for i in 1..=3 {
+        chain.add_message(Message::new(i, 7, format!("message {}", i), 1000 + i)).unwrap();
+    }
+    let path = temp_path("round_trip");
+    let salts = [(GlobalId { sender_id: 7, id: 3 }, [3u8; 32]), (GlobalId { sender_id: 7, id: 1 }, [1u8; 32])];
+    StoredChain::new(&chain, 2).with_content_salts(salts).save(&path).unwrap();
+
+    let stored = StoredChain::load(&path).unwrap().expect("chain was saved");
+    assert_eq!((stored.epoch, stored.salt), (2, session_salt()));
+    assert_eq!(stored.content_salts.iter().map(|s| s.id).collect::<Vec<_>>(), vec![1, 3]);
+    let restored = stored.restore().unwrap();
+    assert_eq!(restored.chain_hash, chain.chain_hash);
+    assert_eq!(restored.message_root(), chain.message_root());
+
+    // Edited content no longer matches its hash; a forged head no longer matches the messages
+    let mut edited = stored.clone();
+    edited.messages[1].content = "edited".into();
+    assert!(matches!(edited.restore(), Err(ZkChatError::InvalidMessageHash)));
+    let mut forged = stored.clone();
+    forged.chain_hash = Digest::new([field::element(9); 4]);
+    assert!(matches!(forged.restore(), Err(ZkChatError::ChainInconsistency(_))));
+
+    // A chain from another session restores only after adopting its salt
+    let mut other_session = stored.clone();
+    other_session.salt = stored.salt.wrapping_add(1);
+    assert!(matches!(other_session.restore(), Err(ZkChatError::ChainInconsistency(_))));
+    restore_session_salt(stored.salt);
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(StoredChain::load(&path).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn chat_server_notifies_clients_and_flushes_chain() {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let path = temp_path("legacy");
+    let server = ChatServer::new().with_chain_store(&path).unwrap();
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let running = tokio::spawn(async move {
+        server.start_with_shutdown(addr, async { let _ = stop_rx.await; }).await
+    });
+
+    let mut ws = loop {
+        match tokio_tungstenite::connect_async(format!("ws://{}", addr)).await {
+            Ok((ws, _)) => break ws,
+            Err(_) => tokio::time::sleep(std::time::Duration::from_millis(20)).await,
+        }
+    };
+    let join = ProtocolMessage::Join { user_id: 1, username: "alice".into() };
+    ws.send(WsMessage::Text(String::from_utf8(join.to_bytes().unwrap()).unwrap())).await.unwrap();
+    assert!(matches!(ws.next().await, Some(Ok(WsMessage::Text(_)))));
+
+    stop_tx.send(()).unwrap();
+    let notice = match ws.next().await {
+        Some(Ok(WsMessage::Text(text))) => ProtocolMessage::from_bytes(text.as_bytes()).unwrap(),
+        other => panic!("expected a shutdown notice, got {:?}", other),
+    };
+    assert!(matches!(notice, ProtocolMessage::ServerShutdown { .. }));
+    assert!(matches!(ws.next().await, Some(Ok(WsMessage::Close(_))) | None));
+
+    running.await.unwrap().unwrap();
+    let stored = StoredChain::load(&path).unwrap().expect("chain flushed on shutdown");
+    assert!(stored.messages.is_empty());
+    assert!(tokio::net::TcpStream::connect(addr).await.is_err(), "listener closed");
+    std::fs::remove_file(&path).unwrap();
+}
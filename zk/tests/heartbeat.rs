@@ -0,0 +1,84 @@
+use futures_util::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use zk_chat::{
+    config::{HeartbeatConfig, ServerConfig},
+    websocket::{server::ChatServer, ProtocolMessage},
+    ZkChatError,
+};
+
+type Client = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn start_server(heartbeat: HeartbeatConfig) -> std::net::SocketAddr {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    tokio::spawn(async move {
+        ChatServer::new().with_heartbeat(heartbeat).start_with_shutdown(addr, std::future::pending()).await
+    });
+    addr
+}
+
+async fn join(addr: std::net::SocketAddr, user_id: u64, username: &str) -> (Client, Vec<(u64, String)>) {
+    let mut ws = loop {
+        match tokio_tungstenite::connect_async(format!("ws://{}", addr)).await {
+            Ok((ws, _)) => break ws,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    };
+    let join = ProtocolMessage::Join { user_id, username: username.into() };
+    ws.send(WsMessage::Text(String::from_utf8(join.to_bytes().unwrap()).unwrap())).await.unwrap();
+    loop {
+        if let Some(Ok(WsMessage::Text(text))) = ws.next().await {
+            if let Ok(ProtocolMessage::UserListUpdate { users }) = ProtocolMessage::from_bytes(text.as_bytes()) {
+                return (ws, users);
+            }
+        }
+    }
+}
+
+#[test]
+fn heartbeat_settings() {
+    let config = ServerConfig::default();
+    assert_eq!(config.heartbeat.interval(), Some(Duration::from_secs(30)));
+    assert_eq!(config.heartbeat.idle_timeout(), Some(Duration::from_secs(90)));
+
+    let mut config = ServerConfig::default();
+    config.set("heartbeat_secs", "0").unwrap();
+    config.set("idle-timeout-secs", "0").unwrap();
+    assert_eq!((config.heartbeat.interval(), config.heartbeat.idle_timeout()), (None, None));
+    assert!(config.validate().is_ok());
+
+    config.set("heartbeat_secs", "60").unwrap();
+    config.set("idle_timeout_secs", "60").unwrap();
+    assert!(matches!(config.validate(), Err(ZkChatError::Config(_))), "timeout must outlast the ping interval");
+}
+
+#[tokio::test]
+async fn responsive_clients_are_pinged_and_kept() {
+    let addr = start_server(HeartbeatConfig { interval_secs: 1, idle_timeout_secs: 2 }).await;
+    let (mut ws, _) = join(addr, 1, "alice").await;
+
+    // Reading the stream answers pings automatically, so the connection outlives the idle timeout
+    let mut pings = 0;
+    let deadline = tokio::time::Instant::now() + Duration::from_millis(3500);
+    while let Ok(Some(frame)) = tokio::time::timeout_at(deadline, ws.next()).await {
+        match frame.unwrap() {
+            WsMessage::Ping(_) => pings += 1,
+            WsMessage::Close(_) => panic!("responsive client was dropped"),
+            _ => {}
+        }
+    }
+    assert!(pings >= 3, "expected a ping per second, got {}", pings);
+}
+
+#[tokio::test]
+async fn silent_clients_are_dropped_and_removed() {
+    let addr = start_server(HeartbeatConfig { interval_secs: 0, idle_timeout_secs: 1 }).await;
+    let (mut silent, users) = join(addr, 1, "alice").await;
+    assert_eq!(users, vec![(1, "alice".to_string())]);
+
+    // Without pings, a client that sends nothing for the idle timeout is dropped
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let (_bob, users) = join(addr, 2, "bob").await;
+    assert_eq!(users, vec![(2, "bob".to_string())]);
+    assert!(matches!(silent.next().await, Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None));
+}
@@ -0,0 +1,55 @@
+pub mod common;
+
+use common::{message_air, violated_steps, TestChain};
+use zk_chat::zk::{
+    air::{build_trace, PublicInputs, BLOCK_LENGTH, CHAIN_HASH},
+    disclosure::{redact_messages, verify_redacted},
+    field,
+    prover::{verify_proof, MessageProver, ProofProfile},
+};
+
+#[test]
+fn final_hash_is_bound_to_the_rows() {
+    let (chain, proof) = TestChain::new("commitment word").proven(3, ProofProfile::Fast);
+    verify_proof(&proof, chain.public_inputs(), ProofProfile::Fast).unwrap();
+
+    // The final hash of another chain does not verify
+    let other = TestChain::new("other word").chain(3);
+    let mut swapped = chain.public_inputs();
+    swapped.final_hash = other.public_inputs().final_hash;
+    assert!(verify_proof(&proof, swapped, ProofProfile::Fast).is_err());
+
+    let mut moved = chain.public_inputs();
+    moved.final_timestamp += 1;
+    assert!(verify_proof(&proof, moved, ProofProfile::Fast).is_err());
+
+    let mut salted = chain.public_inputs();
+    salted.salt ^= 1;
+    assert!(verify_proof(&proof, salted, ProofProfile::Fast).is_err());
+}
+
+#[test]
+fn chain_hash_is_derived_in_circuit() {
+    let chain = TestChain::new("commitment word").chain(1);
+    let mut trace = build_trace(&chain.messages);
+    assert!(violated_steps(&message_air(&trace, chain.public_inputs()), &trace).is_empty());
+
+    // A chain hash that is not the permutation output of the block breaks the block's last step
+    trace[CHAIN_HASH][BLOCK_LENGTH - 1] += field::element(1);
+    assert_eq!(violated_steps(&message_air(&trace, chain.public_inputs()), &trace), [BLOCK_LENGTH - 1]);
+}
+
+#[test]
+fn redacted_public_inputs_carry_no_rows() {
+    let (chain, _) = TestChain::new("commitment word").proven(3, ProofProfile::Fast);
+    let salts: Vec<[u8; 32]> = (0..3u8).map(|i| [i + 1; 32]).collect();
+    let redacted = redact_messages(&chain.messages, &salts).unwrap();
+    let proof = MessageProver::with_profile(ProofProfile::Fast).prove_redacted(&redacted).unwrap();
+    let pub_inputs = verify_redacted(&proof, &redacted, ProofProfile::Fast).unwrap();
+
+    let json = serde_json::to_value(&pub_inputs).unwrap();
+    assert!(json.get("rows").is_none());
+    let mut tampered: PublicInputs = serde_json::from_value(json).unwrap();
+    tampered.final_hash = chain.public_inputs().initial_hash;
+    assert!(verify_proof(&proof, tampered, ProofProfile::Fast).is_err());
+}
@@ -0,0 +1,66 @@
+pub mod common;
+
+use common::TestChain;
+use zk_chat::{Message, zk::{MessageChain, consistency::{ServerSigningKey, SignedInclusionProof}, merkle::{message_root, MerkleTree}, prover::{MessageProver, ProofProfile, verify_proof}, field, Digest}};
+
+#[test]
+fn incremental_root_matches_full_tree() {
+    let mut chain = MessageChain::new();
+    let mut leaves = Vec::new();
+    assert_eq!(chain.message_root(), Digest::ZERO);
+    for i in 0..9u64 {
+        let message = Message::new(i + 1, 1, format!("m{i}"), 100 + i);
+        leaves.push(message.hash);
+        chain.add_message(message).unwrap();
+        assert_eq!(chain.message_root(), MerkleTree::new(&leaves).root(), "root mismatch after {} messages", i + 1);
+    }
+    assert_eq!(chain.message_root(), message_root(&chain.messages));
+}
+
+#[test]
+fn single_message_disclosed_with_inclusion_path() {
+    let chain = TestChain::new("secret").senders(3, 2).chain(5);
+    let root = chain.message_root();
+    let inclusion = chain.inclusion_proof(2).unwrap();
+    assert!(inclusion.verify(&root));
+
+    let mut tampered = inclusion.clone();
+    tampered.message = Message::new(3, tampered.message.sender_id, "forged".into(), tampered.message.timestamp);
+    assert!(!tampered.verify(&root), "different message must not verify under the root");
+    assert!(chain.inclusion_proof(5).is_none());
+}
+
+#[test]
+fn signed_inclusion_discloses_only_the_path() {
+    let chain = TestChain::new("secret").senders(3, 2).chain(5);
+    let server = ServerSigningKey::generate();
+    let checkpoint = server.sign(chain.len(), chain.chain_hash, chain.message_root(), 1, None);
+    let inclusion = SignedInclusionProof {
+        leaf: chain.messages[3].hash,
+        path: chain.inclusion_proof(3).unwrap().path,
+        message_root: chain.message_root(),
+        checkpoint,
+    };
+    inclusion.verify(&server.public_key()).unwrap();
+    assert!(inclusion.verify(&ServerSigningKey::generate().public_key()).is_err(), "other server key");
+
+    let mut other_leaf = inclusion.clone();
+    other_leaf.leaf = chain.messages[2].hash;
+    assert!(other_leaf.verify(&server.public_key()).is_err());
+    // A root the server did not sign does not verify, even with a consistent path
+    let mut unsigned_root = inclusion.clone();
+    unsigned_root.message_root = MerkleTree::new(&[inclusion.leaf]).root();
+    unsigned_root.path = MerkleTree::new(&[inclusion.leaf]).path(0).unwrap();
+    assert!(unsigned_root.verify(&server.public_key()).is_err());
+}
+
+#[test]
+fn message_root_bound_into_chain_proof() {
+    let chain = TestChain::new("secret").senders(3, 2).chain(3);
+    let proof = MessageProver::new().prove(&chain.messages).unwrap();
+    verify_proof(&proof, chain.public_inputs(), ProofProfile::Standard).unwrap();
+
+    let mut wrong_root = chain.public_inputs();
+    wrong_root.message_root = Digest::new(wrong_root.message_root.elements().map(|e| e + field::element(1)));
+    assert!(verify_proof(&proof, wrong_root, ProofProfile::Standard).is_err());
+}
@@ -0,0 +1,46 @@
+pub mod common;
+
+use common::TestChain;
+use zk_chat::zk::{
+    air::{message_index, GlobalId},
+    prover::{verify_proof, MessageProver, ProofProfile},
+};
+
+#[test]
+fn chain_order_is_a_permutation_of_the_published_index() {
+    let chain = TestChain::new("indexed").senders(1, 2).chain(4);
+    let proof = MessageProver::with_profile(ProofProfile::Fast).prove(&chain.messages).unwrap();
+
+    let pub_inputs = chain.public_inputs();
+    let chain_order: Vec<GlobalId> = chain.messages.iter().map(GlobalId::of).collect();
+    assert_ne!(pub_inputs.message_index, chain_order);
+    assert_eq!(pub_inputs.message_index, message_index(chain_order.into_iter().rev()));
+    verify_proof(&proof, pub_inputs, ProofProfile::Fast).unwrap();
+}
+
+#[test]
+fn dropped_or_duplicated_messages_are_rejected() {
+    let chain = TestChain::new("indexed").senders(1, 2).chain(4);
+    let proof = MessageProver::with_profile(ProofProfile::Fast).prove(&chain.messages).unwrap();
+
+    // An index listing a message the trace does not contain in place of one it does
+    let mut substituted = chain.public_inputs();
+    substituted.message_index[3] = GlobalId { sender_id: 2, id: 3 };
+    assert!(verify_proof(&proof, substituted, ProofProfile::Fast).is_err());
+
+    let mut duplicated = chain.public_inputs();
+    duplicated.message_index[1] = duplicated.message_index[0];
+    assert!(verify_proof(&proof, duplicated, ProofProfile::Fast).is_err());
+
+    let mut dropped = chain.public_inputs();
+    dropped.message_index.remove(2);
+    assert!(verify_proof(&proof, dropped, ProofProfile::Fast).is_err());
+}
+
+#[test]
+fn index_serializes_as_sender_and_id() {
+    let pub_inputs = TestChain::new("indexed").senders(1, 2).chain(4).public_inputs();
+    let json = serde_json::to_value(&pub_inputs).unwrap();
+    assert_eq!(json["message_index"][0], serde_json::json!({ "sender_id": 1, "id": 1 }));
+    assert_eq!(json["message_index"].as_array().unwrap().len(), 4);
+}
@@ -0,0 +1,31 @@
+use zk_chat::{Message, ZkChatError, metrics, websocket::ProtocolMessage, zk::{MessageChain, prover::{MessageProver, ProofProfile, verify_proof}}};
+
+#[test]
+fn proving_and_verification_are_timed() {
+    let proved = metrics::PROVE_DURATION.get_sample_count();
+    let verified = metrics::VERIFY_DURATION.get_sample_count();
+
+    let mut chain = MessageChain::new();
+    chain.add_message(Message::new(1, 1, "timed".into(), 10)).unwrap();
+    let proof = MessageProver::new().prove(&chain.messages).unwrap();
+    verify_proof(&proof, chain.public_inputs(), ProofProfile::Standard).unwrap();
+
+    assert!(metrics::PROVE_DURATION.get_sample_count() > proved);
+    assert!(metrics::VERIFY_DURATION.get_sample_count() > verified);
+}
+
+#[test]
+fn errors_and_broadcasts_counted_by_label() {
+    let error = ProtocolMessage::from(ZkChatError::InvalidTimestamp);
+    metrics::record_outgoing(&error);
+    metrics::record_outgoing(&ProtocolMessage::Pong);
+    assert!(metrics::PROTOCOL_ERRORS.with_label_values(&["1002"]).get() >= 1);
+
+    metrics::record_broadcast(false, metrics::error_reason(&ZkChatError::DuplicateMessageId));
+    assert!(metrics::BROADCASTS.with_label_values(&["false", "duplicate_id"]).get() >= 1);
+
+    let text = metrics::render();
+    for name in ["zk_chat_prove_duration_seconds_bucket", "zk_chat_online_users", "zk_chat_broadcast_lag", "zk_chat_protocol_errors_total{code=\"1002\"}"] {
+        assert!(text.contains(name), "missing {name}");
+    }
+}
@@ -1,4 +1,4 @@
-use zk_chat::{Message, zk::MessageChain, ZkChatError};
+use zk_chat::{Message, zk::{Digest, MessageChain}, ZkChatError};
 
 #[test]
 fn negative_duplicate_id_rejected_same_sender() {
@@ -27,6 +27,8 @@ fn negative_non_monotonic_timestamp_rejected() {
 #[test]
 fn negative_tampered_hash_fails_verify() {
     let mut msg = Message::new(1, 1, "tamper".into(), 1000);
-    msg.hash[0] ^= 0xFF; // corrupt a byte
+    let mut bytes = msg.hash.to_bytes();
+    bytes[0] ^= 0xFF; // corrupt a byte
+    msg.hash = Digest::from_bytes(&bytes).unwrap();
     assert!(!msg.verify_hash(), "Tampered hash should fail verification");
 }
@@ -0,0 +1,82 @@
+pub mod common;
+
+use common::{message_air, violated_steps, TestChain};
+use winterfell::{math::FieldElement, Air};
+use zk_chat::zk::{
+    air::{build_trace, MessageAir, BLOCK_LENGTH, CHAIN_HASH, PREV_HASH, SELECTOR},
+    field::{self, BaseElement},
+    prover::{verify_proof, MessageProver, ProofProfile},
+};
+
+/// Whether every main assertion holds on the trace
+fn assertions_hold(air: &MessageAir, trace: &[Vec<BaseElement>]) -> bool {
+    air.get_assertions().iter().all(|assertion| trace[assertion.column()][assertion.first_step()] == assertion.values()[0])
+}
+
+/// Last step of block `block`, where its chain hash is output
+fn block_end(block: usize) -> usize {
+    (block + 1) * BLOCK_LENGTH - 1
+}
+
+#[test]
+fn selector_marks_message_blocks() {
+    let chain = TestChain::new("padded").chain(3);
+    let trace = build_trace(&chain.messages);
+    let selector: Vec<u64> = trace[SELECTOR].iter().step_by(BLOCK_LENGTH).map(|&s| field::element_to_u64(s)).collect();
+    assert_eq!(selector, [1, 1, 1, 0]);
+    assert!(trace[SELECTOR].chunks(BLOCK_LENGTH).all(|block| block.iter().all(|&s| s == block[0])));
+
+    let air = message_air(&trace, chain.public_inputs());
+    assert!(assertions_hold(&air, &trace));
+    assert!(violated_steps(&air, &trace).is_empty());
+}
+
+#[test]
+fn claimed_count_must_match_the_selector() {
+    let full = TestChain::new("padded").chain(3);
+    let trace = build_trace(&full.messages);
+
+    // Claiming only the first two messages of a three-message trace
+    let prefix = TestChain::new("padded").chain(2);
+    assert!(!assertions_hold(&message_air(&trace, prefix.public_inputs()), &trace));
+
+    // Relabelling the third message as padding breaks the hash freeze instead
+    let mut relabelled = trace.clone();
+    relabelled[SELECTOR][2 * BLOCK_LENGTH..3 * BLOCK_LENGTH].fill(BaseElement::ZERO);
+    let air = message_air(&relabelled, prefix.public_inputs());
+    assert!(assertions_hold(&air, &relabelled));
+    assert_eq!(violated_steps(&air, &relabelled), [block_end(2)]);
+}
+
+#[test]
+fn padding_blocks_cannot_extend_the_chain() {
+    let chain = TestChain::new("padded").chain(2);
+    let mut trace = build_trace(&chain.messages);
+
+    // Changing the hash on a padding block (keeping it chained) is caught where it changes,
+    // and where the next block's chain step starts from it
+    trace[CHAIN_HASH][2 * BLOCK_LENGTH..].iter_mut().for_each(|e| *e += BaseElement::ONE);
+    trace[PREV_HASH][3 * BLOCK_LENGTH..].iter_mut().for_each(|e| *e += BaseElement::ONE);
+    let air = message_air(&trace, chain.public_inputs());
+    assert!(assertions_hold(&air, &trace));
+    assert_eq!(violated_steps(&air, &trace), [block_end(2), 3 * BLOCK_LENGTH + BLOCK_LENGTH / 2]);
+
+    // A selector that turns back on after padding is rejected too
+    let mut revived = build_trace(&chain.messages);
+    revived[SELECTOR][3 * BLOCK_LENGTH..].fill(BaseElement::ONE);
+    assert_eq!(violated_steps(&message_air(&revived, chain.public_inputs()), &revived), [block_end(2)]);
+}
+
+#[test]
+fn single_message_traces_prove() {
+    // One message leaves a single padding block; its constraints still have their declared degrees
+    let chain = TestChain::new("padded").chain(1);
+    let proof = MessageProver::with_profile(ProofProfile::Fast).prove(&chain.messages).unwrap();
+    verify_proof(&proof, chain.public_inputs(), ProofProfile::Fast).unwrap();
+
+    let mut trace = build_trace(&chain.messages);
+    let air = message_air(&trace, chain.public_inputs());
+    assert!(violated_steps(&air, &trace).is_empty());
+    trace[PREV_HASH][BLOCK_LENGTH..].iter_mut().for_each(|e| *e += BaseElement::ONE);
+    assert_eq!(violated_steps(&air, &trace), [block_end(0), BLOCK_LENGTH + BLOCK_LENGTH / 2]);
+}
@@ -0,0 +1,125 @@
+use num_bigint::BigUint;
+use winterfell::math::FieldElement;
+use zk_chat::zk::{
+    field::{self, BaseElement},
+    poseidon::{params, poseidon_hash, poseidon_hash_unseparated, poseidon_permutation, GrainLfsr, WIDTH},
+};
+
+// BN254 scalar field, the field of the reference repository's `poseidonperm_x5_254_3` instance
+const BN254: &str = "30644e72e131a029b85045b68181585d2833e84879b9709143e1f593f0000001";
+
+fn big(hex: &str) -> BigUint {
+    BigUint::parse_bytes(hex.trim_start_matches("0x").as_bytes(), 16).unwrap()
+}
+
+#[test]
+fn grain_reproduces_reference_round_constants() {
+    let modulus = hex::decode(BN254).unwrap();
+    let mut grain = GrainLfsr::new(254, 3, 8, 57);
+    let constants: Vec<BigUint> = (0..3).map(|_| BigUint::from_bytes_be(&grain.next_below(254, &modulus))).collect();
+    assert_eq!(constants, vec![
+        big("0x0ee9a592ba9a9518d05986d656f40c2114c4993c11bb29938d21d47304cd8e6e"),
+        big("0x00f1445235f2148c5986587169fc1bcd887b08d4d00868df5696fff40956e864"),
+        big("0x08dff3487e8ac99e1f29a058d0fa80b930c728730b7ab36ce879f3890ecf73f5"),
+    ]);
+}
+
+#[test]
+fn grain_procedure_reproduces_reference_permutation_vector() {
+    // Same generation procedure as `PoseidonParams::generate`, over BN254 with x^5, R_F = 8, R_P = 57
+    let p = big(BN254);
+    let modulus = hex::decode(BN254).unwrap();
+    let (t, full, partial) = (3, 8, 57);
+    let mut grain = GrainLfsr::new(254, t, full, partial);
+    let constants: Vec<BigUint> = (0..(full + partial) * t).map(|_| BigUint::from_bytes_be(&grain.next_below(254, &modulus))).collect();
+    let draws: Vec<BigUint> = (0..2 * t).map(|_| BigUint::from_bytes_be(&grain.next_bits(254)) % &p).collect();
+    let inv = |x: BigUint| x.modpow(&(&p - 2u32), &p);
+    let mds: Vec<Vec<BigUint>> = (0..t).map(|i| (0..t).map(|j| inv((&draws[i] + &draws[t + j]) % &p)).collect()).collect();
+    assert_eq!(mds[0][0], big("0x109b7f411ba0e4c9b2b70caf5c36a7b194be7c11ad24378bfedb68592ba8118b"));
+
+    let mut state: Vec<BigUint> = (0..t as u32).map(BigUint::from).collect();
+    for round in 0..full + partial {
+        for (i, s) in state.iter_mut().enumerate() {
+            *s = (&*s + &constants[round * t + i]) % &p;
+        }
+        let sboxes = if round < full / 2 || round >= full / 2 + partial { t } else { 1 };
+        for s in state.iter_mut().take(sboxes) {
+            *s = s.modpow(&BigUint::from(5u32), &p);
+        }
+        state = mds.iter().map(|row| row.iter().zip(&state).fold(BigUint::from(0u32), |acc, (m, s)| acc + m * s) % &p).collect();
+    }
+    assert_eq!(state, vec![
+        big("0x115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a"),
+        big("0x0fca49b798923ab0239de1c9e7a4a9a2210312b6a2f616d18b5a87f9b628ae29"),
+        big("0x0e7ae82e40091e63cbd4f16a6d16310b3729d4b6e138fcf54110e2867045a30c"),
+    ]);
+}
+
+#[test]
+fn instance_matches_regression_values() {
+    // Values this implementation produced for this crate's instance, listed in the README; no external
+    // vectors exist for these fields and width, so they catch changes rather than validate the instance
+    #[cfg(not(feature = "f64"))]
+    let (constant, mds, output) = (
+        0x221b2ea2977dc93e4627d7acd622ae2du128,
+        0xa081f293018b8dc791e39b2a200e7b0eu128,
+        [0xa3d4d919db1a5b50d1cabfab2832223fu128, 0xa16a10c8be060739493b4ec9661a5bd7, 0x8aceeaa36e0ec5b331058fbd7a6f4472, 0x17113cb3d5dd39b5585a76679ae0dcf3],
+    );
+    #[cfg(feature = "f64")]
+    let (constant, mds, output) = (
+        0x5098165ee28e503eu128,
+        0x439bc0b0e546b174u128,
+        [0xa45042b8b988787fu128, 0xb6f02016dfb3213f, 0xb694a63e36d36aae, 0xbb3a71ab4f2f2b82],
+    );
+    assert_eq!(params().round_constants[0][0], field::element_from_u128(constant));
+    assert_eq!(params().mds[0][0], field::element_from_u128(mds));
+    let input: [BaseElement; WIDTH] = std::array::from_fn(|i| field::element(i as u64));
+    assert_eq!(poseidon_permutation(input), output.map(field::element_from_u128));
+
+    #[cfg(not(feature = "f64"))]
+    assert_eq!(
+        zk_chat::zk::zk_hash(&(1..=5u64).map(field::element).collect::<Vec<_>>()),
+        [0x21d785c797741f624c4b6a93986a26b3u128, 0x0211de7c4bf5d167acb5a20439e312d0, 0x7325391e15d9bf54228326927c06fa43, 0xe64ff382b79df2d89f03d7c8fc5a0d0e]
+            .map(field::element_from_u128)
+    );
+    // `HashId::Poseidon` keeps the sponge its chains were hashed with
+    #[cfg(not(feature = "f64"))]
+    assert_eq!(
+        poseidon_hash_unseparated(&(1..=5u64).map(field::element).collect::<Vec<_>>()),
+        [0x85c91ed40f4709787383e53edbf51146u128, 0xf4efcff8ffec9334e4e8b05daf9a5099, 0x049c6ae574be8939d5999406419d36e2, 0x70ccd1e0e7bdb26683d77664f6ef36bf]
+            .map(field::element_from_u128)
+    );
+}
+
+#[test]
+fn sponge_separates_input_lengths() {
+    let a = field::element(7);
+    let zero = BaseElement::ZERO;
+    assert_ne!(poseidon_hash(&[a]), poseidon_hash(&[a, zero, zero]));
+    assert_ne!(poseidon_hash(&[]), poseidon_hash(&[zero]));
+    assert_ne!(poseidon_hash(&[a, zero, zero]), poseidon_hash(&[a, zero, zero, zero]));
+    assert_eq!(poseidon_hash_unseparated(&[a]), poseidon_hash_unseparated(&[a, zero, zero]));
+}
+
+#[test]
+fn mds_matrix_is_mds() {
+    // Every square submatrix of an MDS matrix is invertible
+    fn det(m: &[Vec<BaseElement>]) -> BaseElement {
+        if m.len() == 1 {
+            return m[0][0];
+        }
+        (0..m.len()).fold(BaseElement::ZERO, |acc, col| {
+            let minor: Vec<Vec<BaseElement>> =
+                m[1..].iter().map(|row| row.iter().enumerate().filter(|(j, _)| *j != col).map(|(_, v)| *v).collect()).collect();
+            let term = m[0][col] * det(&minor);
+            if col % 2 == 0 { acc + term } else { acc - term }
+        })
+    }
+    let subsets: Vec<Vec<usize>> = (1..1u32 << WIDTH).map(|mask| (0..WIDTH).filter(|i| mask & (1 << i) != 0).collect()).collect();
+    for rows in &subsets {
+        for cols in subsets.iter().filter(|cols| cols.len() == rows.len()) {
+            let sub: Vec<Vec<BaseElement>> = rows.iter().map(|&i| cols.iter().map(|&j| params().mds[i][j]).collect()).collect();
+            assert_ne!(det(&sub), BaseElement::ZERO, "singular submatrix at rows {:?}, cols {:?}", rows, cols);
+        }
+    }
+}
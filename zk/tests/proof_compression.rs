@@ -0,0 +1,55 @@
+pub mod common;
+
+use common::TestChain;
+use zk_chat::zk::{compression::{compress_proof, decompress_proof, ProofCache, ProofHeader, ProofCodec, MAX_PROOF_BYTES}, prover::{MessageProver, ProofProfile, verify_proof}};
+
+#[test]
+fn compressed_proof_round_trips_and_verifies() {
+    let (chain, proof) = TestChain::new("m").proven(2, ProofProfile::Standard);
+    let compressed = compress_proof(&proof).unwrap();
+
+    let header = ProofHeader::parse(&compressed).unwrap();
+    assert_eq!(header.codec, ProofCodec::Zstd);
+    assert_eq!(&header.options, MessageProver::new().options());
+    assert_eq!(header.raw_len as usize, proof.len());
+
+    let raw = decompress_proof(&compressed).unwrap();
+    assert_eq!(raw, proof);
+    verify_proof(&raw, chain.public_inputs(), ProofProfile::Standard).unwrap();
+}
+
+#[test]
+fn corrupted_header_rejected() {
+    let (_, proof) = TestChain::new("m").proven(1, ProofProfile::Standard);
+    let mut compressed = compress_proof(&proof).unwrap();
+    compressed[0] ^= 0xFF;
+    assert!(decompress_proof(&compressed).is_err());
+    assert!(decompress_proof(&compressed[..8]).is_err());
+
+    // A header claiming an oversized proof is refused before decompressing
+    let mut oversized = compress_proof(&proof).unwrap();
+    oversized[12..16].copy_from_slice(&(MAX_PROOF_BYTES as u32 + 1).to_le_bytes());
+    let err = decompress_proof(&oversized).unwrap_err();
+    assert!(err.to_string().contains("limit"), "{}", err);
+}
+
+#[test]
+fn cache_publishes_each_proof_once() {
+    let (chain, proof) = TestChain::new("m").proven(1, ProofProfile::Standard);
+    let mut cache = ProofCache::default();
+    assert!(cache.flush().is_none());
+
+    let mut pub_inputs = chain.public_inputs();
+    cache.update(0, &pub_inputs, &proof).unwrap();
+    assert_eq!(cache.flush().unwrap().message_count, 1);
+    assert!(cache.flush().is_none(), "same proof must not be published twice");
+
+    pub_inputs.message_count = 3;
+    cache.update(0, &pub_inputs, &proof).unwrap();
+    assert_eq!(cache.flush().unwrap().message_count, 3);
+
+    // A segment proof after the first three messages reaches their count plus its own
+    cache.update(3, &pub_inputs, &proof).unwrap();
+    let segment = cache.flush().unwrap();
+    assert_eq!((segment.start_count, segment.message_count), (3, 6));
+}
@@ -0,0 +1,83 @@
+use std::ops::RangeInclusive;
+use zk_chat::{
+    zk::{
+        hasher::default_hash,
+        prover::{verify_proof, MessageProver, ProofProfile},
+        segment::{verify_segments, ChainBoundary, SegmentProof},
+        Digest, MessageChain,
+    },
+    Message, ZkChatError,
+};
+
+/// Chain message k: two senders alternate, each numbering its own messages
+fn message(k: u64) -> Message {
+    Message::new(k.div_ceil(2), 2 - k % 2, format!("segment message {}", k), 1_000 + k)
+}
+
+/// Prove consecutive segments of the chain, each resuming where the previous one ended
+fn prove_segments(ranges: &[RangeInclusive<u64>]) -> Vec<SegmentProof> {
+    let mut prover = MessageProver::with_profile(ProofProfile::Fast);
+    let mut start = ChainBoundary::GENESIS;
+    ranges
+        .iter()
+        .map(|range| {
+            let mut chain = MessageChain::resume(start, default_hash());
+            for k in range.clone() {
+                chain.add_message(message(k)).unwrap();
+            }
+            let proof = prover.prove_segment(&start, &chain.messages).unwrap();
+            start = chain.end();
+            SegmentProof { public_inputs: chain.public_inputs(), proof }
+        })
+        .collect()
+}
+
+#[test]
+fn segments_compose_into_the_full_history() {
+    let segments = prove_segments(&[1..=3, 4..=5, 6..=7]);
+    assert_ne!(segments[1].public_inputs.initial_hash, Digest::ZERO);
+    assert_eq!(segments[1].public_inputs.initial_timestamp, 1_003);
+
+    let mut full = MessageChain::new();
+    for k in 1..=7 {
+        full.add_message(message(k)).unwrap();
+    }
+    let history = verify_segments(&segments, ProofProfile::Fast).unwrap();
+    assert_eq!((history.start, history.end), (ChainBoundary::GENESIS, full.end()));
+    assert_eq!(history.message_count, 7);
+}
+
+#[test]
+fn misaligned_segments_are_rejected() {
+    let segments = prove_segments(&[1..=3, 4..=5, 6..=7]);
+    assert!(verify_segments(&[], ProofProfile::Fast).is_err());
+
+    let reordered = [segments[0].clone(), segments[2].clone(), segments[1].clone()];
+    assert!(matches!(verify_segments(&reordered, ProofProfile::Fast), Err(ZkChatError::ChainInconsistency(_))));
+    let dropped = [segments[0].clone(), segments[2].clone()];
+    assert!(matches!(verify_segments(&dropped, ProofProfile::Fast), Err(ZkChatError::ChainInconsistency(_))));
+
+    // The initial timestamp is asserted in the trace, so it cannot be restated to fit another boundary
+    let mut restated = segments[1].public_inputs.clone();
+    restated.initial_timestamp += 1;
+    assert!(verify_proof(&segments[1].proof, restated, ProofProfile::Fast).is_err());
+}
+
+#[test]
+fn sequences_and_timestamps_continue_across_segments() {
+    let first = prove_segments(&[1..=3]).remove(0);
+    let start = ChainBoundary::end_of(&first.public_inputs);
+
+    // A resumed chain only accepts messages after the boundary
+    let mut resumed = MessageChain::resume(start, default_hash());
+    let stale = Message::new(3, 1, "stale".into(), start.timestamp);
+    assert!(matches!(resumed.add_message(stale.clone()), Err(ZkChatError::InvalidTimestamp)));
+    assert!(matches!(MessageProver::with_profile(ProofProfile::Fast).prove_segment(&start, &[stale]), Err(ZkChatError::InvalidTimestamp)));
+
+    // Sender 1 ended the first segment at ID 2; starting the next one at 4 leaves a gap only the composition sees
+    resumed.add_message(Message::new(4, 1, "skipped".into(), 1_010)).unwrap();
+    let proof = MessageProver::with_profile(ProofProfile::Fast).prove_segment(&start, &resumed.messages).unwrap();
+    let second = SegmentProof { public_inputs: resumed.public_inputs(), proof };
+    let result = verify_segments(&[first, second], ProofProfile::Fast);
+    assert!(matches!(result, Err(ZkChatError::SequenceGap { sender_id: 1, expected: 3, found: 4 })));
+}
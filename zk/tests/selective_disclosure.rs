@@ -0,0 +1,82 @@
+pub mod common;
+
+use common::TestChain;
+use zk_chat::zk::{MessageChain, disclosure::{random_salt, redact_messages, redacted_segment_public_inputs, verify_redacted, verify_redacted_segments, RedactedMessage}, prover::{MessageProver, ProofProfile, verify_proof}, segment::{ChainBoundary, SegmentProof}};
+
+#[test]
+fn redacted_chain_verifies_without_content() {
+    let messages = TestChain::new("private note").senders(2, 3).messages(0..3);
+    let salts: Vec<[u8; 32]> = messages.iter().map(|_| random_salt()).collect();
+    let redacted = redact_messages(&messages, &salts).unwrap();
+    let proof = MessageProver::new().prove_redacted(&redacted).unwrap();
+
+    let pub_inputs = verify_redacted(&proof, &redacted, ProofProfile::Standard).unwrap();
+    assert_eq!(pub_inputs.message_count, 3);
+
+    // The transcript carries metadata and commitments but no content
+    let json = serde_json::to_string(&redacted).unwrap();
+    assert!(!json.contains("private note"));
+    assert!(redacted[1].opens_to(&messages[1].content, &salts[1]));
+    assert!(!redacted[1].opens_to("something else", &salts[1]));
+    assert!(!redacted[1].opens_to(&messages[1].content, &random_salt()));
+}
+
+#[test]
+fn tampered_metadata_or_order_rejected() {
+    let messages = TestChain::new("private note").senders(2, 3).messages(0..3);
+    let salts: Vec<[u8; 32]> = messages.iter().map(|_| random_salt()).collect();
+    let redacted = redact_messages(&messages, &salts).unwrap();
+    let proof = MessageProver::new().prove_redacted(&redacted).unwrap();
+
+    let mut forged_sender = redacted.clone();
+    forged_sender[0] = RedactedMessage { sender_id: 99, ..forged_sender[0].clone() };
+    assert!(verify_redacted(&proof, &forged_sender, ProofProfile::Standard).is_err(), "stale hash must be rejected");
+    forged_sender[0].hash = forged_sender[0].compute_hash();
+    assert!(verify_redacted(&proof, &forged_sender, ProofProfile::Standard).is_err(), "rehashed forgery must not match the proof");
+
+    let dropped = &redacted[..2];
+    assert!(verify_redacted(&proof, dropped, ProofProfile::Standard).is_err());
+}
+
+#[test]
+fn full_and_redacted_proofs_cover_nine_messages() {
+    // Non power-of-two chain longer than the minimum trace
+    let mut chain = MessageChain::new();
+    for message in TestChain::new("private note").senders(2, 3).messages(0..9) {
+        chain.add_message(message).unwrap();
+    }
+    let proof = MessageProver::new().prove(&chain.messages).unwrap();
+    verify_proof(&proof, chain.public_inputs(), ProofProfile::Standard).unwrap();
+
+    let salts: Vec<[u8; 32]> = chain.messages.iter().map(|_| random_salt()).collect();
+    let redacted = redact_messages(&chain.messages, &salts).unwrap();
+    let redacted_proof = MessageProver::new().prove_redacted(&redacted).unwrap();
+    assert_eq!(verify_redacted(&redacted_proof, &redacted, ProofProfile::Standard).unwrap().message_count, 9);
+}
+
+#[test]
+fn redacted_segments_extend_an_earlier_proof() {
+    let messages = TestChain::new("private note").senders(2, 3).messages(0..6);
+    let salts: Vec<[u8; 32]> = messages.iter().map(|_| random_salt()).collect();
+    let redacted = redact_messages(&messages, &salts).unwrap();
+
+    // Prove the first three, then only the three that followed, from where the first segment ended
+    let mut segments = Vec::new();
+    let mut start = ChainBoundary::GENESIS;
+    for part in redacted.chunks(3) {
+        let proof = MessageProver::new().prove_redacted_segment(&start, part).unwrap();
+        let public_inputs = redacted_segment_public_inputs(&start, part).unwrap();
+        start = ChainBoundary::end_of(&public_inputs);
+        segments.push(SegmentProof { public_inputs, proof });
+    }
+    let history = verify_redacted_segments(&segments, &redacted, ProofProfile::Standard).unwrap();
+    assert_eq!(history.message_count, 6);
+    assert_eq!(history.start, ChainBoundary::GENESIS);
+
+    assert!(verify_redacted_segments(&segments, &redacted[..5], ProofProfile::Standard).is_err(), "dropped message must be rejected");
+    assert!(verify_redacted_segments(&segments[1..], &redacted[3..], ProofProfile::Standard).is_err(), "history must start at genesis");
+    let mut forged = redacted.clone();
+    forged[4] = RedactedMessage { timestamp: forged[4].timestamp + 1, ..forged[4].clone() };
+    forged[4].hash = forged[4].compute_hash();
+    assert!(verify_redacted_segments(&segments, &forged, ProofProfile::Standard).is_err());
+}
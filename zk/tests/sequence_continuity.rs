@@ -0,0 +1,63 @@
+pub mod common;
+
+use common::{message_air, violated_steps};
+use winterfell::math::FieldElement;
+use zk_chat::{
+    zk::{
+        air::{build_trace, PublicInputs, BLOCK_LENGTH, SENDER_INVERSE},
+        field::BaseElement,
+        prover::{verify_proof, MessageProver, ProofProfile},
+        MessageChain,
+    },
+    Message, ZkChatError,
+};
+
+#[test]
+fn chain_rejects_skipped_sequence_numbers() {
+    let mut chain = MessageChain::new();
+    // Each sender's first message may start anywhere
+    chain.add_message(Message::new(5, 1, "first".into(), 10)).unwrap();
+    chain.add_message(Message::new(1, 2, "other".into(), 11)).unwrap();
+    let result = chain.add_message(Message::new(7, 1, "skipped".into(), 12));
+    assert!(matches!(result, Err(ZkChatError::SequenceGap { sender_id: 1, expected: 6, found: 7 })));
+    chain.add_message(Message::new(6, 1, "next".into(), 12)).unwrap();
+
+    // The prover refuses gaps and repeats even when the messages never went through a chain
+    let mut prover = MessageProver::with_profile(ProofProfile::Fast);
+    let gapped = [Message::new(1, 4, "a".into(), 1), Message::new(3, 4, "b".into(), 2)];
+    assert!(matches!(prover.prove(&gapped), Err(ZkChatError::SequenceGap { sender_id: 4, expected: 2, found: 3 })));
+    let repeated = [Message::new(2, 4, "a".into(), 1), Message::new(2, 4, "b".into(), 2)];
+    assert!(matches!(prover.prove(&repeated), Err(ZkChatError::DuplicateMessageId)));
+}
+
+#[test]
+fn interleaved_senders_prove_continuous_streams() {
+    let mut chain = MessageChain::new();
+    for (i, (sender_id, id)) in [(3, 10), (1, 1), (3, 11), (3, 12), (1, 2)].into_iter().enumerate() {
+        chain.add_message(Message::new(id, sender_id, format!("stream {}", i), 300 + i as u64)).unwrap();
+    }
+    let trace = build_trace(&chain.messages);
+    assert!(violated_steps(&message_air(&trace, chain.public_inputs()), &trace).is_empty());
+
+    let proof = MessageProver::with_profile(ProofProfile::Fast).prove(&chain.messages).unwrap();
+    verify_proof(&proof, chain.public_inputs(), ProofProfile::Fast).unwrap();
+}
+
+#[test]
+fn gaps_in_the_sorted_columns_violate_the_air() {
+    // Built without the chain's checks: sender 4 skips ID 2
+    let gapped = [Message::new(1, 4, "a".into(), 1), Message::new(3, 4, "b".into(), 2), Message::new(1, 6, "c".into(), 3)];
+    let pub_inputs = MessageChain::new().public_inputs();
+    let trace = build_trace(&gapped);
+    let air = message_air(&trace, PublicInputs { message_count: 3, ..pub_inputs });
+    assert_eq!(violated_steps(&air, &trace), [BLOCK_LENGTH - 1]);
+
+    // Claiming the sender stays across a sender change (inverse zeroed) is caught too
+    let mut chain = MessageChain::new();
+    for message in [Message::new(1, 4, "a".into(), 1), Message::new(2, 4, "b".into(), 2), Message::new(1, 6, "c".into(), 3)] {
+        chain.add_message(message).unwrap();
+    }
+    let mut trace = build_trace(&chain.messages);
+    trace[SENDER_INVERSE][2 * BLOCK_LENGTH - 1] = BaseElement::ZERO;
+    assert_eq!(violated_steps(&message_air(&trace, chain.public_inputs()), &trace), [2 * BLOCK_LENGTH - 1]);
+}
@@ -0,0 +1,51 @@
+pub mod common;
+
+use common::head;
+use zk_chat::{websocket::ProtocolMessage, zk::{consistency::{CheckpointVerifier, ServerSigningKey}, Digest}};
+
+#[test]
+fn checkpoints_verify_against_pinned_key() {
+    let server = ServerSigningKey::generate();
+    let mut verifier = CheckpointVerifier::new(server.public_key());
+
+    verifier.check(&server.sign(2, head(1), Digest::ZERO, 77, Some(2))).unwrap();
+    verifier.check(&server.sign(5, head(2), Digest::ZERO, 77, None)).unwrap();
+    assert_eq!(verifier.latest().unwrap().message_count, 5);
+
+    // Signed by another key, or altered after signing
+    let impostor = ServerSigningKey::generate();
+    assert!(verifier.check(&impostor.sign(6, head(3), Digest::ZERO, 77, Some(6))).is_err());
+    let mut altered = server.sign(6, head(3), Digest::ZERO, 77, Some(6));
+    altered.proof_ref = Some(5);
+    assert!(verifier.check(&altered).is_err());
+    let mut rerooted = server.sign(6, head(3), Digest::ZERO, 77, Some(6));
+    rerooted.message_root = head(4);
+    assert!(verifier.check(&rerooted).is_err());
+}
+
+#[test]
+fn contradicting_checkpoints_rejected() {
+    let server = ServerSigningKey::generate();
+    let mut verifier = CheckpointVerifier::new(server.public_key());
+    verifier.check(&server.sign(4, head(1), Digest::ZERO, 9, Some(4))).unwrap();
+
+    assert!(verifier.check(&server.sign(4, head(2), Digest::ZERO, 9, Some(4))).is_err(), "two heads at one count");
+    assert!(verifier.check(&server.sign(4, head(1), head(5), 9, Some(4))).is_err(), "two roots at one count");
+    assert!(verifier.check(&server.sign(3, head(1), Digest::ZERO, 9, Some(3))).is_err(), "rewound chain");
+    assert!(verifier.check(&server.sign(5, head(1), Digest::ZERO, 10, Some(5))).is_err(), "salt changed");
+    verifier.check(&server.sign(4, head(1), Digest::ZERO, 9, Some(4))).unwrap();
+}
+
+#[test]
+fn signing_key_persists_across_restarts() {
+    let path = std::env::temp_dir().join(format!("zk_chat_signing_{}.key", std::process::id()));
+    let first = ServerSigningKey::load_or_create(&path).unwrap();
+    let second = ServerSigningKey::load_or_create(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(first.public_key(), second.public_key());
+
+    // The broadcast form round-trips with the salt as a decimal string
+    let json = serde_json::to_string(&ProtocolMessage::from(first.sign(1, Digest::ZERO, Digest::ZERO, u64::MAX, None))).unwrap();
+    assert!(json.contains(&format!("\"salt\":\"{}\"", u64::MAX)));
+    assert!(matches!(ProtocolMessage::from_bytes(json.as_bytes()).unwrap(), ProtocolMessage::Checkpoint { salt: u64::MAX, .. }));
+}
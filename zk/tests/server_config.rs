@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use zk_chat::{config::ServerConfig, rate_limit::RateLimiter, zk::prover::ProofProfile, ZkChatError};
+
+fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    move |key| vars.get(key).cloned()
+}
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn layers_override_in_order() {
+    let path = std::env::temp_dir().join(format!("zk_chat_config_{}.toml", std::process::id()));
+    std::fs::write(&path, "bind = \"0.0.0.0:9000\"\nstatic_dir = \"web\"\n[proof]\nprofile = \"fast\"\n[storage]\ndir = \"/var/lib/zk\"\n").unwrap();
+
+    let config = ServerConfig::load(
+        args(&["--config", path.to_str().unwrap(), "--batch-size=4", "--no-test-harness"]),
+        env(&[("ZK_CHAT_STATIC_DIR", "public"), ("ZK_CHAT_BATCH_SIZE", "8"), ("ZK_CHAT_USERS", "/tmp/u.json")]),
+    )
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.bind.to_string(), "0.0.0.0:9000"); // file
+    assert_eq!(config.static_dir.to_str(), Some("public")); // env over file
+    assert_eq!(config.proof.batch_size, 4); // flag over env
+    assert_eq!(config.proof.profile, ProofProfile::Fast);
+    assert!(!config.features.test_harness && config.features.admin_api);
+    assert_eq!(config.signing_key_path().to_str(), Some("/var/lib/zk/server_signing.key"));
+    assert_eq!(config.users_path().to_str(), Some("/tmp/u.json"));
+}
+
+#[test]
+fn defaults_and_positional_address() {
+    let config = ServerConfig::load(args(&["127.0.0.1:9090"]), env(&[])).unwrap();
+    assert_eq!(config.bind.to_string(), "127.0.0.1:9090");
+    assert_eq!(config.broadcast_capacity, 100);
+    assert!(!config.tls_enabled());
+    assert_eq!(config.tls.reload_secs, 30);
+    assert_eq!(config.shutdown_timeout().as_secs(), 30);
+    assert_eq!(config.chain_path().to_str(), Some("./chain.json"));
+    assert_eq!(ServerConfig::from_toml(include_str!("../zk-chat.example.toml")).unwrap(), ServerConfig::default());
+}
+
+#[test]
+fn invalid_settings_rejected() {
+    for bad in [&["--bind", "nowhere"][..], &["--proof-profile", "paranoid"], &["--tls-cert", "c.pem"], &["--colour", "blue"], &["--batch-size"]] {
+        assert!(matches!(ServerConfig::load(args(bad), env(&[])), Err(ZkChatError::Config(_))), "{:?}", bad);
+    }
+    assert!(ServerConfig::from_toml("[proof]\nprofle = \"fast\"").is_err(), "unknown keys are rejected");
+    assert!(ServerConfig::load(args(&["--config", "/nonexistent/zk-chat.toml"]), env(&[])).is_err());
+}
+
+#[test]
+fn rate_limiter_refills_over_time() {
+    let start = std::time::Instant::now();
+    let mut limiter = RateLimiter::new(60, 2);
+    limiter.check_at(1, start).unwrap();
+    limiter.check_at(1, start).unwrap();
+    assert!(matches!(limiter.check_at(1, start), Err(ZkChatError::RateLimited)));
+    limiter.check_at(2, start).unwrap(); // buckets are per user
+    limiter.check_at(1, start + std::time::Duration::from_secs(1)).unwrap();
+
+    // Pruning keeps buckets that are still refilling (user 1) and drops full ones (user 2)
+    limiter.prune_at(start + std::time::Duration::from_secs(1));
+    assert_eq!(limiter.tracked(), 1);
+    assert!(matches!(limiter.check_at(1, start + std::time::Duration::from_secs(1)), Err(ZkChatError::RateLimited)));
+    limiter.prune_at(start + std::time::Duration::from_secs(3));
+    assert_eq!(limiter.tracked(), 0);
+
+    let mut unlimited = RateLimiter::new(0, 1);
+    for _ in 0..100 {
+        unlimited.check(1).unwrap();
+    }
+}
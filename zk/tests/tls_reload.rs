@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use futures_util::StreamExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::rustls::{self, pki_types::ServerName};
+use zk_chat::{tls::TlsReloader, ZkChatError};
+
+// Write a fresh self-signed certificate for localhost, returning its DER form
+fn write_cert(dir: &Path) -> Vec<u8> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    std::fs::write(dir.join("cert.pem"), cert.serialize_pem().unwrap()).unwrap();
+    std::fs::write(dir.join("key.pem"), cert.serialize_private_key_pem()).unwrap();
+    cert.serialize_der().unwrap()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("zk_chat_tls_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn handshake(addr: std::net::SocketAddr, trusted: &[u8]) -> std::io::Result<()> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(trusted.to_vec().into()).unwrap();
+    let config = rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let mut tls = connector.connect(ServerName::try_from("localhost").unwrap(), stream).await?;
+    tls.write_all(b"ping").await?;
+    let mut reply = [0u8; 4];
+    tls.read_exact(&mut reply).await?;
+    assert_eq!(&reply, b"ping");
+    Ok(())
+}
+
+#[tokio::test]
+async fn serves_and_hot_reloads_certificate() {
+    let dir = temp_dir("reload");
+    let first = write_cert(&dir);
+    let tls = TlsReloader::load(dir.join("cert.pem"), dir.join("key.pem")).unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut incoming = Box::pin(tls.incoming(listener));
+    tokio::spawn(async move {
+        while let Some(Ok(mut stream)) = incoming.next().await {
+            let mut buf = [0u8; 4];
+            if stream.read_exact(&mut buf).await.is_ok() {
+                let _ = stream.write_all(&buf).await;
+                let _ = stream.flush().await;
+            }
+        }
+    });
+    handshake(addr, &first).await.unwrap();
+
+    // New certificate on disk: served once reloaded, and the old one is no longer trusted
+    let second = write_cert(&dir);
+    tls.reload().unwrap();
+    handshake(addr, &second).await.unwrap();
+    assert!(handshake(addr, &first).await.is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn watcher_picks_up_changed_files() {
+    let dir = temp_dir("watch");
+    write_cert(&dir);
+    let tls = TlsReloader::load(dir.join("cert.pem"), dir.join("key.pem")).unwrap();
+    let before = Arc::as_ptr(&tls.server_config());
+    let watcher = tls.watch(std::time::Duration::from_millis(20));
+
+    // Make the change visible on filesystems with coarse mtimes
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    write_cert(&dir);
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    watcher.abort();
+
+    assert_ne!(Arc::as_ptr(&tls.server_config()), before);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_files_rejected_and_previous_certificate_kept() {
+    let dir = temp_dir("invalid");
+    write_cert(&dir);
+    let tls = TlsReloader::load(dir.join("cert.pem"), dir.join("key.pem")).unwrap();
+    let before = Arc::as_ptr(&tls.server_config());
+
+    std::fs::write(dir.join("key.pem"), "not a key").unwrap();
+    assert!(matches!(tls.reload(), Err(ZkChatError::Tls(_))));
+    assert_eq!(Arc::as_ptr(&tls.server_config()), before);
+    assert!(matches!(TlsReloader::load(dir.join("missing.pem"), dir.join("key.pem")), Err(ZkChatError::Tls(_))));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
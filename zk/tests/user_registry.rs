@@ -0,0 +1,108 @@
+use zk_chat::{
+    registry::{join_challenge, verify_join, AccountKey, UserRegistry},
+    websocket::{ProtocolMessage, error_codes},
+    ZkChatError,
+};
+
+#[test]
+fn ids_allocated_and_usernames_unique() {
+    let mut registry = UserRegistry::in_memory();
+    let alice = registry.register("alice", None).unwrap();
+    let bob = registry.register(" bob ", Some(AccountKey::generate().public_key())).unwrap();
+    assert_eq!((alice.id, bob.id), (1, 2));
+    assert_eq!(bob.username, "bob");
+
+    assert!(matches!(registry.register("ALICE", None), Err(ZkChatError::UsernameTaken(_))));
+    assert!(matches!(registry.register("no spaces", None), Err(ZkChatError::InvalidUsername(_))));
+    assert!(matches!(registry.register("al", None), Err(ZkChatError::InvalidUsername(_))));
+    assert!(matches!(registry.register("carol", Some("not a key".into())), Err(ZkChatError::InvalidPublicKey)));
+    assert!(matches!(registry.rename(bob.id, "Alice"), Err(ZkChatError::UsernameTaken(_))));
+
+    // Renaming to a different case of one's own name is allowed
+    assert_eq!(registry.rename(alice.id, "Alice").unwrap().username, "Alice");
+    assert_eq!(registry.find_by_username("alice").unwrap().id, alice.id);
+}
+
+#[test]
+fn deactivated_users_keep_their_id_and_name() {
+    let mut registry = UserRegistry::in_memory();
+    let carol = registry.register("carol", None).unwrap();
+    registry.deactivate(carol.id).unwrap();
+
+    assert!(matches!(registry.active_user(carol.id), Err(ZkChatError::UnknownUser(_))));
+    assert!(registry.register("carol", None).is_err());
+    assert_eq!(registry.register("dave", None).unwrap().id, carol.id + 1);
+    assert!(matches!(registry.deactivate(99), Err(ZkChatError::UnknownUser(99))));
+
+    match ProtocolMessage::from(ZkChatError::UnknownUser(99)) {
+        ProtocolMessage::Error { code, .. } => assert_eq!(code, error_codes::UNKNOWN_USER),
+        other => panic!("expected Error, got {:?}", other),
+    }
+}
+
+#[test]
+fn registry_persists_across_restarts() {
+    let path = std::env::temp_dir().join(format!("zk_chat_users_{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let key = AccountKey::generate().public_key();
+    {
+        let mut registry = UserRegistry::open(&path).unwrap();
+        registry.register("erin", None).unwrap();
+        let frank = registry.register("frank", None).unwrap();
+        registry.set_public_key(frank.id, Some(key.clone())).unwrap();
+        registry.deactivate(1).unwrap();
+    }
+
+    let mut reopened = UserRegistry::open(&path).unwrap();
+    assert_eq!(reopened.len(), 2);
+    assert!(!reopened.get(1).unwrap().active);
+    assert_eq!(reopened.get(2).unwrap().public_key.as_deref(), Some(key.as_str()));
+    assert_eq!(reopened.register("grace", None).unwrap().id, 3);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn joining_requires_a_signature_from_the_registered_key() {
+    let mut registry = UserRegistry::in_memory();
+    let key = AccountKey::generate();
+    let heidi = registry.register("heidi", Some(key.public_key())).unwrap();
+    let nonce = join_challenge();
+    assert_ne!(nonce, join_challenge());
+    verify_join(&heidi, &nonce, &key.sign_join(heidi.id, &nonce)).unwrap();
+
+    // Another key, another user id or a replayed nonce does not authenticate
+    let intruder = AccountKey::generate();
+    let failed = |result| matches!(result, Err(ZkChatError::AuthenticationFailed(id)) if id == heidi.id);
+    assert!(failed(verify_join(&heidi, &nonce, &intruder.sign_join(heidi.id, &nonce))));
+    assert!(failed(verify_join(&heidi, &nonce, &key.sign_join(heidi.id + 1, &nonce))));
+    assert!(failed(verify_join(&heidi, &join_challenge(), &key.sign_join(heidi.id, &nonce))));
+
+    // Users registered without a key cannot join until one is set
+    let ivan = registry.register("ivan", None).unwrap();
+    assert!(matches!(verify_join(&ivan, &nonce, &key.sign_join(ivan.id, &nonce)), Err(ZkChatError::AuthenticationFailed(_))));
+    let ivan = registry.set_public_key(ivan.id, Some(key.public_key())).unwrap();
+    verify_join(&ivan, &nonce, &key.sign_join(ivan.id, &nonce)).unwrap();
+}
+
+#[test]
+fn failed_writes_leave_the_registry_unchanged() {
+    let dir = std::env::temp_dir().join(format!("zk_chat_users_readonly_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("users.json");
+    let mut registry = UserRegistry::open(&path).unwrap();
+    let judy = registry.register("judy", None).unwrap();
+
+    // A directory where the temporary file goes makes every save fail
+    std::fs::create_dir(path.with_extension("tmp")).unwrap();
+    assert!(registry.register("mike", None).is_err());
+    assert!(registry.rename(judy.id, "judith").is_err());
+    assert!(registry.set_banned(judy.id, true).is_err());
+    assert_eq!(registry.len(), 1);
+    assert!(registry.find_by_username("mike").is_none());
+    assert_eq!(registry.active_user(judy.id).unwrap(), &judy);
+
+    std::fs::remove_dir(path.with_extension("tmp")).unwrap();
+    assert_eq!(registry.register("mike", None).unwrap().id, judy.id + 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}